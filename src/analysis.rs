@@ -0,0 +1,388 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use chumsky::error::Simple;
+use ropey::Rope;
+use tower_lsp::lsp_types::Diagnostic;
+
+use crate::{
+    bazel_flags::{load_bazel_flags_for_release, BazelFlags},
+    bazel_version::BazelVersion,
+    config_index::collect_flag_aliases,
+    diagnostic::{
+        apply_severity_overrides, apply_strict_mode, apply_suppressions,
+        diagnostics_for_abbreviations, diagnostics_for_banned_flags, diagnostics_for_bazel_version,
+        diagnostics_for_changed_defaults, diagnostics_for_common_flags,
+        diagnostics_for_config_cycles, diagnostics_for_conflicting_startup_flags,
+        diagnostics_for_cross_file_duplicates, diagnostics_for_duplicate_imports,
+        diagnostics_for_empty_configs, diagnostics_for_experimental_flags,
+        diagnostics_for_import_cycles, diagnostics_for_overridden_flags,
+        diagnostics_for_redundant_defaults, diagnostics_for_secrets,
+        diagnostics_for_shadowed_flags, diagnostics_for_try_imported_configs,
+        diagnostics_for_undefined_configs, diagnostics_for_unportable_configs,
+        diagnostics_for_unused_configs, diagnostics_from_parser, diagnostics_from_rcconfig,
+        DiagnosticKind,
+    },
+    effective_config::EffectiveConfig,
+    file_utils::{get_default_rc_files, get_workspace_path, normalize_path},
+    formatting::pretty_print,
+    import_graph::{FileCache, ParsedFile},
+    parser::Line,
+    project::Project,
+    semantic_model::RcFile,
+    settings::{BannedFlag, DiagnosticLevel},
+};
+
+/// Options for [`analyze`], corresponding to the options of `bazelrc-lsp lint`.
+#[derive(Clone, Debug, Default)]
+pub struct AnalyzeOptions {
+    /// The path of the analyzed file. Required for all diagnostics involving other
+    /// files, e.g. missing imports or configs defined in imported files.
+    pub file_path: Option<PathBuf>,
+    /// Report warnings indicating that Bazel might fail as errors.
+    pub strict: bool,
+    /// Report flag values which look like secrets.
+    pub detect_secrets: bool,
+    /// Report abbreviated flags like `-k`.
+    pub report_abbreviations: bool,
+    /// Report experimental flags.
+    pub report_experimental_flags: bool,
+    /// Report flags which are set to their default value.
+    pub report_redundant_defaults: bool,
+    /// Diagnostics which are not reported.
+    pub disabled: Vec<DiagnosticKind>,
+    /// The severities of diagnostic kinds, keyed by their name. Applied after `strict`.
+    pub severity_overrides: BTreeMap<String, DiagnosticLevel>,
+    /// The Bazel version used with the file. Flags which don't exist in this version
+    /// are reported.
+    pub bazel_version: Option<BazelVersion>,
+    /// Flags which must not be used.
+    pub banned_flags: Vec<BannedFlag>,
+    /// The Bazel version to upgrade to. Flags whose default value changes are reported.
+    pub upgrade_target_version: Option<BazelVersion>,
+}
+
+/// Parses a bazelrc file, combining flags and their values given as separate tokens
+/// (e.g. `--jobs 8`).
+pub fn parse(src: &str, bazel_flags: &BazelFlags) -> ParsedFile {
+    ParsedFile::from_str(src, bazel_flags)
+}
+
+/// Computes the diagnostics for a bazelrc file, as reported by the language server
+/// and by `bazelrc-lsp lint`. Imported files are read from disk.
+pub fn analyze(src: &str, bazel_flags: &BazelFlags, options: &AnalyzeOptions) -> Vec<Diagnostic> {
+    let file = parse(src, bazel_flags);
+    let flag_aliases = collect_flag_aliases(&file.lines, bazel_flags);
+    analyze_parsed(
+        &file,
+        bazel_flags,
+        &FileCache::default(),
+        &flag_aliases,
+        options,
+    )
+}
+
+/// Like [`analyze`], but for an already parsed file. Imported files are taken from
+/// `file_cache` and flag aliases defined by other files can be passed in `flag_aliases`.
+pub fn analyze_parsed(
+    file: &ParsedFile,
+    bazel_flags: &BazelFlags,
+    file_cache: &FileCache,
+    flag_aliases: &BTreeSet<String>,
+    options: &AnalyzeOptions,
+) -> Vec<Diagnostic> {
+    analyze_lines(
+        &file.rope,
+        &file.lines,
+        &file.parser_errors,
+        bazel_flags,
+        file_cache,
+        flag_aliases,
+        options,
+    )
+}
+
+/// Like [`analyze_parsed`], but for the parts of a parsed file. This is the pipeline shared
+/// by the language server and `bazelrc-lsp lint`.
+pub fn analyze_lines(
+    rope: &Rope,
+    lines: &[Line],
+    parser_errors: &[Simple<char>],
+    bazel_flags: &BazelFlags,
+    file_cache: &FileCache,
+    flag_aliases: &BTreeSet<String>,
+    options: &AnalyzeOptions,
+) -> Vec<Diagnostic> {
+    // Relative paths are resolved against the working directory, as the workspace and
+    // imported files can't be found otherwise
+    let absolute_path = options
+        .file_path
+        .as_ref()
+        .and_then(|p| Some(normalize_path(&std::env::current_dir().ok()?.join(p))));
+    let model = RcFile::new(lines, bazel_flags);
+    let mut diagnostics = Vec::<Diagnostic>::new();
+    diagnostics.extend(diagnostics_from_parser(rope, parser_errors));
+    diagnostics.extend(diagnostics_from_rcconfig(
+        rope,
+        &model,
+        bazel_flags,
+        absolute_path.as_deref(),
+        flag_aliases,
+    ));
+    if let Some(file_path) = &absolute_path {
+        diagnostics.extend(diagnostics_for_import_cycles(
+            rope,
+            file_path,
+            file_cache,
+            bazel_flags,
+        ));
+        let rc_files = Project::for_file(file_path, file_cache, bazel_flags).rc_files;
+        let effective_config = EffectiveConfig::new(&rc_files, file_cache, bazel_flags);
+        diagnostics.extend(diagnostics_for_overridden_flags(
+            &effective_config,
+            file_path,
+        ));
+        diagnostics.extend(diagnostics_for_shadowed_flags(&effective_config, file_path));
+        diagnostics.extend(diagnostics_for_conflicting_startup_flags(
+            &effective_config,
+            file_path,
+        ));
+        diagnostics.extend(diagnostics_for_cross_file_duplicates(
+            &effective_config,
+            file_path,
+        ));
+        if let Some(workspace) = get_workspace_path(file_path) {
+            diagnostics.extend(diagnostics_for_unportable_configs(
+                rope,
+                lines,
+                file_path,
+                &[workspace.join(".bazelrc")],
+                &get_default_rc_files(Some(&workspace)),
+                file_cache,
+                bazel_flags,
+            ));
+        }
+        diagnostics.extend(diagnostics_for_duplicate_imports(
+            rope,
+            file_path,
+            &rc_files,
+            file_cache,
+            bazel_flags,
+        ));
+        diagnostics.extend(diagnostics_for_try_imported_configs(
+            rope,
+            lines,
+            file_path,
+            &rc_files,
+            file_cache,
+            bazel_flags,
+        ));
+        diagnostics.extend(diagnostics_for_undefined_configs(
+            rope,
+            lines,
+            &rc_files,
+            file_cache,
+            bazel_flags,
+        ));
+        diagnostics.extend(diagnostics_for_config_cycles(
+            rope,
+            lines,
+            file_path,
+            &rc_files,
+            file_cache,
+            bazel_flags,
+        ));
+        diagnostics.extend(diagnostics_for_unused_configs(
+            rope,
+            lines,
+            file_path,
+            &rc_files,
+            file_cache,
+            bazel_flags,
+        ));
+        diagnostics.extend(diagnostics_for_empty_configs(
+            rope,
+            lines,
+            file_path,
+            &rc_files,
+            file_cache,
+            bazel_flags,
+        ));
+    }
+    if let Some(version) = &options.bazel_version {
+        diagnostics.extend(diagnostics_for_bazel_version(
+            rope,
+            &model,
+            bazel_flags,
+            version,
+        ));
+    }
+    diagnostics.extend(diagnostics_for_common_flags(
+        rope,
+        &model,
+        bazel_flags,
+        options.bazel_version.as_ref(),
+    ));
+    if options.detect_secrets {
+        diagnostics.extend(diagnostics_for_secrets(rope, &model, bazel_flags));
+    }
+    diagnostics.extend(diagnostics_for_banned_flags(
+        rope,
+        &model,
+        bazel_flags,
+        &options.banned_flags,
+        absolute_path.as_deref(),
+    ));
+    if options.report_experimental_flags {
+        diagnostics.extend(diagnostics_for_experimental_flags(
+            rope,
+            &model,
+            bazel_flags,
+        ));
+    }
+    if options.report_redundant_defaults {
+        diagnostics.extend(diagnostics_for_redundant_defaults(
+            rope,
+            &model,
+            bazel_flags,
+        ));
+    }
+    if options.report_abbreviations {
+        diagnostics.extend(diagnostics_for_abbreviations(rope, lines, bazel_flags));
+    }
+    if let Some(target_version) = &options.upgrade_target_version {
+        // Without a known Bazel version, the bundled flags are compared to the target
+        let current = options
+            .bazel_version
+            .as_ref()
+            .and_then(|v| load_bazel_flags_for_release(&v.version));
+        if let Some(target) = load_bazel_flags_for_release(&target_version.version) {
+            diagnostics.extend(diagnostics_for_changed_defaults(
+                rope,
+                lines,
+                current.as_deref().unwrap_or(bazel_flags),
+                &target,
+                target_version,
+            ));
+        }
+    }
+    postprocess_diagnostics(rope, lines, &mut diagnostics, options);
+    diagnostics
+}
+
+/// Applies strict mode, suppression comments, disabled diagnostics and severity overrides,
+/// in this order. Also needed for diagnostics computed elsewhere, e.g. by Bazel itself.
+pub fn postprocess_diagnostics(
+    rope: &Rope,
+    lines: &[Line],
+    diagnostics: &mut Vec<Diagnostic>,
+    options: &AnalyzeOptions,
+) {
+    if options.strict {
+        apply_strict_mode(diagnostics);
+    }
+    apply_suppressions(rope, lines, diagnostics);
+    diagnostics.retain(|d| !DiagnosticKind::of(d).is_some_and(|k| options.disabled.contains(&k)));
+    apply_severity_overrides(diagnostics, &options.severity_overrides);
+}
+
+/// Formats a bazelrc file. Returns `None` if the file contains syntax errors.
+pub fn format(src: &str) -> Option<String> {
+    pretty_print(src)
+}
+
+#[test]
+fn test_analyze() {
+    use crate::bazel_flags::load_bazel_flags;
+
+    let bazel_flags = load_bazel_flags();
+    let src = "build --keep_going --unknown_flag\nbuidl --jobs=8\n";
+    let diagnostics = analyze(src, &bazel_flags, &AnalyzeOptions::default());
+    let kinds = diagnostics
+        .iter()
+        .map(|d| DiagnosticKind::of(d).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![DiagnosticKind::UnknownFlag, DiagnosticKind::UnknownCommand]
+    );
+
+    let options = AnalyzeOptions {
+        disabled: vec![DiagnosticKind::UnknownFlag],
+        ..Default::default()
+    };
+    assert_eq!(analyze(src, &bazel_flags, &options).len(), 1);
+
+    assert_eq!(parse(src, &bazel_flags).lines.len(), 2);
+    assert_eq!(
+        format("build   --jobs  8").as_deref(),
+        Some("build --jobs=8\n")
+    );
+    assert_eq!(format("build '--x"), None);
+}
+
+#[test]
+fn test_analyze_relative_path() {
+    use crate::{bazel_flags::load_bazel_flags, import_graph::create_test_workspace};
+
+    let bazel_flags = load_bazel_flags();
+    let src = "try-import %workspace%/user.bazelrc\n";
+    let ws = create_test_workspace("analyze-relative", &[(".bazelrc", src)]);
+    // The same file, relative to the working directory
+    let cwd = std::env::current_dir().unwrap();
+    let relative_path = cwd
+        .components()
+        .skip(1)
+        .map(|_| "..")
+        .collect::<PathBuf>()
+        .join(ws.strip_prefix("/").unwrap())
+        .join(".bazelrc");
+    assert!(relative_path.is_relative());
+    let options = AnalyzeOptions {
+        file_path: Some(relative_path),
+        ..Default::default()
+    };
+    let messages = analyze(src, &bazel_flags, &options)
+        .into_iter()
+        .map(|d| d.message)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![format!(
+            "Imported file {} does not exist",
+            ws.join("user.bazelrc").display()
+        )]
+    );
+}
+
+#[test]
+fn test_postprocess_diagnostics() {
+    use crate::bazel_flags::load_bazel_flags;
+    use tower_lsp::lsp_types::DiagnosticSeverity;
+
+    let bazel_flags = load_bazel_flags();
+    let src = "build --unknown_flag --keep_going=maybe\n";
+    let severities = |options: &AnalyzeOptions| {
+        analyze(src, &bazel_flags, options)
+            .iter()
+            .map(|d| (DiagnosticKind::of(d).unwrap(), d.severity))
+            .collect::<Vec<_>>()
+    };
+    let unknown_flag_severity = severities(&AnalyzeOptions::default())[0].1;
+    // Severity overrides take precedence over strict mode
+    let options = AnalyzeOptions {
+        strict: true,
+        severity_overrides: BTreeMap::from([
+            ("unknown-flag".to_string(), DiagnosticLevel::Hint),
+            ("invalid-value".to_string(), DiagnosticLevel::Off),
+        ]),
+        ..Default::default()
+    };
+    assert_ne!(unknown_flag_severity, Some(DiagnosticSeverity::HINT));
+    assert_eq!(
+        severities(&options),
+        vec![(DiagnosticKind::UnknownFlag, Some(DiagnosticSeverity::HINT))]
+    );
+}
@@ -0,0 +1,131 @@
+use std::{path::Path, process::Command};
+
+use ropey::Rope;
+use tower_lsp::lsp_types::TextEdit;
+
+use crate::{formatting::format_token, lsp_utils::range_to_lsp, parser::Line};
+
+// The name of the Bazel binary. Usually, this is Bazelisk installed as `bazel`.
+const BAZEL_BINARY: &str = "bazel";
+
+// The flags of a line as they would be passed on the command line
+pub fn get_line_args(line: &Line) -> Vec<String> {
+    line.flags
+        .iter()
+        .filter_map(|flag| match (&flag.name, &flag.value) {
+            (Some(name), Some(value)) => Some(format!("{}={}", name.0, value.0)),
+            (Some(name), None) => Some(name.0.clone()),
+            (None, Some(value)) => Some(value.0.clone()),
+            (None, None) => None,
+        })
+        .collect()
+}
+
+// Parses the output of `bazel canonicalize-flags`, which prints one flag per line
+pub fn parse_canonicalize_output(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect()
+}
+
+// Extracts the error messages from Bazel's stderr output.
+// Only lines starting with `ERROR:` are relevant; the rest is progress output.
+pub fn parse_bazel_errors(stderr: &str) -> Vec<String> {
+    stderr
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("ERROR:"))
+        .map(|l| l.trim().to_string())
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum BazelError {
+    // Bazel could not be started, e.g. because it is not installed
+    Spawn(std::io::Error),
+    // Bazel failed, with the given error messages
+    Failed(String),
+}
+
+impl std::fmt::Display for BazelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BazelError::Spawn(e) => write!(f, "Failed to run `{}`: {}", BAZEL_BINARY, e),
+            BazelError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// Runs `bazel canonicalize-flags --for_command=<command> -- <flags>` within the workspace
+// and returns the canonical flags. On failure, the error messages printed by Bazel are returned.
+pub fn canonicalize_flags(
+    workspace: &Path,
+    command: &str,
+    flags: &[String],
+) -> Result<Vec<String>, BazelError> {
+    let output = Command::new(BAZEL_BINARY)
+        .current_dir(workspace)
+        .arg("canonicalize-flags")
+        .arg(format!("--for_command={}", command))
+        .arg("--")
+        .args(flags)
+        .output()
+        .map_err(BazelError::Spawn)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let errors = parse_bazel_errors(&stderr);
+        return Err(BazelError::Failed(if errors.is_empty() {
+            format!("`{} canonicalize-flags` failed", BAZEL_BINARY)
+        } else {
+            errors.join("\n")
+        }));
+    }
+    Ok(parse_canonicalize_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+// An edit replacing the flags of the line by the given canonical flags.
+// The command, config name and comment of the line are kept.
+pub fn get_canonical_flags_edit(
+    rope: &Rope,
+    line: &Line,
+    canonical: &[String],
+) -> Option<TextEdit> {
+    let first = line.flags.first()?;
+    let last = line.flags.last()?;
+    let start = first.name.as_ref().or(first.value.as_ref())?.1.start;
+    let end = last.value.as_ref().or(last.name.as_ref())?.1.end;
+    let new_text = canonical
+        .iter()
+        .map(|f| format_token(f))
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(TextEdit {
+        range: range_to_lsp(rope, &(start..end))?,
+        new_text,
+    })
+}
+
+#[test]
+fn test_canonicalize_helpers() {
+    use crate::parser::parse_from_str;
+
+    let src = "build:ci -k --copt \"-O2 -g\" # comment";
+    let lines = parse_from_str(src).lines;
+    assert_eq!(get_line_args(&lines[0]), vec!["-k", "--copt", "-O2 -g"]);
+
+    let canonical = parse_canonicalize_output("--keep_going\n--copt=-O2 -g\n\n");
+    assert_eq!(canonical, vec!["--keep_going", "--copt=-O2 -g"]);
+    let edit = get_canonical_flags_edit(&Rope::from_str(src), &lines[0], &canonical).unwrap();
+    assert_eq!(edit.new_text, "--keep_going \"--copt=-O2 -g\"");
+    assert_eq!(edit.range.start.character, 9);
+    assert_eq!(edit.range.end.character, 27);
+
+    assert_eq!(
+        parse_bazel_errors("Loading:\nERROR: Unrecognized option: --foo\n"),
+        vec!["Unrecognized option: --foo"]
+    );
+}
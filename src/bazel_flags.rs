@@ -1,8 +1,27 @@
+//! The flag metadata, loaded from the flag dumps in `proto/flag-dumps`.
+//!
+//! Bazel's flag dumps (`bazel help flags-as-proto`) lack some metadata, e.g. the allowed
+//! values of enum flags or the release which added a flag. `BazelFlags::from_flags` fills
+//! the proto fields numbered from 100 from the hand-maintained tables below. The tables
+//! describe the flags of the newest bundled dump, compiled from Bazel's documentation and
+//! release notes. After adding a dump (see `extract_bazel_flags.py`), re-check the tables
+//! against the new release: drop entries the dump now provides and record added, removed
+//! and changed flags.
+
 use phf::phf_map;
 use prost::Message;
-use std::{collections::HashMap, io::Cursor};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::{Arc, Mutex, OnceLock},
+};
 
-use crate::bazel_flags_proto::{FlagCollection, FlagInfo};
+use crate::{
+    bazel_flags_proto::{FlagCollection, FlagInfo},
+    bazel_version::{parse_release, FLAG_DUMP_VERSIONS},
+    effective_config::get_parent_command,
+    flag_docs::{render_flag_documentation, DocFormat},
+};
 
 pub static COMMAND_DOCS: phf::Map<&'static str, &'static str> = phf_map! {
     // The command line docs, taken from the `bazel help`
@@ -46,8 +65,177 @@ pub struct BazelFlags {
     pub flags_by_abbreviation: HashMap<String, usize>,
 }
 
+// The values accepted by flags taking one of a fixed set of values
+static ALLOWED_VALUES: phf::Map<&'static str, &'static [&'static str]> = phf_map! {
+    "android_manifest_merger" => &["legacy", "android", "force_android"],
+    "bes_upload_mode" => &["wait_for_upload_complete", "nowait_for_upload_complete", "fully_async"],
+    "build_python_zip" => &["auto", "yes", "no", "true", "false", "1", "0"],
+    "cache_test_results" => &["auto", "yes", "no", "true", "false", "1", "0"],
+    "check_direct_dependencies" => &["off", "warning", "error"],
+    "color" => &["yes", "no", "auto"],
+    "compilation_mode" => &["fastbuild", "dbg", "opt"],
+    "curses" => &["yes", "no", "auto"],
+    "dynamic_mode" => &["off", "default", "fully"],
+    "experimental_convenience_symlinks" => &["normal", "clean", "ignore", "log_only"],
+    "host_compilation_mode" => &["fastbuild", "dbg", "opt"],
+    "lockfile_mode" => &["update", "error", "off"],
+    "order_output" => &["no", "deps", "auto", "full"],
+    "remote_build_event_upload" => &["all", "minimal"],
+    "remote_download_outputs" => &["all", "minimal", "toplevel"],
+    "strip" => &["always", "sometimes", "never"],
+    "subcommands" => &["true", "false", "pretty_print"],
+    "test_output" => &["summary", "errors", "all", "streamed"],
+    "test_summary" => &["short", "terse", "detailed", "none", "testcase"],
+};
+
+// The types of values accepted by flags, see `FlagInfo::value_type` for the possible types
+static VALUE_TYPES: phf::Map<&'static str, &'static str> = phf_map! {
+    "bes_outerr_buffer_size" => "integer",
+    "bes_outerr_chunk_size" => "integer",
+    "bes_timeout" => "duration",
+    "bazelrc" => "path",
+    "disk_cache" => "path",
+    "distdir" => "path",
+    "install_base" => "path",
+    "output_base" => "path",
+    "output_user_root" => "path",
+    "override_module" => "repository_override",
+    "override_repository" => "repository_override",
+    "repository_cache" => "path",
+    "bep_maximum_open_remote_upload_files" => "integer",
+    "experimental_remote_cache_eviction_retries" => "integer",
+    "experimental_remote_cache_ttl" => "duration",
+    "experimental_ui_max_stdouterr_bytes" => "integer",
+    "grpc_keepalive_time" => "duration",
+    "grpc_keepalive_timeout" => "duration",
+    "jobs" => "resource",
+    "loading_phase_threads" => "resource",
+    "local_cpu_resources" => "resource",
+    "local_ram_resources" => "resource",
+    "local_startup_timeout_secs" => "integer",
+    "local_test_jobs" => "resource",
+    "max_config_changes_to_show" => "integer",
+    "max_idle_secs" => "integer",
+    "remote_max_connections" => "integer",
+    "remote_retries" => "integer",
+    "remote_retry_max_delay" => "duration",
+    "remote_timeout" => "duration",
+    "show_result" => "integer",
+    "test_timeout" => "integer_list",
+};
+
+// The default values of commonly set flags
+static DEFAULT_VALUES: phf::Map<&'static str, &'static str> = phf_map! {
+    "announce_rc" => "false",
+    "build_python_zip" => "auto",
+    "build_runfile_links" => "true",
+    "build_tests_only" => "false",
+    "cache_test_results" => "auto",
+    "check_direct_dependencies" => "warning",
+    "color" => "auto",
+    "compilation_mode" => "fastbuild",
+    "curses" => "auto",
+    "dynamic_mode" => "default",
+    "enable_bzlmod" => "true",
+    "enable_workspace" => "true",
+    "host_compilation_mode" => "opt",
+    "incompatible_disallow_empty_glob" => "false",
+    "incompatible_sandbox_hermetic_tmp" => "true",
+    "incompatible_strict_action_env" => "false",
+    "keep_going" => "false",
+    "legacy_external_runfiles" => "true",
+    "lockfile_mode" => "update",
+    "remote_download_outputs" => "toplevel",
+    "remote_max_connections" => "100",
+    "remote_retries" => "5",
+    "remote_timeout" => "60s",
+    "show_result" => "1",
+    "show_timestamps" => "false",
+    "strip" => "sometimes",
+    "test_output" => "summary",
+    "test_summary" => "short",
+    "verbose_failures" => "false",
+    "watchfs" => "false",
+};
+
+// Default values which changed between releases, as `(release, old, new)` sorted by release
+static DEFAULT_CHANGES: phf::Map<
+    &'static str,
+    &'static [(&'static str, &'static str, &'static str)],
+> = phf_map! {
+    "enable_bzlmod" => &[("7.0.0", "false", "true")],
+    "enable_workspace" => &[("8.0.0", "true", "false")],
+    "incompatible_disallow_empty_glob" => &[("8.0.0", "false", "true")],
+    "incompatible_sandbox_hermetic_tmp" => &[("7.0.0", "false", "true")],
+    "legacy_external_runfiles" => &[("8.0.0", "true", "false")],
+    "remote_download_outputs" => &[("7.0.0", "all", "toplevel")],
+};
+
+// The default value of a flag in the given release, if it differs between releases
+fn default_value_in_release(name: &str, release: (u32, u32, u32)) -> Option<&'static str> {
+    let changes = DEFAULT_CHANGES.get(name)?;
+    match changes
+        .iter()
+        .find(|(r, _, _)| parse_release(r).is_some_and(|r| r > release))
+    {
+        Some((_, old, _)) => Some(old),
+        None => changes.last().map(|(_, _, new)| *new),
+    }
+}
+
+// The Bazel releases in which flags were added and removed, as `(added, removed)`
+static FLAG_VERSIONS: phf::Map<&'static str, (Option<&'static str>, Option<&'static str>)> = phf_map! {
+    "enable_bzlmod" => (Some("6.0.0"), None),
+    "enable_workspace" => (Some("7.1.0"), None),
+    "host_javabase" => (None, Some("8.0.0")),
+    "javabase" => (None, Some("8.0.0")),
+};
+
+// Flags removed before the newest flag dump, as `(release which removed them, is boolean)`
+static REMOVED_FLAGS: phf::Map<&'static str, (&'static str, bool)> = phf_map! {
+    "experimental_enable_bzlmod" => ("6.0.0", true),
+};
+
+// The Bazel release which removed a flag missing from the flag dumps, if known
+pub fn removed_flag_version(name: &str) -> Option<&'static str> {
+    REMOVED_FLAGS.get(name).map(|(removed, _)| *removed)
+}
+
+// A flag, as used on the command line or in an rc file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Invocation<'f, 's> {
+    pub flag: &'f FlagInfo,
+    // Whether the negated `--no` form of a boolean flag was used
+    pub negated: bool,
+    // The value given after `=`, if any
+    pub value: Option<&'s str>,
+}
+
 impl BazelFlags {
-    pub fn from_flags(flags: Vec<FlagInfo>) -> BazelFlags {
+    pub fn from_flags(mut flags: Vec<FlagInfo>) -> BazelFlags {
+        // Complete the metadata missing from Bazel's flag dumps
+        for f in &mut flags {
+            if f.allowed_values.is_empty() {
+                if let Some(values) = ALLOWED_VALUES.get(f.name.as_str()) {
+                    f.allowed_values = values.iter().map(|v| v.to_string()).collect();
+                }
+            }
+            if f.value_type.is_none() {
+                f.value_type = VALUE_TYPES.get(f.name.as_str()).map(|t| t.to_string());
+            }
+            if f.default_value.is_none() {
+                f.default_value = DEFAULT_VALUES.get(f.name.as_str()).map(|v| v.to_string());
+            }
+            if let Some((added, removed)) = FLAG_VERSIONS.get(f.name.as_str()) {
+                if f.added_in_version.is_none() {
+                    f.added_in_version = added.map(|v| v.to_string());
+                }
+                if f.removed_in_version.is_none() {
+                    f.removed_in_version = removed.map(|v| v.to_string());
+                }
+            }
+        }
+
         // Index the flags from the protobuf description
         let mut flags_by_commands = HashMap::<String, Vec<usize>>::new();
         let mut flags_by_name = HashMap::<String, usize>::new();
@@ -93,70 +281,157 @@ impl BazelFlags {
     }
 
     pub fn get_by_invocation(&self, s: &str) -> Option<&FlagInfo> {
-        let stripped = s.strip_suffix('=').unwrap_or(s);
+        self.resolve_invocation(s).map(|invocation| invocation.flag)
+    }
+
+    // Looks up the flag used by an invocation like `--keep_going`, `--nokeep_going`, `-k`
+    // or `--jobs=16`
+    pub fn resolve_invocation<'s>(&self, s: &'s str) -> Option<Invocation<'_, 's>> {
+        let (name, value) = match s.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+        let invocation = |flag, negated| Invocation {
+            flag,
+            negated,
+            value,
+        };
         // Long names
-        if let Some(long_name) = stripped.strip_prefix("--") {
+        if let Some(long_name) = name.strip_prefix("--") {
             if long_name.starts_with('-') {
                 return None;
             }
-            // Strip the `no` prefix, if any
-            let stripped_no = long_name.strip_prefix("no").unwrap_or(long_name);
-            return self
-                .flags_by_name
-                .get(stripped_no)
-                .map(|i| self.flags.get(*i).unwrap());
+            if let Some(i) = self.flags_by_name.get(long_name) {
+                return Some(invocation(&self.flags[*i], false));
+            }
+            // Strip the `no` prefix of boolean flags
+            return long_name
+                .strip_prefix("no")
+                .and_then(|name| self.flags_by_name.get(name))
+                .map(|i| &self.flags[*i])
+                .filter(|f| f.has_negative_flag())
+                .map(|f| invocation(f, true));
         }
         // Short names
-        if let Some(abbreviation) = stripped.strip_prefix('-') {
+        if let Some(abbreviation) = name.strip_prefix('-') {
             if abbreviation.starts_with('-') {
                 return None;
             }
             return self
                 .flags_by_abbreviation
                 .get(abbreviation)
-                .map(|i| self.flags.get(*i).unwrap());
+                .map(|i| invocation(&self.flags[*i], false));
         }
         None
     }
+
+    // The host variant of a target flag (e.g. `--host_copt` for `--copt`) and vice versa
+    pub fn get_host_counterpart(&self, flag: &FlagInfo) -> Option<&FlagInfo> {
+        let counterpart = match flag.name.strip_prefix("host_") {
+            Some(target_name) => target_name.to_string(),
+            None => format!("host_{}", flag.name),
+        };
+        self.flags_by_name
+            .get(&counterpart)
+            .map(|i| self.flags.get(*i).unwrap())
+    }
+
+    // The documentation for the flag, including a cross-reference to its
+    // host or target counterpart, if any
+    pub fn render_documentation(&self, flag: &FlagInfo, format: DocFormat) -> String {
+        render_flag_documentation(flag, self.get_host_counterpart(flag), format)
+    }
+
+    pub fn get_documentation_markdown(&self, flag: &FlagInfo) -> String {
+        self.render_documentation(flag, DocFormat::Markdown)
+    }
+
+    // The flags of another Bazel release, derived from these flags using the version
+    // metadata: Flags added after the release or removed up to the release are dropped.
+    // Flags removed before the flag dump are added back for older releases. Default values
+    // which changed in between are adjusted.
+    pub fn for_release(&self, release: (u32, u32, u32)) -> BazelFlags {
+        let is_after = |v: Option<&str>| v.and_then(parse_release).is_some_and(|v| v > release);
+        let mut flags = self
+            .flags
+            .iter()
+            .filter(|f| {
+                !is_after(f.added_in_version.as_deref())
+                    && (f.removed_in_version.is_none() || is_after(f.removed_in_version.as_deref()))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let commands = crate::semantic_model::expand_command("common", self)
+            .into_iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>();
+        for (name, (removed, is_boolean)) in REMOVED_FLAGS.entries() {
+            if is_after(Some(removed)) {
+                flags.push(FlagInfo {
+                    name: name.to_string(),
+                    has_negative_flag: Some(*is_boolean),
+                    commands: commands.clone(),
+                    removed_in_version: Some(removed.to_string()),
+                    ..Default::default()
+                });
+            }
+        }
+        for f in &mut flags {
+            if let Some(default) = default_value_in_release(&f.name, release) {
+                f.default_value = Some(default.to_string());
+            }
+        }
+        BazelFlags::from_flags(flags)
+    }
 }
 
-pub fn load_bazel_flags() -> BazelFlags {
-    let proto_bytes = include_bytes!("../proto/flag-dumps/7.1.0.data");
-    let flags = FlagCollection::decode(&mut Cursor::new(proto_bytes))
-        .unwrap()
-        .flag_infos;
-    BazelFlags::from_flags(flags)
+// Loads the flags of the bundled flag dump.
+//
+// The flags are decoded only once per process. All callers share the same
+// instance, independent of how many documents are open.
+pub fn load_bazel_flags() -> Arc<BazelFlags> {
+    static BAZEL_FLAGS: OnceLock<Arc<BazelFlags>> = OnceLock::new();
+    BAZEL_FLAGS
+        .get_or_init(|| {
+            let proto_bytes = include_bytes!("../proto/flag-dumps/7.1.0.data");
+            let flags = FlagCollection::decode(&mut Cursor::new(proto_bytes))
+                .unwrap()
+                .flag_infos;
+            Arc::new(BazelFlags::from_flags(flags))
+        })
+        .clone()
 }
 
-fn escape_markdown(str: &str) -> String {
-    let mut res = String::with_capacity(str.len());
-    for c in str.chars() {
-        match c {
-            '\\' => res.push_str("\\\\"),
-            '`' => res.push_str("\\`"),
-            '*' => res.push_str("\\*"),
-            '_' => res.push_str("\\_"),
-            '#' => res.push_str("\\#"),
-            '+' => res.push_str("\\+"),
-            '-' => res.push_str("\\-"),
-            '.' => res.push_str("\\."),
-            '!' => res.push_str("\\!"),
-            '~' => res.push_str("\\~"),
-            '{' => res.push_str("\\{"),
-            '}' => res.push_str("\\}"),
-            '[' => res.push_str("\\["),
-            ']' => res.push_str("\\]"),
-            '(' => res.push_str("\\("),
-            ')' => res.push_str("\\)"),
-            '<' => res.push_str("\\<"),
-            '>' => res.push_str("\\>"),
-            _ => res.push(c),
-        }
+// Loads the flags of the bundled flag dump for the given version, which is one
+// of the `FLAG_DUMP_VERSIONS`
+pub fn load_bazel_flags_for_version(version: &str) -> Option<Arc<BazelFlags>> {
+    // Currently, only a single flag dump is bundled
+    FLAG_DUMP_VERSIONS
+        .last()
+        .filter(|v| **v == version)
+        .map(|_| load_bazel_flags())
+}
+
+// Loads the flags of the given Bazel release. Uses the bundled flag dump of that release, if
+// any. Otherwise, the flags are derived from the newest flag dump, see `for_release`.
+// Returns `None` for versions which aren't releases, e.g. `latest`.
+pub fn load_bazel_flags_for_release(version: &str) -> Option<Arc<BazelFlags>> {
+    if let Some(flags) = load_bazel_flags_for_version(version) {
+        return Some(flags);
     }
-    res
+    let release = parse_release(version)?;
+    // Deriving the flags is expensive, hence they are cached just like the bundled flags
+    type DerivedFlags = Mutex<HashMap<(u32, u32, u32), Arc<BazelFlags>>>;
+    static DERIVED_FLAGS: OnceLock<DerivedFlags> = OnceLock::new();
+    let mut derived_flags = DERIVED_FLAGS.get_or_init(Default::default).lock().unwrap();
+    let flags = derived_flags
+        .entry(release)
+        .or_insert_with(|| Arc::new(load_bazel_flags().for_release(release)));
+    Some(flags.clone())
 }
 
-// Combines flags names with their values based on the `requires_value` metadata
+// Combines flags names with their values based on the `requires_value` metadata, such that
+// the value of `--jobs 8` is bound to `--jobs`, just like for `--jobs=8`
 pub fn combine_key_value_flags(lines: &mut [crate::parser::Line], bazel_flags: &BazelFlags) {
     use crate::parser::Flag;
     use crate::tokenizer::Spanned;
@@ -169,7 +444,7 @@ pub fn combine_key_value_flags(lines: &mut [crate::parser::Line], bazel_flags: &
                 || -> Option<Spanned<String>> {
                     let flag_name = &flag.name.as_ref()?.0;
                     let info = bazel_flags.get_by_invocation(flag_name)?;
-                    if info.requires_value() {
+                    if info.takes_value() && flag.value.is_none() {
                         // Combine with the next flag
                         let next_flag = &l.flags.get(i + 1)?;
                         i += 1;
@@ -207,58 +482,84 @@ impl FlagInfo {
         self.metadata_tags.contains(&"DEPRECATED".to_string())
     }
 
-    pub fn supports_command(&self, command: &str) -> bool {
-        command == "common" || command == "always" || self.commands.iter().any(|c| c == command)
+    // Experimental flags might change or disappear in any Bazel release
+    pub fn is_experimental(&self) -> bool {
+        self.name.starts_with("experimental_")
+            || self.metadata_tags.contains(&"EXPERIMENTAL".to_string())
     }
 
-    pub fn get_documentation_markdown(&self) -> String {
-        let mut result = String::new();
+    // Flags which are only kept for backwards compatibility and don't have any effect
+    pub fn is_noop(&self) -> bool {
+        self.effect_tags.contains(&"NO_OP".to_string())
+    }
 
-        // First line: Flag name and short hand (if any)
-        result += format!("`--{}`", self.name).as_str();
-        if let Some(abbr) = &self.abbreviation {
-            result += format!(" [`-{}`]", abbr).as_str();
-        }
-        if self.has_negative_flag() {
-            result += format!(", `--no{}`", self.name).as_str();
-        }
-        // Followed by the documentation text
-        if let Some(doc) = &self.documentation {
-            result += "\n\n";
-            result += &escape_markdown(&doc.as_str().replace("%{product}", "Bazel"));
-        }
-        // And a list of tags
-        result += "\n\n";
-        if !self.effect_tags.is_empty() {
-            result += "Effect tags: ";
-            result += self
-                .effect_tags
-                .iter()
-                .map(|t| t.to_lowercase())
-                .collect::<Vec<_>>()
-                .join(", ")
-                .as_str();
-            result += "\\\n";
-        }
-        if !self.metadata_tags.is_empty() {
-            result += "Tags: ";
-            result += self
-                .metadata_tags
-                .iter()
-                .map(|t| t.to_lowercase())
-                .collect::<Vec<_>>()
-                .join(", ")
-                .as_str();
-            result += "\\\n";
+    // The sentence of the documentation explaining the deprecation, e.g. which flag
+    // replaces this flag. Bare "No-op." documentation does not add any information.
+    pub fn deprecation_message(&self) -> Option<&str> {
+        const KEYWORDS: [&str; 5] = ["deprecat", "no-op", "obsolete", "superseded", "removed"];
+        let documentation = self.documentation.as_deref()?;
+        documentation
+            .split_inclusive(". ")
+            .map(|s| s.trim())
+            .filter(|s| s.split_whitespace().count() > 3)
+            .find(|s| {
+                let lower = s.to_lowercase();
+                KEYWORDS.iter().any(|k| lower.contains(k))
+            })
+    }
+
+    // Whether the flag consumes the following token as its value, if no value is given
+    // using `=`. Flag dumps without the `requires_value` metadata fall back to assuming
+    // that all non-boolean flags take a value.
+    pub fn takes_value(&self) -> bool {
+        self.requires_value.unwrap_or(!self.has_negative_flag())
+    }
+
+    // Whether the flag accepts a value at all. Expansion flags (e.g. `--remote_download_minimal`)
+    // and flags without a value (e.g. `--expunge_async`) don't.
+    pub fn accepts_value(&self) -> bool {
+        self.takes_value() || self.has_negative_flag() || !self.allowed_values.is_empty()
+    }
+
+    // Whether the flag can be used with the given command. Commands inherit the options
+    // of their parent command, e.g. `test` accepts all `build` options.
+    pub fn supports_command(&self, command: &str) -> bool {
+        if command == "common" || command == "always" {
+            return true;
         }
-        if let Some(catgegory) = &self.documentation_category {
-            result += format!("Category: {}\n", catgegory.to_lowercase()).as_str();
+        let mut command = Some(command);
+        while let Some(c) = command {
+            if self.commands.iter().any(|fc| fc == c) {
+                return true;
+            }
+            command = get_parent_command(c);
         }
+        false
+    }
 
-        result
+    pub fn get_documentation_markdown(&self) -> String {
+        render_flag_documentation(self, None, DocFormat::Markdown)
     }
 }
 
+#[test]
+fn test_host_counterparts() {
+    let flags = load_bazel_flags();
+    let copt = flags.get_by_invocation("--copt").unwrap();
+    let host_copt = flags.get_by_invocation("--host_copt").unwrap();
+    assert_eq!(flags.get_host_counterpart(copt), Some(host_copt));
+    assert_eq!(flags.get_host_counterpart(host_copt), Some(copt));
+    let keep_going = flags.get_by_invocation("--keep_going").unwrap();
+    assert_eq!(flags.get_host_counterpart(keep_going), None);
+
+    let docs = flags.get_documentation_markdown(copt);
+    assert!(docs.ends_with(
+        "Host counterpart: [`--host_copt`](https://bazel.build/reference/command-line-reference#flag--host_copt)\n"
+    ));
+    let docs = flags.get_documentation_markdown(host_copt);
+    assert!(docs.contains("Target counterpart: [`--copt`]"));
+}
+
 #[test]
 fn test_flags() {
     let flags = load_bazel_flags();
@@ -303,3 +604,244 @@ fn test_flags() {
         .unwrap()
         .contains(&build_flag_id));
 }
+
+#[test]
+fn test_combine_key_value_flags() {
+    use crate::parser::parse_from_str;
+
+    let flag = |name: &str, has_negative_flag: bool, requires_value: Option<bool>| FlagInfo {
+        name: name.to_string(),
+        has_negative_flag: Some(has_negative_flag),
+        requires_value,
+        abbreviation: (name == "compilation_mode").then(|| "c".to_string()),
+        commands: vec!["build".to_string()],
+        ..Default::default()
+    };
+    let flags = BazelFlags::from_flags(vec![
+        flag("jobs", false, Some(true)),
+        flag("compilation_mode", false, Some(true)),
+        flag("keep_going", true, Some(false)),
+        // Without the `requires_value` metadata, non-boolean flags take a value
+        flag("copt", false, None),
+        flag("verbose", true, None),
+    ]);
+    let combine = |src: &str| {
+        let mut lines = parse_from_str(src).lines;
+        combine_key_value_flags(&mut lines, &flags);
+        lines[0]
+            .flags
+            .iter()
+            .map(|f| {
+                (
+                    f.name.as_ref().map(|n| n.0.clone()),
+                    f.value.clone().map(|v| v.0),
+                    f.value.clone().map(|v| v.1),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let some = |s: &str| Some(s.to_string());
+    assert_eq!(
+        combine("build --jobs 8 -c opt --keep_going false"),
+        vec![
+            (some("--jobs"), some("8"), Some(13..14)),
+            (some("-c"), some("opt"), Some(18..21)),
+            (some("--keep_going"), None, None),
+            (None, some("false"), Some(35..40)),
+        ]
+    );
+    assert_eq!(
+        combine("build --copt -O2 --verbose x --jobs=1 --jobs"),
+        vec![
+            (some("--copt"), some("-O2"), Some(13..16)),
+            (some("--verbose"), None, None),
+            (None, some("x"), Some(27..28)),
+            (some("--jobs"), some("1"), Some(35..37)),
+            (some("--jobs"), None, None),
+        ]
+    );
+}
+
+#[test]
+fn test_negated_invocations() {
+    let flags = load_bazel_flags();
+    let invocation = flags.resolve_invocation("--nokeep_going").unwrap();
+    assert_eq!(invocation.flag.name, "keep_going");
+    assert!(invocation.negated);
+    assert!(!flags.resolve_invocation("--keep_going").unwrap().negated);
+    assert!(!flags.resolve_invocation("-k").unwrap().negated);
+    // Only boolean flags have a `--no` form
+    assert!(flags.get_by_invocation("--jobs").is_some());
+    assert_eq!(flags.get_by_invocation("--nojobs"), None);
+    assert_eq!(flags.get_by_invocation("--nounknown_flag"), None);
+}
+
+#[test]
+fn test_invocations_with_values() {
+    let flags = load_bazel_flags();
+    let jobs = flags.resolve_invocation("--jobs=16").unwrap();
+    assert_eq!(jobs.flag.name, "jobs");
+    assert_eq!(jobs.value, Some("16"));
+    let keep_going = flags.resolve_invocation("-k=0").unwrap();
+    assert_eq!(keep_going.flag.name, "keep_going");
+    assert_eq!(keep_going.value, Some("0"));
+    // Only the first `=` separates the value
+    let copt = flags.resolve_invocation("--copt=-DX=1").unwrap();
+    assert_eq!(copt.value, Some("-DX=1"));
+    assert_eq!(flags.resolve_invocation("--jobs=").unwrap().value, Some(""));
+    assert_eq!(flags.resolve_invocation("--jobs").unwrap().value, None);
+    assert_eq!(
+        flags.get_by_invocation("--nokeep_going=1"),
+        flags.get_by_invocation("--keep_going")
+    );
+}
+
+#[test]
+fn test_supports_command() {
+    let flag = FlagInfo {
+        name: "build_only".to_string(),
+        commands: vec!["build".to_string()],
+        ..Default::default()
+    };
+    assert!(flag.supports_command("build"));
+    assert!(flag.supports_command("common"));
+    // `coverage` inherits from `test`, which inherits from `build`
+    assert!(flag.supports_command("test"));
+    assert!(flag.supports_command("coverage"));
+    assert!(!flag.supports_command("query"));
+    assert!(!flag.supports_command("startup"));
+}
+
+#[test]
+fn test_allowed_values() {
+    let flags = load_bazel_flags();
+    // All flags of the hand-maintained metadata exist
+    for name in ALLOWED_VALUES
+        .keys()
+        .chain(VALUE_TYPES.keys())
+        .chain(FLAG_VERSIONS.keys())
+        .chain(DEFAULT_VALUES.keys())
+    {
+        assert!(flags.flags_by_name.contains_key(*name), "{}", name);
+    }
+    // Default values are accepted by the flags
+    for (name, default) in DEFAULT_VALUES.entries() {
+        let info = flags.get_by_invocation(&format!("--{}", name)).unwrap();
+        if info.allowed_values.is_empty() && info.has_negative_flag() {
+            assert!(["true", "false"].contains(default), "{}", name);
+        } else if !info.allowed_values.is_empty() {
+            assert!(
+                info.allowed_values.contains(&default.to_string()),
+                "{}",
+                name
+            );
+        }
+    }
+    for name in REMOVED_FLAGS.keys() {
+        assert!(!flags.flags_by_name.contains_key(*name), "{}", name);
+    }
+    assert_eq!(
+        flags
+            .get_by_invocation("--enable_workspace")
+            .unwrap()
+            .added_in_version
+            .as_deref(),
+        Some("7.1.0")
+    );
+    assert_eq!(
+        removed_flag_version("experimental_enable_bzlmod"),
+        Some("6.0.0")
+    );
+    assert_eq!(
+        flags.get_by_invocation("-c").unwrap().allowed_values,
+        vec!["fastbuild", "dbg", "opt"]
+    );
+    assert!(flags
+        .get_by_invocation("--jobs")
+        .unwrap()
+        .allowed_values
+        .is_empty());
+    assert_eq!(
+        flags.get_by_invocation("--jobs").unwrap().value_type(),
+        "resource"
+    );
+    assert_eq!(flags.get_by_invocation("-c").unwrap().value_type(), "");
+    for (name, value_type) in VALUE_TYPES.entries() {
+        assert_eq!(
+            flags
+                .get_by_invocation(&format!("--{}", name))
+                .map(|f| f.value_type()),
+            Some(*value_type)
+        );
+    }
+}
+
+#[test]
+fn test_flags_for_release() {
+    let bundled = load_bazel_flags();
+    assert!(Arc::ptr_eq(
+        &load_bazel_flags_for_release("7.1.0").unwrap(),
+        &bundled
+    ));
+    assert!(load_bazel_flags_for_release("latest").is_none());
+
+    let newer = load_bazel_flags_for_release("9.0.0").unwrap();
+    assert!(newer.flags_by_name.contains_key("keep_going"));
+    assert!(newer.flags_by_name.contains_key("enable_workspace"));
+    // Removed in Bazel 8
+    assert!(bundled.flags_by_name.contains_key("javabase"));
+    assert!(!newer.flags_by_name.contains_key("javabase"));
+
+    let older = load_bazel_flags_for_release("6.5.0").unwrap();
+    assert!(!older.flags_by_name.contains_key("enable_workspace"));
+    assert!(older.flags_by_name.contains_key("enable_bzlmod"));
+    assert!(!older
+        .flags_by_name
+        .contains_key("experimental_enable_bzlmod"));
+    // Flags removed before the flag dump are added back
+    let oldest = load_bazel_flags_for_release("5.4.0").unwrap();
+    assert!(!oldest.flags_by_name.contains_key("enable_bzlmod"));
+    let removed = oldest
+        .get_by_invocation("--noexperimental_enable_bzlmod")
+        .unwrap();
+    assert!(removed.supports_command("build"));
+    assert!(!removed.supports_command("startup"));
+}
+
+#[test]
+fn test_default_changes() {
+    let bundled = load_bazel_flags();
+    let dump_release = parse_release(FLAG_DUMP_VERSIONS.last().unwrap()).unwrap();
+    // The changes are consistent with the defaults of the flag dump
+    for (name, changes) in DEFAULT_CHANGES.entries() {
+        assert!(changes
+            .windows(2)
+            .all(|w| parse_release(w[0].0) < parse_release(w[1].0)));
+        assert_eq!(
+            bundled.flags[bundled.flags_by_name[*name]]
+                .default_value
+                .as_deref(),
+            default_value_in_release(name, dump_release),
+            "{}",
+            name
+        );
+    }
+
+    let default_in = |version: &str, name: &str| {
+        let flags = load_bazel_flags_for_release(version).unwrap();
+        flags.flags[flags.flags_by_name[name]].default_value.clone()
+    };
+    assert_eq!(
+        default_in("6.5.0", "enable_bzlmod").as_deref(),
+        Some("false")
+    );
+    assert_eq!(
+        default_in("7.0.0", "enable_bzlmod").as_deref(),
+        Some("true")
+    );
+    assert_eq!(
+        default_in("8.0.0", "enable_workspace").as_deref(),
+        Some("false")
+    );
+    assert_eq!(default_in("8.0.0", "keep_going").as_deref(), Some("false"));
+}
@@ -0,0 +1,272 @@
+use std::path::{Path, PathBuf};
+
+// The Bazel versions for which we ship a flag dump
+pub const FLAG_DUMP_VERSIONS: &[&str] = &["7.1.0"];
+
+// Where the Bazel version was configured
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VersionSource {
+    // The `USE_BAZEL_VERSION` environment variable
+    Environment,
+    // `USE_BAZEL_VERSION` in a `.bazeliskrc` file
+    Bazeliskrc(PathBuf),
+    // A `.bazelversion` file
+    Bazelversion(PathBuf),
+    // `USE_BAZEL_FALLBACK_VERSION`, from the environment or a `.bazeliskrc`
+    Fallback,
+    // Passed as a command line argument
+    CommandLine,
+    // Configured in the language server's settings
+    Settings,
+}
+
+// A Bazel version, as understood by Bazelisk.
+//
+// The version is `[<fork>/]<version>`, where the version can be a release (`7.1.0`),
+// a release candidate (`7.1.0rc1`), a wildcard (`7.x`), `latest`, `latest-N`,
+// `last_green`, `last_rc`, `rolling` or a commit hash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BazelVersion {
+    pub fork: Option<String>,
+    pub version: String,
+    pub source: VersionSource,
+}
+
+impl BazelVersion {
+    pub fn parse(spec: &str, source: VersionSource) -> Option<BazelVersion> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return None;
+        }
+        let (fork, version) = match spec.rsplit_once('/') {
+            Some((fork, version)) => (Some(fork.to_string()), version),
+            None => (None, spec),
+        };
+        if version.is_empty() {
+            return None;
+        }
+        Some(BazelVersion {
+            fork,
+            version: version.to_string(),
+            source,
+        })
+    }
+
+    // The major version, if the version is a release, a release candidate or a wildcard
+    pub fn major_version(&self) -> Option<u32> {
+        let major = self.version.split('.').next()?;
+        if major.len() == self.version.len() {
+            // No `.` at all, e.g. a commit hash or `latest`
+            return None;
+        }
+        major.parse().ok()
+    }
+
+    // The `(major, minor, patch)` version, if the version is a release or a release candidate
+    pub fn release(&self) -> Option<(u32, u32, u32)> {
+        parse_release(&self.version)
+    }
+
+    // The flag dump to use for this version.
+    //
+    // Picks the newest flag dump with the same major version, and falls back to
+    // the newest flag dump otherwise.
+    pub fn flag_dump_version(&self) -> &'static str {
+        self.exact_flag_dump_version()
+            .unwrap_or(FLAG_DUMP_VERSIONS.last().unwrap())
+    }
+
+    // The newest flag dump with the same major version, without any fallback
+    pub fn exact_flag_dump_version(&self) -> Option<&'static str> {
+        let major = self.major_version()?;
+        FLAG_DUMP_VERSIONS
+            .iter()
+            .rev()
+            .find(|v| v.split('.').next() == Some(&major.to_string()))
+            .copied()
+    }
+}
+
+// Parses a release version like `7.1.0` or a release candidate like `7.1.0rc1`
+pub fn parse_release(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.splitn(3, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts.next()?;
+    let patch = patch[..patch.find("rc").unwrap_or(patch.len())]
+        .parse()
+        .ok()?;
+    Some((major, minor, patch))
+}
+
+impl std::fmt::Display for BazelVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(fork) = &self.fork {
+            write!(f, "{}/", fork)?;
+        }
+        write!(f, "{}", self.version)?;
+        match &self.source {
+            VersionSource::Environment => write!(f, " (from $USE_BAZEL_VERSION)"),
+            VersionSource::Bazeliskrc(path) | VersionSource::Bazelversion(path) => {
+                write!(f, " (from {})", path.display())
+            }
+            VersionSource::Fallback => write!(f, " (from USE_BAZEL_FALLBACK_VERSION)"),
+            VersionSource::CommandLine => Ok(()),
+            VersionSource::Settings => write!(f, " (from the settings)"),
+        }
+    }
+}
+
+// Looks up a variable in the contents of a `.bazeliskrc` file.
+// The file consists of `KEY=value` lines. Lines starting with `#` are comments.
+pub fn get_bazeliskrc_value(contents: &str, key: &str) -> Option<String> {
+    contents.lines().rev().find_map(|line| {
+        let line = line.trim();
+        if line.starts_with('#') {
+            return None;
+        }
+        let (k, v) = line.split_once('=')?;
+        (k.trim() == key).then(|| v.trim().to_string())
+    })
+}
+
+// The fallback version has the format `[error:|warn:|silent:]<version>`
+fn strip_fallback_mode(value: &str) -> &str {
+    ["error:", "warn:", "silent:"]
+        .iter()
+        .find_map(|prefix| value.strip_prefix(prefix))
+        .unwrap_or(value)
+}
+
+// Determines the Bazel version Bazelisk would use for the given workspace.
+//
+// Bazelisk checks, in this order: the `USE_BAZEL_VERSION` environment variable, the
+// `.bazeliskrc` in the workspace, the `.bazeliskrc` in the home directory, the
+// `.bazelversion` file and finally `USE_BAZEL_FALLBACK_VERSION`.
+pub fn determine_bazel_version(
+    workspace: Option<&Path>,
+    home: Option<&Path>,
+    get_env: impl Fn(&str) -> Option<String>,
+) -> Option<BazelVersion> {
+    if let Some(version) = get_env("USE_BAZEL_VERSION")
+        .and_then(|v| BazelVersion::parse(&v, VersionSource::Environment))
+    {
+        return Some(version);
+    }
+    let bazeliskrcs = [workspace, home]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(".bazeliskrc"))
+        .filter_map(|path| Some((std::fs::read_to_string(&path).ok()?, path)))
+        .collect::<Vec<_>>();
+    for (contents, path) in &bazeliskrcs {
+        if let Some(version) = get_bazeliskrc_value(contents, "USE_BAZEL_VERSION")
+            .and_then(|v| BazelVersion::parse(&v, VersionSource::Bazeliskrc(path.clone())))
+        {
+            return Some(version);
+        }
+    }
+    if let Some(workspace) = workspace {
+        let path = workspace.join(".bazelversion");
+        if let Some(version) = std::fs::read_to_string(&path).ok().and_then(|contents| {
+            // Only the first line is relevant
+            let first_line = contents.lines().next()?.to_string();
+            BazelVersion::parse(&first_line, VersionSource::Bazelversion(path.clone()))
+        }) {
+            return Some(version);
+        }
+    }
+    get_env("USE_BAZEL_FALLBACK_VERSION")
+        .or_else(|| {
+            bazeliskrcs.iter().find_map(|(contents, _)| {
+                get_bazeliskrc_value(contents, "USE_BAZEL_FALLBACK_VERSION")
+            })
+        })
+        .and_then(|v| BazelVersion::parse(strip_fallback_mode(&v), VersionSource::Fallback))
+}
+
+#[test]
+fn test_parse_version() {
+    let version = BazelVersion::parse("someorg/5.x", VersionSource::Environment).unwrap();
+    assert_eq!(version.fork.as_deref(), Some("someorg"));
+    assert_eq!(version.version, "5.x");
+    assert_eq!(version.major_version(), Some(5));
+    assert_eq!(version.flag_dump_version(), "7.1.0");
+
+    let version = BazelVersion::parse("7.0.2rc1\n", VersionSource::Environment).unwrap();
+    assert_eq!(version.fork, None);
+    assert_eq!(version.major_version(), Some(7));
+    assert_eq!(version.flag_dump_version(), "7.1.0");
+    assert_eq!(version.exact_flag_dump_version(), Some("7.1.0"));
+
+    let version = BazelVersion::parse("9.0.0", VersionSource::CommandLine).unwrap();
+    assert_eq!(version.flag_dump_version(), "7.1.0");
+    assert_eq!(version.exact_flag_dump_version(), None);
+
+    let version = BazelVersion::parse("latest", VersionSource::Environment).unwrap();
+    assert_eq!(version.major_version(), None);
+    assert_eq!(version.flag_dump_version(), "7.1.0");
+
+    assert_eq!(BazelVersion::parse(" ", VersionSource::Environment), None);
+    assert_eq!(
+        BazelVersion::parse("someorg/", VersionSource::Environment),
+        None
+    );
+}
+
+#[test]
+fn test_determine_bazel_version() {
+    let ws = crate::import_graph::create_test_workspace(
+        "bazel-version",
+        &[
+            (".bazelversion", "7.1.0\n"),
+            (
+                "home/.bazeliskrc",
+                "# Comment\nUSE_BAZEL_VERSION=hisorg/6.x\n",
+            ),
+        ],
+    );
+    let home = ws.join("home");
+    let no_env = |_: &str| None;
+
+    // The `.bazeliskrc` in the home directory takes precedence over `.bazelversion`
+    let version = determine_bazel_version(Some(&ws), Some(&home), no_env).unwrap();
+    assert_eq!(
+        version.to_string(),
+        format!("hisorg/6.x (from {})", home.join(".bazeliskrc").display())
+    );
+
+    // ... but not over the environment variable
+    let version = determine_bazel_version(Some(&ws), Some(&home), |k: &str| {
+        (k == "USE_BAZEL_VERSION").then(|| "8.0.0".to_string())
+    })
+    .unwrap();
+    assert_eq!(version.to_string(), "8.0.0 (from $USE_BAZEL_VERSION)");
+
+    // Without a `.bazeliskrc`, the `.bazelversion` file is used
+    let version = determine_bazel_version(Some(&ws), None, no_env).unwrap();
+    assert_eq!(version.version, "7.1.0");
+    assert_eq!(
+        version.source,
+        VersionSource::Bazelversion(ws.join(".bazelversion"))
+    );
+
+    // The fallback version is used if nothing else is configured
+    let version = determine_bazel_version(None, None, |k: &str| {
+        (k == "USE_BAZEL_FALLBACK_VERSION").then(|| "warn:6.4.0".to_string())
+    })
+    .unwrap();
+    assert_eq!(
+        version.to_string(),
+        "6.4.0 (from USE_BAZEL_FALLBACK_VERSION)"
+    );
+}
+
+#[test]
+fn test_parse_release() {
+    assert_eq!(parse_release("7.1.0"), Some((7, 1, 0)));
+    assert_eq!(parse_release("7.0.2rc1"), Some((7, 0, 2)));
+    assert_eq!(parse_release("7.x"), None);
+    assert_eq!(parse_release("latest"), None);
+    assert_eq!(parse_release("1a2b3c4d"), None);
+}
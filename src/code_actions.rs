@@ -0,0 +1,120 @@
+use ropey::Rope;
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{
+    config_index::name_span,
+    formatting::format_token,
+    lsp_utils::{lsp_pos_to_offset, offset_to_lsp_pos, range_to_lsp},
+    parser::Line,
+};
+
+// An edit replacing a misspelled config name within the given range, e.g. the range of
+// an undefined-config diagnostic, by the suggested name
+pub fn get_config_rename_edit(
+    rope: &Rope,
+    range: &Range,
+    config: &str,
+    suggestion: &str,
+) -> Option<TextEdit> {
+    let span = lsp_pos_to_offset(rope, &range.start)?..lsp_pos_to_offset(rope, &range.end)?;
+    Some(TextEdit {
+        range: range_to_lsp(rope, &name_span(rope, &span, config))?,
+        new_text: format_token(suggestion),
+    })
+}
+
+// An edit inserting a `command:config` stub line, defining a config which is referenced
+// but not defined yet.
+//
+// The stub is inserted after the last definition of another config for the same command,
+// or after the last config definition, such that it ends up close to related configs.
+// If the file has no config definitions at all, the stub is appended at the end.
+pub fn get_config_stub_edit(rope: &Rope, lines: &[Line], command: &str, config: &str) -> TextEdit {
+    let is_definition = |l: &&Line| l.config.is_some() && l.command.is_some();
+    let anchor = lines
+        .iter()
+        .filter(is_definition)
+        .rfind(|l| l.command.as_ref().is_some_and(|c| c.0 == command))
+        .or_else(|| lines.iter().rfind(is_definition));
+
+    let stub = format!(
+        "{}:{} # TODO: add the flags for this config",
+        format_token(command),
+        format_token(config)
+    );
+    let insert_line =
+        anchor.map(|l| rope.char_to_line(l.span.end.saturating_sub(1).max(l.span.start)) + 1);
+    let (position, new_text) = match insert_line {
+        Some(line) if line < rope.len_lines() && rope.line_to_char(line) < rope.len_chars() => {
+            (Position::new(line as u32, 0), format!("{}\n", stub))
+        }
+        _ => {
+            // Append at the end of the file, starting a new line if necessary
+            let end = offset_to_lsp_pos(rope, rope.len_chars()).unwrap_or_default();
+            let needs_newline = rope.len_chars() > 0 && rope.char(rope.len_chars() - 1) != '\n';
+            let prefix = if needs_newline { "\n" } else { "" };
+            (end, format!("{}{}\n", prefix, stub))
+        }
+    };
+    TextEdit {
+        range: Range::new(position, position),
+        new_text,
+    }
+}
+
+#[test]
+fn test_config_rename_edit() {
+    let rope = Rope::from_str("build --config=relese");
+    let range = Range::new(Position::new(0, 14), Position::new(0, 21));
+    let edit = get_config_rename_edit(&rope, &range, "relese", "release").unwrap();
+    assert_eq!(
+        edit.range,
+        Range::new(Position::new(0, 15), Position::new(0, 21))
+    );
+    assert_eq!(edit.new_text, "release");
+}
+
+#[test]
+fn test_config_stub_edit() {
+    use crate::parser::parse_from_str;
+
+    let stub_edit = |src: &str, command: &str| {
+        let edit = get_config_stub_edit(
+            &Rope::from_str(src),
+            &parse_from_str(src).lines,
+            command,
+            "new",
+        );
+        (
+            edit.range.start.line,
+            edit.range.start.character,
+            edit.new_text,
+        )
+    };
+    // Inserted after related configs
+    assert_eq!(
+        stub_edit("build:a -k\ntest:b -k\nbuild:c -k\n\ntest:d -k\n", "build"),
+        (
+            3,
+            0,
+            "build:new # TODO: add the flags for this config\n".to_string()
+        )
+    );
+    assert_eq!(
+        stub_edit("build:a -k\ntest:b -k\n", "run"),
+        (
+            2,
+            0,
+            "run:new # TODO: add the flags for this config\n".to_string()
+        )
+    );
+    // Appended to the end of the file otherwise
+    assert_eq!(
+        stub_edit("build -k", "build"),
+        (
+            0,
+            8,
+            "\nbuild:new # TODO: add the flags for this config\n".to_string()
+        )
+    );
+}
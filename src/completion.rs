@@ -1,48 +1,136 @@
+use std::collections::HashMap;
+use std::path::Path;
+
 use ropey::Rope;
 use tower_lsp::lsp_types::{
-    CompletionItem, CompletionItemTag, CompletionTextEdit, Documentation, MarkupContent,
-    MarkupKind, Range, TextEdit,
+    CompletionItem, CompletionItemKind, CompletionItemLabelDetails, CompletionItemTag,
+    CompletionTextEdit, Documentation, InsertTextFormat, MarkupContent, MarkupKind, Range,
+    TextEdit, Url,
 };
 
 use crate::{
     bazel_flags::{BazelFlags, COMMAND_DOCS},
     bazel_flags_proto::FlagInfo,
+    config_index::{is_config_flag, name_span, ConfigIndex},
+    effective_config::get_command_chain,
+    file_utils::{get_workspace_path, is_bazelrc_file, resolve_bazelrc_path},
     line_index::{IndexEntryKind, IndexedLines},
     lsp_utils::range_to_lsp,
+    semantic_model::{is_startup_flag, supports_configs},
+    settings::Settings,
     tokenizer::Span,
 };
 
+// The commands, including `import` and `try-import`, with the first sentence of their
+// documentation shown inline
 fn complete_bazel_command(bazel_flags: &BazelFlags) -> Vec<CompletionItem> {
-    bazel_flags
-        .commands
-        .iter()
+    let mut commands = bazel_flags.commands.iter().collect::<Vec<_>>();
+    commands.sort();
+    commands
+        .into_iter()
         .map(|cmd| CompletionItem {
             label: cmd.clone(),
-            commit_characters: Some(vec![':'.to_string()]),
+            kind: Some(CompletionItemKind::KEYWORD),
+            label_details: COMMAND_DOCS
+                .get(cmd)
+                .map(|docs| CompletionItemLabelDetails {
+                    detail: None,
+                    description: docs
+                        .split(". ")
+                        .next()
+                        .map(|s| s.trim_end_matches('.').to_string()),
+                }),
+            // Typing `:` continues with the config name
+            commit_characters: supports_configs(cmd).then(|| vec![':'.to_string()]),
             documentation: get_command_documentation(cmd),
             ..Default::default()
         })
         .collect::<Vec<_>>()
 }
 
-fn complete_bazel_flag(
-    bazel_flags: &BazelFlags,
-    command: &str,
-    range: Range,
-) -> Vec<CompletionItem> {
-    let exisiting_flags = bazel_flags.flags_by_commands.get(command);
+// The completion items for the flags of each command.
+//
+// Rendering the documentation of all flags is expensive. Hence, the items are built
+// once when loading the flags, and completion requests only clone them and fill in
+// the range to replace. Each command lists the flags it supports, including the flags
+// inherited from parent commands, e.g. `test` also lists all `build` flags. Flag dumps
+// usually already list the inherited commands, but not necessarily all of them.
+#[derive(Debug, Default)]
+pub struct FlagCompletions {
+    items_by_command: HashMap<String, Vec<CompletionItem>>,
+}
 
-    if exisiting_flags.is_none() {
-        return vec![];
+impl FlagCompletions {
+    pub fn new(bazel_flags: &BazelFlags) -> FlagCompletions {
+        let items_by_command = bazel_flags
+            .flags_by_commands
+            .keys()
+            .map(|command| {
+                let flags = bazel_flags.flags.iter().filter(|f| match command.as_str() {
+                    // `common` and `always` accept the flags of all commands, but no startup options
+                    "common" | "always" => !is_startup_flag(f),
+                    _ => f.supports_command(command),
+                });
+                (
+                    command.clone(),
+                    create_flag_completion_items(bazel_flags, flags),
+                )
+            })
+            .collect();
+        FlagCompletions { items_by_command }
     }
 
-    let relevant_flags = exisiting_flags
-        .unwrap()
-        .iter()
-        .map(|i| &bazel_flags.flags[*i])
-        // Hide undocumented flags
-        .filter(|f| f.documentation_category != Some("UNDOCUMENTED".to_string()));
+    // The completion items for all flags of the command, replacing the given range.
+    // Optionally, the flags are sorted by their documentation category instead of by name.
+    // Without snippet support, flags requiring a value are completed without placeholder.
+    pub fn get(
+        &self,
+        command: &str,
+        range: Range,
+        sort_by_category: bool,
+        snippet_support: bool,
+    ) -> Vec<CompletionItem> {
+        let Some(items) = self.items_by_command.get(command) else {
+            return vec![];
+        };
+        items
+            .iter()
+            .cloned()
+            .map(|mut item| {
+                if let Some(CompletionTextEdit::Edit(edit)) = &mut item.text_edit {
+                    edit.range = range;
+                    if !snippet_support
+                        && item.insert_text_format == Some(InsertTextFormat::SNIPPET)
+                    {
+                        edit.new_text = item.filter_text.clone().unwrap_or_default();
+                        item.insert_text_format = None;
+                        item.commit_characters = Some(vec!["=".to_string()]);
+                    }
+                }
+                if sort_by_category {
+                    // Flags without category go last
+                    let category = item
+                        .label_details
+                        .as_ref()
+                        .and_then(|d| d.description.as_deref())
+                        .unwrap_or("~");
+                    item.sort_text = Some(format!("{}/{}", category, item.label));
+                }
+                item
+            })
+            .collect()
+    }
+}
+
+fn create_flag_completion_items<'a>(
+    bazel_flags: &BazelFlags,
+    flags: impl Iterator<Item = &'a FlagInfo> + Clone,
+) -> Vec<CompletionItem> {
+    // Hide undocumented flags
+    let relevant_flags =
+        flags.filter(|f| f.documentation_category != Some("UNDOCUMENTED".to_string()));
 
+    // The range is filled in by `FlagCompletions::get`
     let create_completion_item =
         |label: String, new_text: String, flag: &FlagInfo, commit_characters: Vec<String>| {
             let tags = if flag.is_deprecated() {
@@ -50,11 +138,30 @@ fn complete_bazel_flag(
             } else {
                 None
             };
+            // Show the category and effect tags inline, to make the list easier to browse
+            let label_details = CompletionItemLabelDetails {
+                detail: None,
+                description: flag
+                    .documentation_category
+                    .as_ref()
+                    .map(|c| c.to_lowercase()),
+            };
+            let detail = if flag.effect_tags.is_empty() {
+                None
+            } else {
+                let tags = flag.effect_tags.iter().map(|t| t.to_lowercase());
+                Some(format!("Effects: {}", tags.collect::<Vec<_>>().join(", ")))
+            };
             CompletionItem {
                 label,
-                documentation: get_flag_documentation(flag),
+                label_details: Some(label_details),
+                detail,
+                documentation: get_flag_documentation(bazel_flags, flag),
                 filter_text: Some(new_text.clone()),
-                text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range: Range::default(),
+                    new_text,
+                })),
                 commit_characters: Some(commit_characters),
                 tags,
                 deprecated: Some(flag.is_deprecated()),
@@ -66,7 +173,19 @@ fn complete_bazel_flag(
     let mut completion_items: Vec<CompletionItem> = Vec::<CompletionItem>::new();
     completion_items.extend(relevant_flags.clone().map(|flag| {
         let new_text = format!("--{}", flag.name);
-        create_completion_item(flag.name.clone(), new_text, flag, vec!["=".to_string()])
+        if flag.takes_value() && !flag.has_negative_flag() {
+            // Leave the cursor at the value of flags requiring one
+            let snippet = format!("{}={}", new_text, value_placeholder(flag));
+            let mut item =
+                create_completion_item(flag.name.clone(), new_text.clone(), flag, vec![]);
+            if let Some(CompletionTextEdit::Edit(edit)) = &mut item.text_edit {
+                edit.new_text = snippet;
+            }
+            item.insert_text_format = Some(InsertTextFormat::SNIPPET);
+            item
+        } else {
+            create_completion_item(flag.name.clone(), new_text, flag, vec!["=".to_string()])
+        }
     }));
 
     // ... and their negations
@@ -79,12 +198,330 @@ fn complete_bazel_flag(
                 create_completion_item(label, new_text, flag, vec![])
             }),
     );
+    completion_items
+}
+
+// The snippet placeholder for the value of a flag, e.g. `${1:N}` for `--jobs` or
+// `${1|fastbuild,dbg,opt|}` for `--compilation_mode`. The default value is the first choice.
+fn value_placeholder(flag: &FlagInfo) -> String {
+    if !flag.allowed_values.is_empty() {
+        let mut values = flag.allowed_values.iter().collect::<Vec<_>>();
+        values.sort_by_key(|v| flag.default_value.as_ref() != Some(*v));
+        let values = values.iter().map(|v| v.as_str()).collect::<Vec<_>>();
+        return format!("${{1|{}|}}", values.join(","));
+    }
+    let placeholder = match flag.value_type() {
+        "integer" | "resource" => "N",
+        "integer_list" => "N,N",
+        "duration" => "30s",
+        "path" => "path",
+        "repository_override" => "name=path",
+        _ => "value",
+    };
+    format!("${{1:{}}}", placeholder)
+}
+
+fn complete_bazel_flag(
+    flag_completions: &FlagCompletions,
+    config_index: &ConfigIndex,
+    settings: &Settings,
+    snippet_support: bool,
+    command: &str,
+    range: Range,
+) -> Vec<CompletionItem> {
+    let mut completion_items = flag_completions.get(
+        command,
+        range,
+        settings.sort_completions_by_category,
+        snippet_support,
+    );
+
+    // ... and the flag aliases defined in the workspace
+    if command != "startup" {
+        completion_items.extend(config_index.flag_aliases.iter().map(|(name, definitions)| {
+            let new_text = format!("--{}", name);
+            CompletionItem {
+                label: name.clone(),
+                detail: definitions
+                    .first()
+                    .map(|d| format!("Alias for {}", d.label)),
+                documentation: config_index
+                    .get_flag_alias_documentation(name)
+                    .map(|value| {
+                        Documentation::MarkupContent(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value,
+                        })
+                    }),
+                filter_text: Some(new_text.clone()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+                commit_characters: Some(vec!["=".to_string()]),
+                ..Default::default()
+            }
+        }));
+    }
 
     completion_items
 }
 
+fn complete_config_name(config_index: &ConfigIndex, range: Range) -> Vec<CompletionItem> {
+    config_index
+        .configs
+        .iter()
+        .map(|(name, entry)| CompletionItem {
+            label: name.clone(),
+            detail: Some(format!("{} flag(s)", entry.flag_count())),
+            documentation: config_index.get_config_documentation(name).map(|value| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                })
+            }),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: name.clone(),
+            })),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>()
+}
+
+// Config names after `command:`. Only configs which are already defined are offered, such
+// that lines extending an existing config don't introduce typos. Configs defined for the
+// same command are listed first.
+fn complete_config_definition(
+    config_index: &ConfigIndex,
+    command: &str,
+    range: Range,
+) -> Vec<CompletionItem> {
+    complete_config_name(config_index, range)
+        .into_iter()
+        .filter_map(|mut item| {
+            let definitions = &config_index.configs.get(&item.label)?.definitions;
+            if definitions.is_empty() {
+                return None;
+            }
+            let rank = if definitions.iter().any(|d| *d.command == *command) {
+                0
+            } else {
+                1
+            };
+            item.sort_text = Some(format!("{}/{}", rank, item.label));
+            Some(item)
+        })
+        .collect()
+}
+
+// Config names within `--config=` values. Only the configs with definitions for the line's
+// command, or one of the commands it inherits from, are offered, since Bazel ignores
+// the other sections of a config.
+fn complete_config_usage(
+    config_index: &ConfigIndex,
+    command: &str,
+    range: Range,
+) -> Vec<CompletionItem> {
+    let chain = match command {
+        "common" | "always" => None,
+        _ => Some(get_command_chain(command)),
+    };
+    complete_config_name(config_index, range)
+        .into_iter()
+        .filter_map(|mut item| {
+            let entry = config_index.configs.get(&item.label)?;
+            let relevant = entry
+                .definitions
+                .iter()
+                .filter(|d| {
+                    chain
+                        .as_ref()
+                        .map_or(true, |chain| chain.contains(&&*d.command))
+                })
+                .collect::<Vec<_>>();
+            if relevant.is_empty() {
+                return None;
+            }
+            let mut files = relevant
+                .iter()
+                .map(|d| display_file_name(&d.file))
+                .collect::<Vec<_>>();
+            files.dedup();
+            item.detail = Some(format!(
+                "{} flag(s), defined in {}",
+                entry.flag_count(),
+                files.join(", ")
+            ));
+            Some(item)
+        })
+        .collect()
+}
+
+// The file name of a file in the config index, e.g. `ci.bazelrc` for `file:///ws/ci.bazelrc`
+fn display_file_name(file: &str) -> String {
+    Url::parse(file)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .and_then(|path| Some(path.file_name()?.to_string_lossy().to_string()))
+        .unwrap_or_else(|| file.to_string())
+}
+
+// The values of boolean flags and of flags accepting one of a fixed set of values, e.g.
+// `--compilation_mode`. The default value is preselected and listed first.
+//
+// For boolean flags, `--flag=false` can also be rewritten to the more readable `--noflag`.
+// `flag_range` is the range of the whole flag, including its name.
+fn complete_flag_value(
+    flag: &FlagInfo,
+    invocation: &str,
+    range: Range,
+    flag_range: Range,
+) -> Vec<CompletionItem> {
+    let is_boolean = flag.has_negative_flag() && flag.allowed_values.is_empty();
+    let values = if is_boolean {
+        ["true", "false", "yes", "no"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    } else {
+        flag.allowed_values.clone()
+    };
+    let mut items = values
+        .iter()
+        .map(|value| {
+            let is_default = flag.default_value.as_ref() == Some(value);
+            CompletionItem {
+                label: value.clone(),
+                kind: Some(CompletionItemKind::ENUM_MEMBER),
+                label_details: is_default.then(|| CompletionItemLabelDetails {
+                    detail: None,
+                    description: Some("default".to_string()),
+                }),
+                sort_text: Some(format!("{}/{}", if is_default { 0 } else { 1 }, value)),
+                preselect: is_default.then_some(true),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: value.clone(),
+                })),
+                ..Default::default()
+            }
+        })
+        .collect::<Vec<_>>();
+    if is_boolean && invocation == format!("--{}", flag.name) {
+        let new_text = format!("--no{}", flag.name);
+        items.push(CompletionItem {
+            label: new_text.clone(),
+            kind: Some(CompletionItemKind::ENUM_MEMBER),
+            detail: Some(format!("Replace by {}", new_text)),
+            sort_text: Some(format!("2/{}", new_text)),
+            // Matched against the value typed so far, like the other values
+            filter_text: Some("false".to_string()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: flag_range,
+                new_text,
+            })),
+            ..Default::default()
+        });
+    }
+    items
+}
+
+// Completes file system paths, e.g. for `import` statements. `typed` is the path typed so
+// far. Its directory is resolved like import paths are (see `resolve_bazelrc_path`), and
+// the directory's entries accepted by `filter` are offered, directories first.
+fn complete_path(
+    file_path: &Path,
+    typed: &str,
+    range: Range,
+    filter: impl Fn(&Path) -> bool,
+) -> Vec<CompletionItem> {
+    let (dir, prefix) = match typed.rfind('/') {
+        Some(i) => typed.split_at(i + 1),
+        None => ("", typed),
+    };
+    let create_item = |label: String, kind: CompletionItemKind, rank: usize| CompletionItem {
+        label: label.clone(),
+        kind: Some(kind),
+        sort_text: Some(format!("{}/{}", rank, label)),
+        filter_text: Some(format!("{}{}", dir, label)),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range,
+            new_text: format!("{}{}", dir, label),
+        })),
+        ..Default::default()
+    };
+
+    let mut items = Vec::<CompletionItem>::new();
+    if dir.is_empty() && get_workspace_path(file_path).is_some() {
+        items.push(CompletionItem {
+            detail: Some("The workspace root".to_string()),
+            ..create_item("%workspace%/".to_string(), CompletionItemKind::FOLDER, 0)
+        });
+    }
+    let dir_path = resolve_bazelrc_path(file_path, if dir.is_empty() { "." } else { dir });
+    let Some(entries) = dir_path.and_then(|p| std::fs::read_dir(p).ok()) else {
+        return items;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Hidden files are only offered once the user typed the leading `.`
+        if name.starts_with('.') && !prefix.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            items.push(create_item(
+                format!("{}/", name),
+                CompletionItemKind::FOLDER,
+                1,
+            ));
+        } else if filter(&path) {
+            items.push(create_item(name, CompletionItemKind::FILE, 2));
+        }
+    }
+    items
+}
+
+// Completes the values of flags taking paths, e.g. `--disk_cache`. For
+// `--override_repository=name=path`, only the part after the name is a path.
+fn complete_path_value(
+    file_path: &Path,
+    value_type: &str,
+    value: &str,
+    range: Range,
+) -> Vec<CompletionItem> {
+    let (prefix, path) = match value_type {
+        "path" => ("", value),
+        "repository_override" => match value.find('=') {
+            Some(i) => value.split_at(i + 1),
+            None => return vec![],
+        },
+        _ => return vec![],
+    };
+    let mut items = complete_path(file_path, path, range, |_| true);
+    for item in &mut items {
+        item.filter_text = item.filter_text.take().map(|t| format!("{}{}", prefix, t));
+        if let Some(CompletionTextEdit::Edit(edit)) = &mut item.text_edit {
+            edit.new_text = format!("{}{}", prefix, edit.new_text);
+        }
+    }
+    items
+}
+
+// Drops the `:` in front of config names and the `=` in front of flag values
+fn strip_separator(rope: &Rope, span: &Span) -> Span {
+    match rope.get_char(span.start) {
+        Some(':') | Some('=') if span.start < span.end => span.start + 1..span.end,
+        _ => span.clone(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn get_completion_items(
     bazel_flags: &BazelFlags,
+    flag_completions: &FlagCompletions,
+    config_index: &ConfigIndex,
+    settings: &Settings,
+    snippet_support: bool,
+    file_path: Option<&Path>,
     rope: &Rope,
     index: &IndexedLines,
     pos: usize,
@@ -95,14 +532,44 @@ pub fn get_completion_items(
     let lookup_pos = if pos == 0 { 0 } else { pos - 1 };
     if let Some(entry) = index.find_symbol_at_position(lookup_pos) {
         let line = index.lines.get(entry.line_nr).unwrap();
+        // `:` is a trigger character for config names. Within flags, e.g. in `--//pkg:`,
+        // it does not start anything worth completing.
+        if rope.get_char(lookup_pos) == Some(':') && entry.kind != IndexEntryKind::Config {
+            return vec![];
+        }
+        // Similarly, `/` is a trigger character for paths within values
+        if rope.get_char(lookup_pos) == Some('/')
+            && !matches!(entry.kind, IndexEntryKind::FlagValue(_))
+        {
+            return vec![];
+        }
         // Complete the item which the user is currently typing
         match entry.kind {
             IndexEntryKind::Command => complete_bazel_command(bazel_flags),
-            IndexEntryKind::Config => vec![],
-            IndexEntryKind::FlagName(_) => {
+            IndexEntryKind::Config => {
+                if line
+                    .command
+                    .as_ref()
+                    .is_some_and(|c| !supports_configs(&c.0))
+                {
+                    // Configs are not supported on `startup` lines and imports
+                    return vec![];
+                }
+                complete_config_definition(
+                    config_index,
+                    line.command.as_ref().map_or("", |c| c.0.as_str()),
+                    range_to_lsp(rope, &strip_separator(rope, &entry.span)).unwrap(),
+                )
+            }
+            IndexEntryKind::FlagName(flag_nr) => {
                 if let Some(cmd) = &line.command {
+                    // Don't insert a placeholder in front of an existing value
+                    let has_value = line.flags.get(flag_nr).is_some_and(|f| f.value.is_some());
                     complete_bazel_flag(
-                        bazel_flags,
+                        flag_completions,
+                        config_index,
+                        settings,
+                        snippet_support && !has_value,
                         &cmd.0,
                         range_to_lsp(rope, &entry.span).unwrap(),
                     )
@@ -113,22 +580,73 @@ pub fn get_completion_items(
                     vec![]
                 }
             }
-            IndexEntryKind::FlagValue(_) => vec![],
+            IndexEntryKind::FlagValue(flag_nr) => {
+                let flag_name = line.flags[flag_nr].name.as_ref();
+                let range = range_to_lsp(rope, &strip_separator(rope, &entry.span)).unwrap();
+                if line.import.is_some() {
+                    let (Some(file_path), Some(value)) = (file_path, &line.flags[flag_nr].value)
+                    else {
+                        return vec![];
+                    };
+                    let span = name_span(rope, &value.1, &value.0);
+                    complete_path(
+                        file_path,
+                        &value.0,
+                        range_to_lsp(rope, &span).unwrap(),
+                        is_bazelrc_file,
+                    )
+                } else if flag_name.is_some_and(|n| is_config_flag(&n.0, bazel_flags)) {
+                    complete_config_usage(
+                        config_index,
+                        line.command.as_ref().map_or("", |c| c.0.as_str()),
+                        range,
+                    )
+                } else if let Some((name, info)) =
+                    flag_name.and_then(|n| Some((n, bazel_flags.get_by_invocation(&n.0)?)))
+                {
+                    let flag_range = range_to_lsp(rope, &(name.1.start..entry.span.end)).unwrap();
+                    match (file_path, &line.flags[flag_nr].value) {
+                        (Some(file_path), Some(value))
+                            if ["path", "repository_override"].contains(&info.value_type()) =>
+                        {
+                            let span = name_span(rope, &value.1, &value.0);
+                            complete_path_value(
+                                file_path,
+                                info.value_type(),
+                                &value.0,
+                                range_to_lsp(rope, &span).unwrap(),
+                            )
+                        }
+                        _ => complete_flag_value(info, &name.0, range, flag_range),
+                    }
+                } else {
+                    vec![]
+                }
+            }
         }
     } else if let Some(line) = index.find_line_at_position(lookup_pos) {
         // Not within any item, but on an existing line.
-        if let Some(cmd) = &line.command {
+        let empty_range = range_to_lsp(
+            rope,
+            &Span {
+                start: pos,
+                end: pos,
+            },
+        )
+        .unwrap();
+        if line.import.is_some() {
+            match file_path {
+                Some(file_path) => complete_path(file_path, "", empty_range, is_bazelrc_file),
+                None => vec![],
+            }
+        } else if let Some(cmd) = &line.command {
             complete_bazel_flag(
-                bazel_flags,
+                flag_completions,
+                config_index,
+                settings,
+                snippet_support,
                 &cmd.0,
-                range_to_lsp(
-                    rope,
-                    &Span {
-                        start: pos,
-                        end: pos,
-                    },
-                )
-                .unwrap(),
+                empty_range,
             )
         } else {
             vec![]
@@ -141,10 +659,10 @@ pub fn get_completion_items(
     }
 }
 
-fn get_flag_documentation(flag: &crate::bazel_flags_proto::FlagInfo) -> Option<Documentation> {
+fn get_flag_documentation(bazel_flags: &BazelFlags, flag: &FlagInfo) -> Option<Documentation> {
     let mc = MarkupContent {
         kind: MarkupKind::Markdown,
-        value: flag.get_documentation_markdown(),
+        value: bazel_flags.get_documentation_markdown(flag),
     };
     Some(Documentation::MarkupContent(mc))
 }
@@ -157,3 +675,435 @@ fn get_command_documentation(command: &str) -> Option<Documentation> {
         })
     })
 }
+
+#[test]
+fn test_flag_completions_by_command() {
+    let flag = |name: &str, commands: &[&str]| FlagInfo {
+        name: name.to_string(),
+        commands: commands.iter().map(|c| c.to_string()).collect(),
+        ..Default::default()
+    };
+    let bazel_flags = BazelFlags::from_flags(vec![
+        flag("keep_going", &["build"]),
+        flag("test_output", &["test"]),
+        flag("async", &["clean"]),
+        flag("output_base", &["startup"]),
+    ]);
+    let completions = FlagCompletions::new(&bazel_flags);
+    let labels = |command: &str| {
+        completions
+            .get(command, Range::default(), false, false)
+            .into_iter()
+            .map(|item| item.label)
+            .collect::<Vec<_>>()
+    };
+    // Flags are inherited from the parent commands
+    assert_eq!(labels("build"), vec!["keep_going"]);
+    assert_eq!(labels("test"), vec!["keep_going", "test_output"]);
+    assert_eq!(labels("clean"), vec!["async"]);
+    assert_eq!(labels("startup"), vec!["output_base"]);
+    assert_eq!(labels("common"), vec!["keep_going", "test_output", "async"]);
+    assert_eq!(labels("unknown"), Vec::<String>::new());
+}
+
+#[test]
+fn test_negated_flag_completions() {
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let items = FlagCompletions::new(&bazel_flags).get("build", Range::default(), false, false);
+    let new_text = |label: &str| {
+        items
+            .iter()
+            .find(|i| i.label == label)
+            .and_then(|i| match &i.text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => Some(edit.new_text.clone()),
+                _ => None,
+            })
+    };
+    // Boolean and tri-state flags can be negated
+    assert_eq!(new_text("nokeep_going"), Some("--nokeep_going".to_string()));
+    assert_eq!(
+        new_text("nocache_test_results"),
+        Some("--nocache_test_results".to_string())
+    );
+    // Flags taking a value can't
+    assert_eq!(new_text("nojobs"), None);
+    // Negations are not followed by a value
+    let negation = items.iter().find(|i| i.label == "nokeep_going").unwrap();
+    assert_eq!(negation.commit_characters, Some(vec![]));
+    assert_eq!(negation.filter_text.as_deref(), Some("--nokeep_going"));
+}
+
+#[test]
+fn test_complete_bazel_command() {
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let items = complete_bazel_command(&bazel_flags);
+    let labels = items.iter().map(|i| i.label.as_str()).collect::<Vec<_>>();
+    assert!(labels.windows(2).all(|w| w[0] < w[1]));
+    for command in [
+        "build",
+        "test",
+        "common",
+        "always",
+        "startup",
+        "import",
+        "try-import",
+    ] {
+        assert!(labels.contains(&command), "{command}");
+    }
+
+    let item = |label: &str| items.iter().find(|i| i.label == label).unwrap();
+    let description = |label: &str| {
+        item(label)
+            .label_details
+            .as_ref()
+            .and_then(|d| d.description.clone())
+    };
+    assert_eq!(
+        description("build"),
+        Some("Builds the specified targets".to_string())
+    );
+    assert_eq!(
+        description("import"),
+        Some("Imports the given file".to_string())
+    );
+    // Configs are not supported for `startup` and imports
+    assert_eq!(item("build").commit_characters, Some(vec![":".to_string()]));
+    assert_eq!(item("startup").commit_characters, None);
+    assert_eq!(item("try-import").commit_characters, None);
+}
+
+#[cfg(test)]
+fn complete_with_configs(source: &str, pos: usize, other_file: &str) -> Vec<CompletionItem> {
+    complete_file(source, pos, other_file, None)
+}
+
+#[cfg(test)]
+fn complete_file(
+    source: &str,
+    pos: usize,
+    other_file: &str,
+    file_path: Option<&Path>,
+) -> Vec<CompletionItem> {
+    use crate::bazel_flags::{combine_key_value_flags, load_bazel_flags};
+    use crate::parser::parse_from_str;
+
+    let bazel_flags = load_bazel_flags();
+    let parse = |src: &str| {
+        let mut lines = parse_from_str(src).lines;
+        combine_key_value_flags(&mut lines, &bazel_flags);
+        lines
+    };
+    let mut config_index = ConfigIndex::default();
+    let rope = Rope::from_str(source);
+    let lines = parse(source);
+    config_index.add_file(
+        "file:///ws/ci.bazelrc",
+        &Rope::from_str(other_file),
+        &parse(other_file),
+        &bazel_flags,
+    );
+    config_index.add_file("file:///ws/.bazelrc", &rope, &lines, &bazel_flags);
+    let mut items = get_completion_items(
+        &bazel_flags,
+        &FlagCompletions::new(&bazel_flags),
+        &config_index,
+        &Settings::default(),
+        true,
+        file_path,
+        &rope,
+        &IndexedLines::from_lines(lines),
+        pos,
+    );
+    items.sort_by_key(|i| i.sort_text.clone().unwrap_or_else(|| i.label.clone()));
+    items
+}
+
+#[test]
+fn test_complete_config_definition() {
+    let other_file = "build:opt -c opt\ntest:ci --config=undefined";
+    let labels = |source: &str, pos: usize| {
+        complete_with_configs(source, pos, other_file)
+            .into_iter()
+            .map(|i| i.label)
+            .collect::<Vec<_>>()
+    };
+    // Only defined configs are offered, the ones defined for the same command first
+    assert_eq!(labels("build:", 6), vec!["opt", "ci"]);
+    assert_eq!(labels("test:", 5), vec!["ci", "opt"]);
+    // The name typed so far is replaced
+    let items = complete_with_configs("build:o", 7, other_file);
+    assert_eq!(
+        items[0].text_edit,
+        Some(CompletionTextEdit::Edit(TextEdit {
+            range: Range::new(
+                tower_lsp::lsp_types::Position::new(0, 6),
+                tower_lsp::lsp_types::Position::new(0, 7)
+            ),
+            new_text: "o".to_string()
+        }))
+    );
+    // `:` within flags doesn't trigger any completions
+    assert_eq!(labels("build --//my:", 13), Vec::<String>::new());
+}
+
+#[test]
+fn test_complete_config_usage() {
+    let other_file = "build:opt -c opt\ntest:ci --config=undefined\nrun:debug -c dbg";
+    let items = |source: &str, pos: usize| {
+        complete_with_configs(source, pos, other_file)
+            .into_iter()
+            .map(|i| (i.label, i.detail.unwrap_or_default()))
+            .collect::<Vec<_>>()
+    };
+    // Configs are scoped to the command and the commands it inherits from
+    assert_eq!(
+        items("build:local -k\nbuild --config=", 30),
+        vec![
+            (
+                "local".to_string(),
+                "1 flag(s), defined in .bazelrc".to_string()
+            ),
+            (
+                "opt".to_string(),
+                "1 flag(s), defined in ci.bazelrc".to_string()
+            )
+        ]
+    );
+    let labels = |source: &str, pos: usize| {
+        items(source, pos)
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(labels("test --config=", 14), vec!["ci", "opt"]);
+    assert_eq!(labels("common --config=o", 17), vec!["ci", "debug", "opt"]);
+}
+
+#[test]
+fn test_complete_flag_value() {
+    let items = |source: &str, pos: usize| {
+        complete_with_configs(source, pos, "")
+            .into_iter()
+            .map(|i| (i.label, i.preselect.unwrap_or_default()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        items("build --compilation_mode=", 25),
+        vec![
+            ("fastbuild".to_string(), true),
+            ("dbg".to_string(), false),
+            ("opt".to_string(), false)
+        ]
+    );
+    assert_eq!(
+        items("test --test_output=e", 20),
+        vec![
+            ("summary".to_string(), true),
+            ("all".to_string(), false),
+            ("errors".to_string(), false),
+            ("streamed".to_string(), false)
+        ]
+    );
+    // Flags with arbitrary values don't offer any completions
+    assert_eq!(items("build --copt=", 13), vec![]);
+}
+
+#[test]
+fn test_complete_boolean_value() {
+    let items = complete_with_configs("build --keep_going=", 19, "");
+    assert_eq!(
+        items.iter().map(|i| i.label.as_str()).collect::<Vec<_>>(),
+        vec!["false", "no", "true", "yes", "--nokeep_going"]
+    );
+    // The default is preselected
+    assert_eq!(items[0].preselect, Some(true));
+    // The negation replaces the whole flag
+    assert_eq!(
+        items[4].text_edit,
+        Some(CompletionTextEdit::Edit(TextEdit {
+            range: Range::new(
+                tower_lsp::lsp_types::Position::new(0, 6),
+                tower_lsp::lsp_types::Position::new(0, 19)
+            ),
+            new_text: "--nokeep_going".to_string()
+        }))
+    );
+
+    // Negated flags are not negated again
+    let labels = complete_with_configs("build --nokeep_going=", 21, "")
+        .into_iter()
+        .map(|i| i.label)
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec!["false", "no", "true", "yes"]);
+}
+
+#[test]
+fn test_flag_snippets() {
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let completions = FlagCompletions::new(&bazel_flags);
+    let new_text = |snippet_support: bool, label: &str| {
+        completions
+            .get("build", Range::default(), false, snippet_support)
+            .into_iter()
+            .find(|i| i.label == label)
+            .and_then(|i| match i.text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => Some((edit.new_text, i.insert_text_format)),
+                _ => None,
+            })
+            .unwrap()
+    };
+    let snippet = |text: &str| (text.to_string(), Some(InsertTextFormat::SNIPPET));
+    assert_eq!(new_text(true, "jobs"), snippet("--jobs=${1:N}"));
+    assert_eq!(
+        new_text(true, "compilation_mode"),
+        snippet("--compilation_mode=${1|fastbuild,dbg,opt|}")
+    );
+    assert_eq!(
+        new_text(true, "remote_timeout"),
+        snippet("--remote_timeout=${1:30s}")
+    );
+    assert_eq!(new_text(true, "copt"), snippet("--copt=${1:value}"));
+    // Flags with optional values are inserted as plain text
+    assert_eq!(
+        new_text(true, "keep_going"),
+        ("--keep_going".to_string(), None)
+    );
+    // Clients without snippet support get plain text
+    assert_eq!(new_text(false, "jobs"), ("--jobs".to_string(), None));
+
+    // No placeholder is inserted when editing the name of a flag which already has a value
+    let edit = |source: &str, pos: usize| {
+        complete_with_configs(source, pos, "")
+            .into_iter()
+            .find(|i| i.label == "jobs")
+            .map(|i| i.insert_text_format)
+    };
+    assert_eq!(
+        edit("build --jo", 10),
+        Some(Some(InsertTextFormat::SNIPPET))
+    );
+    assert_eq!(edit("build --jo=8", 10), Some(None));
+}
+
+#[test]
+fn test_complete_import_path() {
+    let ws = crate::import_graph::create_test_workspace(
+        "complete-imports",
+        &[
+            (".bazelrc", ""),
+            ("user.bazelrc", ""),
+            ("BUILD", ""),
+            ("tools/ci.bazelrc", ""),
+            ("tools/remote.bazelrc", ""),
+            ("tools/bazel/.keep", ""),
+            (".hidden/x.bazelrc", ""),
+        ],
+    );
+    let rc_path = ws.join(".bazelrc");
+    let complete = |source: &str| {
+        complete_file(source, source.chars().count(), "", Some(&rc_path))
+            .into_iter()
+            .map(|i| {
+                let Some(CompletionTextEdit::Edit(edit)) = i.text_edit else {
+                    panic!("missing edit");
+                };
+                (edit.new_text, edit.range.start.character)
+            })
+            .collect::<Vec<_>>()
+    };
+    let texts = |source: &str| {
+        complete(source)
+            .into_iter()
+            .map(|(text, _)| text)
+            .collect::<Vec<_>>()
+    };
+    // Directories go first, only bazelrc files are offered
+    assert_eq!(
+        texts("import "),
+        vec!["%workspace%/", "tools/", "user.bazelrc"]
+    );
+    assert_eq!(
+        texts("try-import u"),
+        vec!["%workspace%/", "tools/", "user.bazelrc"]
+    );
+    assert_eq!(
+        complete("import %workspace%/tools/"),
+        vec![
+            ("%workspace%/tools/bazel/".to_string(), 7),
+            ("%workspace%/tools/ci.bazelrc".to_string(), 7),
+            ("%workspace%/tools/remote.bazelrc".to_string(), 7)
+        ]
+    );
+    // Quotes are kept
+    assert_eq!(
+        complete("import \"tools/c\""),
+        vec![
+            ("tools/bazel/".to_string(), 8),
+            ("tools/ci.bazelrc".to_string(), 8),
+            ("tools/remote.bazelrc".to_string(), 8)
+        ]
+    );
+    // Hidden directories are only offered after typing the `.`
+    assert_eq!(
+        texts("import ."),
+        vec![
+            "%workspace%/",
+            ".hidden/",
+            "tools/",
+            ".bazelrc",
+            "user.bazelrc"
+        ]
+    );
+}
+
+#[test]
+fn test_complete_path_value() {
+    let ws = crate::import_graph::create_test_workspace(
+        "complete-path-values",
+        &[
+            (".bazelrc", ""),
+            ("cache/.keep", ""),
+            ("third_party/rules_foo/BUILD", ""),
+            ("tools/BUILD", ""),
+        ],
+    );
+    let rc_path = ws.join(".bazelrc");
+    let complete = |source: &str| {
+        complete_file(source, source.chars().count(), "", Some(&rc_path))
+            .into_iter()
+            .map(|i| {
+                let Some(CompletionTextEdit::Edit(edit)) = i.text_edit else {
+                    panic!("missing edit");
+                };
+                edit.new_text
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        complete("build --disk_cache=c"),
+        vec![
+            "%workspace%/",
+            "cache/",
+            "third_party/",
+            "tools/",
+            "MODULE.bazel"
+        ]
+    );
+    // Files are offered, too
+    assert_eq!(complete("build --disk_cache=tools/"), vec!["tools/BUILD"]);
+    assert_eq!(
+        complete("startup --output_base=\"%workspace%/third_party/\""),
+        vec!["%workspace%/third_party/rules_foo/"]
+    );
+    // Only the path after the repository name is completed
+    assert_eq!(
+        complete("build --override_repository=rules_foo=third_party/"),
+        vec!["rules_foo=third_party/rules_foo/"]
+    );
+    assert_eq!(
+        complete("build --override_repository=rules_foo"),
+        Vec::<String>::new()
+    );
+    // Other flags are not completed with paths
+    assert_eq!(complete("build --copt=t"), Vec::<String>::new());
+}
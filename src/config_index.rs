@@ -0,0 +1,622 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use ropey::Rope;
+use tower_lsp::lsp_types::{Range, Url};
+
+use crate::{
+    bazel_flags::BazelFlags,
+    cst::source_map,
+    file_utils::{get_build_file, get_workspace_path},
+    lsp_utils::range_to_lsp,
+    parser::{Flag, Line},
+    semantic_diff::{normalize_flag, NormalizedFlag},
+    tokenizer::Span,
+};
+
+// The configs which are applied automatically with `--enable_platform_specific_config`,
+// together with the name of the host platform they are applied on
+const PLATFORM_CONFIGS: [(&str, &str); 5] = [
+    ("linux", "Linux"),
+    ("macos", "macOS"),
+    ("windows", "Windows"),
+    ("freebsd", "FreeBSD"),
+    ("openbsd", "OpenBSD"),
+];
+
+// The platform on which a platform-specific config is applied, if it is a platform-specific config
+pub fn get_platform_name(config: &str) -> Option<&'static str> {
+    PLATFORM_CONFIGS
+        .iter()
+        .find(|(c, _)| *c == config)
+        .map(|(_, platform)| *platform)
+}
+
+// The platform-specific config of the host running the language server
+pub fn get_host_platform_config() -> Option<&'static str> {
+    // The config names match Rust's names for the operating systems
+    PLATFORM_CONFIGS
+        .iter()
+        .find(|(c, _)| *c == std::env::consts::OS)
+        .map(|(c, _)| *c)
+}
+
+// A `command:config` line, defining (a part of) a config
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigDefinition {
+    pub file: Arc<str>,
+    pub command: Arc<str>,
+    // The span of the config name
+    pub span: Span,
+    pub range: Range,
+    pub flag_count: usize,
+}
+
+// A `--config=name` flag, referencing a config
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigUsage {
+    pub file: Arc<str>,
+    pub command: Option<Arc<str>>,
+    // The config which this `--config` flag is part of, if any
+    pub within_config: Option<Arc<str>>,
+    // The span of the referenced config name
+    pub span: Span,
+    pub range: Range,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigEntry {
+    pub definitions: Vec<ConfigDefinition>,
+    pub usages: Vec<ConfigUsage>,
+}
+
+impl ConfigEntry {
+    pub fn flag_count(&self) -> usize {
+        self.definitions.iter().map(|d| d.flag_count).sum()
+    }
+}
+
+// A `--flag_alias=name=//label` flag, defining `--name` as a short-hand for `--//label`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlagAliasDefinition {
+    pub file: Arc<str>,
+    pub label: String,
+    // The span of the alias name
+    pub span: Span,
+    pub range: Range,
+}
+
+// Index of all configs and flag aliases defined and referenced across multiple bazelrc files
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigIndex {
+    pub configs: BTreeMap<String, ConfigEntry>,
+    pub flag_aliases: BTreeMap<String, Vec<FlagAliasDefinition>>,
+    files: BTreeSet<String>,
+    // The files setting `--enable_platform_specific_config`
+    platform_specific_config_files: BTreeSet<String>,
+    // Interned file names, commands and config names. Definitions and usages share
+    // these strings instead of each owning a copy.
+    strings: BTreeSet<Arc<str>>,
+}
+
+pub fn is_config_flag(name: &str, bazel_flags: &BazelFlags) -> bool {
+    bazel_flags
+        .get_by_invocation(name)
+        .is_some_and(|f| f.name == "config")
+}
+
+// If the flag is a `--flag_alias=name=//label` flag, returns the alias name and the label
+pub fn parse_flag_alias<'a>(
+    flag: &'a Flag,
+    bazel_flags: &BazelFlags,
+) -> Option<(&'a str, &'a str)> {
+    let name = flag.name.as_ref()?;
+    if bazel_flags.get_by_invocation(&name.0)?.name != "flag_alias" {
+        return None;
+    }
+    let (alias, label) = flag.value.as_ref()?.0.split_once('=')?;
+    (!alias.is_empty()).then_some((alias, label))
+}
+
+// Collects the names of all flag aliases defined in the given lines
+pub fn collect_flag_aliases<'a>(
+    lines: impl IntoIterator<Item = &'a Line>,
+    bazel_flags: &BazelFlags,
+) -> BTreeSet<String> {
+    lines
+        .into_iter()
+        .flat_map(|l| &l.flags)
+        .filter_map(|f| parse_flag_alias(f, bazel_flags))
+        .map(|(alias, _)| alias.to_string())
+        .collect()
+}
+
+// Checks if a flag invocation (e.g., `--my_alias`, `--nomy_alias` or `--my_alias=`)
+// refers to a flag alias, and returns the alias name
+pub fn resolve_flag_alias(invocation: &str, is_alias: impl Fn(&str) -> bool) -> Option<&str> {
+    let name = invocation.strip_prefix("--")?;
+    let name = name.strip_suffix('=').unwrap_or(name);
+    if is_alias(name) {
+        return Some(name);
+    }
+    name.strip_prefix("no").filter(|n| is_alias(n))
+}
+
+// The URL of the BUILD file defining a label, if the label is within the
+// workspace of the given bazelrc file
+fn get_label_url(file: &str, label: &str) -> Option<Url> {
+    let file_path = Url::parse(file).ok()?.to_file_path().ok()?;
+    let workspace = get_workspace_path(&file_path)?;
+    Url::from_file_path(get_build_file(&workspace, label)?).ok()
+}
+
+// Narrows down the span of a token to the contained name, i.e. removes
+// the `:` in front of config definitions and the `=` or quotes around flag values
+pub fn name_span(rope: &Rope, span: &Span, name: &str) -> Span {
+    let Some(text) = rope.get_slice(span.clone()) else {
+        return span.clone();
+    };
+    // Quoted or escaped names are mapped back to the characters they originate from
+    let map = source_map(&text.chars().collect::<Vec<_>>(), span.start);
+    let value = map.iter().map(|(c, _)| *c).collect::<String>();
+    let len = name.chars().count();
+    match value.find(name) {
+        Some(idx) if len > 0 => {
+            let first = value[..idx].chars().count();
+            map[first].1.start..map[first + len - 1].1.end
+        }
+        _ => span.clone(),
+    }
+}
+
+impl ConfigIndex {
+    // Whether any of the indexed files sets `--enable_platform_specific_config`
+    pub fn platform_specific_configs_enabled(&self) -> bool {
+        !self.platform_specific_config_files.is_empty()
+    }
+
+    // Looks up the definitions of a flag alias by its invocation, e.g. `--my_alias`
+    pub fn get_flag_alias(&self, invocation: &str) -> Option<(&str, &[FlagAliasDefinition])> {
+        let name = resolve_flag_alias(invocation, |n| self.flag_aliases.contains_key(n))?;
+        let (name, definitions) = self.flag_aliases.get_key_value(name)?;
+        Some((name.as_str(), definitions.as_slice()))
+    }
+
+    fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(interned) = self.strings.get(s) {
+            return interned.clone();
+        }
+        let interned = Arc::<str>::from(s);
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    pub fn contains_file(&self, file: &str) -> bool {
+        self.files.contains(file)
+    }
+
+    // Replaces all config definitions and usages from the given file
+    pub fn update_file(
+        &mut self,
+        file: &str,
+        rope: &Rope,
+        lines: &[Line],
+        bazel_flags: &BazelFlags,
+    ) {
+        self.remove_file(file);
+        self.add_file(file, rope, lines, bazel_flags);
+    }
+
+    // Removes all config definitions and usages from the given file
+    pub fn remove_file(&mut self, file: &str) {
+        if !self.files.remove(file) {
+            return;
+        }
+        self.platform_specific_config_files.remove(file);
+        for entry in self.configs.values_mut() {
+            entry.definitions.retain(|d| &*d.file != file);
+            entry.usages.retain(|u| &*u.file != file);
+        }
+        self.configs
+            .retain(|_, e| !e.definitions.is_empty() || !e.usages.is_empty());
+        for definitions in self.flag_aliases.values_mut() {
+            definitions.retain(|d| &*d.file != file);
+        }
+        self.flag_aliases.retain(|_, d| !d.is_empty());
+        // Drop the strings which are no longer referenced by any definition or usage
+        self.strings.retain(|s| Arc::strong_count(s) > 1);
+    }
+
+    // Adds the config definitions and usages from the given file to the index
+    pub fn add_file(&mut self, file: &str, rope: &Rope, lines: &[Line], bazel_flags: &BazelFlags) {
+        self.files.insert(file.to_string());
+        let file = self.intern(file);
+        for line in lines {
+            let command = line.command.as_ref().map(|c| self.intern(&c.0));
+            let config = line.config.as_ref().filter(|c| !c.0.is_empty());
+            let within_config = config.map(|c| self.intern(&c.0));
+
+            // Config definitions
+            if let (Some(command), Some(config)) = (&command, config) {
+                let span = name_span(rope, &config.1, &config.0);
+                self.configs
+                    .entry(config.0.clone())
+                    .or_default()
+                    .definitions
+                    .push(ConfigDefinition {
+                        file: file.clone(),
+                        command: command.clone(),
+                        range: range_to_lsp(rope, &span).unwrap_or_default(),
+                        span,
+                        flag_count: line.flags.len(),
+                    });
+            }
+
+            // Config usages
+            for flag in &line.flags {
+                let enables_platform_configs = matches!(
+                    normalize_flag(flag, bazel_flags),
+                    NormalizedFlag::SingleValued { name, value }
+                        if name == "--enable_platform_specific_config" && value.as_deref() == Some("true")
+                );
+                if enables_platform_configs {
+                    self.platform_specific_config_files.insert(file.to_string());
+                }
+                if let Some((alias, label)) = parse_flag_alias(flag, bazel_flags) {
+                    let span = name_span(rope, &flag.value.as_ref().unwrap().1, alias);
+                    self.flag_aliases
+                        .entry(alias.to_string())
+                        .or_default()
+                        .push(FlagAliasDefinition {
+                            file: file.clone(),
+                            label: label.to_string(),
+                            range: range_to_lsp(rope, &span).unwrap_or_default(),
+                            span,
+                        });
+                }
+                let Some(name) = &flag.name else {
+                    continue;
+                };
+                let Some(value) = flag.value.as_ref().filter(|v| !v.0.is_empty()) else {
+                    continue;
+                };
+                if !is_config_flag(&name.0, bazel_flags) {
+                    continue;
+                }
+                let span = name_span(rope, &value.1, &value.0);
+                self.configs
+                    .entry(value.0.clone())
+                    .or_default()
+                    .usages
+                    .push(ConfigUsage {
+                        file: file.clone(),
+                        command: command.clone(),
+                        within_config: within_config.clone(),
+                        range: range_to_lsp(rope, &span).unwrap_or_default(),
+                        span,
+                    });
+            }
+        }
+    }
+
+    // Finds the config defined or referenced at the given position of a file
+    pub fn find_config_at(&self, file: &str, pos: usize) -> Option<&str> {
+        self.configs
+            .iter()
+            .find(|(_, e)| {
+                let mut spans = e
+                    .definitions
+                    .iter()
+                    .filter(|d| &*d.file == file)
+                    .map(|d| &d.span)
+                    .chain(
+                        e.usages
+                            .iter()
+                            .filter(|u| &*u.file == file)
+                            .map(|u| &u.span),
+                    );
+                spans.any(|s| s.start <= pos && pos <= s.end)
+            })
+            .map(|(name, _)| name.as_str())
+    }
+
+    // Markdown documentation for a config, listing all its definitions
+    pub fn get_config_documentation(&self, name: &str) -> Option<String> {
+        let entry = self.configs.get(name)?;
+        let mut docs = format!("Config `{}`\n\n", name);
+        if entry.definitions.is_empty() {
+            docs += "This config is not defined in any of the known bazelrc files.\n";
+        }
+        for d in &entry.definitions {
+            let file_name = d.file.rsplit('/').next().unwrap_or(&d.file);
+            docs += &format!(
+                "* `{}:{}` in `{}`, line {}: {} flag(s)\n",
+                d.command,
+                name,
+                file_name,
+                d.range.start.line + 1,
+                d.flag_count
+            );
+        }
+        docs += &format!("\nReferenced {} time(s)", entry.usages.len());
+        if let Some(platform) = get_platform_name(name) {
+            docs += &format!(
+                "\n\nApplied automatically on {} hosts if `--enable_platform_specific_config` is set",
+                platform
+            );
+            if !self.platform_specific_configs_enabled() {
+                docs += " (which is not set in any of the known bazelrc files)";
+            }
+        }
+        Some(docs)
+    }
+
+    pub fn get_flag_alias_documentation(&self, name: &str) -> Option<String> {
+        let definitions = self.flag_aliases.get(name)?;
+        let mut docs = format!("Flag alias `--{}`\n\n", name);
+        for d in definitions {
+            let file_name = d.file.rsplit('/').next().unwrap_or(&d.file);
+            let label = match get_label_url(&d.file, &d.label) {
+                Some(url) => format!("[`{}`]({})", d.label, url),
+                None => format!("`{}`", d.label),
+            };
+            docs += &format!(
+                "* alias for {} in `{}`, line {}\n",
+                label,
+                file_name,
+                d.range.start.line + 1
+            );
+        }
+        Some(docs)
+    }
+
+    // Configs which are defined, but never referenced through `--config`.
+    // Platform-specific configs are not considered unused, since Bazel applies them automatically.
+    pub fn unused_configs(&self) -> impl Iterator<Item = &str> {
+        self.configs
+            .iter()
+            .filter(|(_, e)| !e.definitions.is_empty() && e.usages.is_empty())
+            .filter(|(name, _)| get_platform_name(name).is_none())
+            .map(|(name, _)| name.as_str())
+    }
+
+    // For each config, the configs it references through `--config`
+    fn config_graph(&self) -> BTreeMap<&str, BTreeSet<&str>> {
+        let mut graph = BTreeMap::<&str, BTreeSet<&str>>::new();
+        for (name, entry) in &self.configs {
+            for usage in &entry.usages {
+                if let Some(within_config) = &usage.within_config {
+                    graph
+                        .entry(&**within_config)
+                        .or_default()
+                        .insert(name.as_str());
+                }
+            }
+        }
+        graph
+    }
+
+    // Finds cyclic config chains, e.g. `a` using `--config=b` and `b` using `--config=a`.
+    //
+    // Every cycle is reported once, starting at its alphabetically first config name,
+    // and with the first config repeated at the end, e.g. `["a", "b", "a"]`.
+    pub fn find_cycles(&self) -> Vec<Vec<String>> {
+        fn visit<'a>(
+            node: &'a str,
+            graph: &BTreeMap<&'a str, BTreeSet<&'a str>>,
+            path: &mut Vec<&'a str>,
+            done: &mut BTreeSet<&'a str>,
+            cycles: &mut BTreeSet<Vec<String>>,
+        ) {
+            if let Some(pos) = path.iter().position(|n| *n == node) {
+                // Normalize the cycle, such that it starts at the smallest config name
+                let cycle = &path[pos..];
+                let start = (0..cycle.len()).min_by_key(|i| cycle[*i]).unwrap();
+                let mut normalized = cycle[start..]
+                    .iter()
+                    .chain(cycle[..start].iter())
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>();
+                normalized.push(normalized[0].clone());
+                cycles.insert(normalized);
+                return;
+            }
+            if done.contains(node) {
+                return;
+            }
+            path.push(node);
+            for next in graph.get(node).into_iter().flatten() {
+                visit(next, graph, path, done, cycles);
+            }
+            path.pop();
+            done.insert(node);
+        }
+
+        let graph = self.config_graph();
+        let mut cycles = BTreeSet::<Vec<String>>::new();
+        let mut done = BTreeSet::<&str>::new();
+        for node in graph.keys() {
+            visit(node, &graph, &mut Vec::new(), &mut done, &mut cycles);
+        }
+        cycles.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+fn index_from_files(files: &[(&str, &str)]) -> ConfigIndex {
+    use crate::bazel_flags::{combine_key_value_flags, load_bazel_flags};
+    use crate::parser::parse_from_str;
+
+    let bazel_flags = load_bazel_flags();
+    let mut index = ConfigIndex::default();
+    for (name, contents) in files {
+        let mut lines = parse_from_str(contents).lines;
+        combine_key_value_flags(&mut lines, &bazel_flags);
+        index.add_file(name, &Rope::from_str(contents), &lines, &bazel_flags);
+    }
+    index
+}
+
+#[test]
+fn test_config_index() {
+    let index = index_from_files(&[
+        (
+            ".bazelrc",
+            "build:opt -c opt --strip=always\ntest:opt --test_output=errors\nbuild --config opt",
+        ),
+        ("ci.bazelrc", "build:ci --config=opt\nbuild:unused -k"),
+    ]);
+    assert_eq!(
+        index.configs.keys().collect::<Vec<_>>(),
+        vec!["ci", "opt", "unused"]
+    );
+
+    let opt = &index.configs["opt"];
+    assert_eq!(opt.flag_count(), 3);
+    assert_eq!(
+        opt.definitions
+            .iter()
+            .map(|d| (&*d.file, &*d.command, d.range.start.line))
+            .collect::<Vec<_>>(),
+        vec![(".bazelrc", "build", 0), (".bazelrc", "test", 1)]
+    );
+    assert_eq!(
+        opt.usages
+            .iter()
+            .map(|u| (&*u.file, u.within_config.as_deref(), u.span.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (".bazelrc", None, 77..80),
+            ("ci.bazelrc", Some("ci"), 18..21)
+        ]
+    );
+
+    assert_eq!(
+        index.unused_configs().collect::<Vec<_>>(),
+        vec!["ci", "unused"]
+    );
+    assert_eq!(index.find_cycles(), Vec::<Vec<String>>::new());
+}
+
+#[test]
+fn test_config_index_update() {
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let mut index = index_from_files(&[
+        (".bazelrc", "build:opt -c opt\nbuild:ci --config=opt"),
+        ("user.bazelrc", "build --config=opt"),
+    ]);
+    assert_eq!(index.configs["opt"].usages.len(), 2);
+    // File names and commands are shared between definitions and usages
+    let definition = &index.configs["opt"].definitions[0];
+    let usage = &index.configs["opt"].usages[0];
+    assert!(Arc::ptr_eq(&definition.file, &usage.file));
+    assert!(Arc::ptr_eq(
+        &definition.command,
+        usage.command.as_ref().unwrap()
+    ));
+    assert_eq!(index.find_config_at(".bazelrc", 7), Some("opt"));
+    assert_eq!(index.find_config_at(".bazelrc", 35), Some("opt"));
+    assert_eq!(index.find_config_at(".bazelrc", 12), None);
+
+    // Updating a file replaces its definitions and usages
+    let mut lines = crate::parser::parse_from_str("build:ci2 -k").lines;
+    crate::bazel_flags::combine_key_value_flags(&mut lines, &bazel_flags);
+    index.update_file(
+        ".bazelrc",
+        &Rope::from_str("build:ci2 -k"),
+        &lines,
+        &bazel_flags,
+    );
+    assert_eq!(index.configs.keys().collect::<Vec<_>>(), vec!["ci2", "opt"]);
+    assert!(index.configs["opt"].definitions.is_empty());
+
+    // Removing a file drops configs which are no longer mentioned anywhere
+    index.remove_file("user.bazelrc");
+    assert_eq!(index.configs.keys().collect::<Vec<_>>(), vec!["ci2"]);
+    assert!(!index.contains_file("user.bazelrc"));
+    assert!(!index.strings.contains("user.bazelrc"));
+}
+
+#[test]
+fn test_platform_specific_configs() {
+    let index = index_from_files(&[(
+        ".bazelrc",
+        "build:linux --copt=-fPIC\nbuild:macos --copt=-fno-common\nbuild:mac -k",
+    )]);
+    assert_eq!(index.unused_configs().collect::<Vec<_>>(), vec!["mac"]);
+    assert!(!index.platform_specific_configs_enabled());
+    assert!(index
+        .get_config_documentation("macos")
+        .unwrap()
+        .ends_with("Applied automatically on macOS hosts if `--enable_platform_specific_config` is set (which is not set in any of the known bazelrc files)"));
+
+    let index = index_from_files(&[
+        (".bazelrc", "build:linux --copt=-fPIC"),
+        ("user.bazelrc", "common --enable_platform_specific_config"),
+    ]);
+    assert!(index.platform_specific_configs_enabled());
+    assert!(index.get_config_documentation("linux").unwrap().ends_with(
+        "Applied automatically on Linux hosts if `--enable_platform_specific_config` is set"
+    ));
+}
+
+#[test]
+fn test_flag_aliases() {
+    let mut index = index_from_files(&[
+        (
+            ".bazelrc",
+            "build --flag_alias=my_setting=//my/pkg:setting\nbuild --my_setting=1",
+        ),
+        ("user.bazelrc", "build --flag_alias \"other=@dep//:other\""),
+    ]);
+    let (name, definitions) = index.get_flag_alias("--nomy_setting").unwrap();
+    assert_eq!(name, "my_setting");
+    assert_eq!(definitions[0].label, "//my/pkg:setting");
+    assert_eq!(definitions[0].span, 19..29);
+    assert_eq!(
+        index.get_flag_alias("--other=").unwrap().1[0].label,
+        "@dep//:other"
+    );
+    assert_eq!(index.get_flag_alias("--unknown"), None);
+    assert_eq!(
+        index.get_flag_alias_documentation("my_setting").unwrap(),
+        "Flag alias `--my_setting`\n\n* alias for `//my/pkg:setting` in `.bazelrc`, line 1\n"
+    );
+
+    index.remove_file("user.bazelrc");
+    assert_eq!(
+        index.flag_aliases.keys().collect::<Vec<_>>(),
+        vec!["my_setting"]
+    );
+}
+
+#[test]
+fn test_config_cycles() {
+    let index = index_from_files(&[(
+        ".bazelrc",
+        "build:a --config=b\nbuild:b --config=c\nbuild:c --config=a\nbuild:self --config=self\nbuild:d --config=a",
+    )]);
+    assert_eq!(
+        index.find_cycles(),
+        vec![
+            vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+                "a".to_string()
+            ],
+            vec!["self".to_string(), "self".to_string()],
+        ]
+    );
+}
+
+#[test]
+fn test_name_span() {
+    let rope = Rope::from_str("build:'my config' --config=o\\pt");
+    assert_eq!(name_span(&rope, &(5..17), "my config"), 7..16);
+    assert_eq!(name_span(&rope, &(26..31), "opt"), 27..31);
+    // Falls back to the whole span if the name is not found
+    assert_eq!(name_span(&rope, &(26..31), "other"), 26..31);
+}
@@ -0,0 +1,304 @@
+use chumsky::Parser;
+
+use crate::tokenizer::{tokenizer, Span, Token, BOM};
+
+// A part of a token, as written in the source code
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Piece {
+    // Characters which are taken over literally, inside or outside of quotes
+    Literal(String),
+    // A character escaped by a `\`
+    Escape(char),
+    // A `\` followed by a newline (`\n` or `\r\n`). It does not contribute to the value.
+    // At the end of the file, the newline is empty.
+    EscapedNewline(String),
+    // An opening or closing quote, i.e. `"` or `'`
+    Quote(char),
+}
+
+impl Piece {
+    // The source code of this piece
+    pub fn source(&self) -> String {
+        match self {
+            Piece::Literal(str) => str.clone(),
+            Piece::Escape(c) => format!("\\{}", c),
+            Piece::EscapedNewline(newline) => format!("\\{}", newline),
+            Piece::Quote(c) => c.to_string(),
+        }
+    }
+
+    // The characters this piece contributes to the value of its token
+    pub fn value(&self) -> String {
+        match self {
+            Piece::Literal(str) => str.clone(),
+            Piece::Escape(c) => c.to_string(),
+            Piece::EscapedNewline(_) | Piece::Quote(_) => String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CstKind {
+    // A token, e.g. a command or a flag, split into its quoted and escaped parts
+    Token(Vec<Piece>),
+    // A comment, including the leading `#`
+    Comment,
+    Whitespace,
+    Newline,
+    EscapedNewline,
+    // Characters skipped by the tokenizer because of a syntax error
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CstElement {
+    pub kind: CstKind,
+    // The exact source code of this element
+    pub text: String,
+    pub span: Span,
+}
+
+impl CstElement {
+    // The value of a token after removing quotes and escapes, as seen by Bazel
+    pub fn value(&self) -> Option<String> {
+        match &self.kind {
+            CstKind::Token(pieces) => Some(pieces.iter().map(|p| p.value()).collect()),
+            _ => None,
+        }
+    }
+
+    // The quotes used within a token, in the order of their appearance
+    pub fn quotes(&self) -> Vec<char> {
+        let mut quotes = Vec::new();
+        if let CstKind::Token(pieces) = &self.kind {
+            let mut open = false;
+            for piece in pieces {
+                if let Piece::Quote(q) = piece {
+                    if !open {
+                        quotes.push(*q);
+                    }
+                    open = !open;
+                }
+            }
+        }
+        quotes
+    }
+}
+
+// A lossless concrete syntax tree of a bazelrc file.
+//
+// In contrast to the parser, which only keeps the values of tokens, the CST records
+// whitespace, quotes and escape sequences, such that the original source can be
+// reproduced exactly.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Cst {
+    pub elements: Vec<CstElement>,
+}
+
+// Splits the source code of a token into its pieces
+fn split_pieces(chars: &[char]) -> Vec<Piece> {
+    let mut pieces = Vec::<Piece>::new();
+    let push_literal = |pieces: &mut Vec<Piece>, c: char| match pieces.last_mut() {
+        Some(Piece::Literal(str)) => str.push(c),
+        _ => pieces.push(Piece::Literal(c.to_string())),
+    };
+    let mut open_quote = Option::<char>::None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' {
+            match (chars.get(i + 1), chars.get(i + 2)) {
+                (Some('\n'), _) => {
+                    pieces.push(Piece::EscapedNewline("\n".to_string()));
+                    i += 2;
+                }
+                (Some('\r'), Some('\n')) => {
+                    pieces.push(Piece::EscapedNewline("\r\n".to_string()));
+                    i += 3;
+                }
+                (Some(escaped), _) => {
+                    pieces.push(Piece::Escape(*escaped));
+                    i += 2;
+                }
+                (None, _) => {
+                    pieces.push(Piece::EscapedNewline(String::new()));
+                    i += 1;
+                }
+            }
+            continue;
+        }
+        match open_quote {
+            None if c == '"' || c == '\'' => {
+                open_quote = Some(c);
+                pieces.push(Piece::Quote(c));
+            }
+            Some(q) if c == q => {
+                open_quote = None;
+                pieces.push(Piece::Quote(c));
+            }
+            _ => push_literal(&mut pieces, c),
+        }
+        i += 1;
+    }
+    pieces
+}
+
+// Maps the characters of a token's value to their location in the source code.
+//
+// `chars` is the source code of (a part of) a token, starting at offset `start`. For each
+// character of the value, the returned list contains the character and the span of source
+// characters it originates from. E.g., for `a\:'b'`, the value `a:b` maps to `0..1`, `1..3`
+// and `4..5`.
+pub fn source_map(chars: &[char], start: usize) -> Vec<(char, Span)> {
+    let mut map = Vec::<(char, Span)>::new();
+    let mut pos = start;
+    for piece in split_pieces(chars) {
+        let len = piece.source().chars().count();
+        match &piece {
+            Piece::Literal(str) => map.extend(
+                str.chars()
+                    .enumerate()
+                    .map(|(i, c)| (c, pos + i..pos + i + 1)),
+            ),
+            Piece::Escape(c) => map.push((*c, pos..pos + len)),
+            Piece::EscapedNewline(_) | Piece::Quote(_) => (),
+        }
+        pos += len;
+    }
+    map
+}
+
+impl Cst {
+    pub fn parse(src: &str) -> Cst {
+        let chars = src.chars().collect::<Vec<_>>();
+        let (tokens, _) = tokenizer().parse_recovery(src);
+        let mut elements = Vec::<CstElement>::new();
+        let text_of = |span: &Span| chars[span.clone()].iter().collect::<String>();
+
+        // Characters between tokens are whitespace, unless the tokenizer skipped them
+        let push_gap = |elements: &mut Vec<CstElement>, span: Span| {
+            if span.is_empty() {
+                return;
+            }
+            let is_whitespace = chars[span.clone()]
+                .iter()
+                .all(|c| matches!(*c, ' ' | '\t' | BOM));
+            elements.push(CstElement {
+                kind: if is_whitespace {
+                    CstKind::Whitespace
+                } else {
+                    CstKind::Error
+                },
+                text: text_of(&span),
+                span,
+            });
+        };
+
+        let mut pos = 0;
+        for (token, span) in tokens.unwrap_or_default() {
+            push_gap(&mut elements, pos..span.start);
+            let kind = match token {
+                Token::Token(_) => CstKind::Token(split_pieces(&chars[span.clone()])),
+                Token::Comment(_) => CstKind::Comment,
+                Token::Newline => CstKind::Newline,
+                Token::EscapedNewline => CstKind::EscapedNewline,
+            };
+            pos = span.end;
+            elements.push(CstElement {
+                kind,
+                text: text_of(&span),
+                span,
+            });
+        }
+        push_gap(&mut elements, pos..chars.len());
+        Cst { elements }
+    }
+
+    // Renders the CST back into source code. This reproduces the parsed source exactly.
+    pub fn render(&self) -> String {
+        self.elements.iter().map(|e| e.text.as_str()).collect()
+    }
+
+    // The tokens of the CST, i.e. commands, configs, flags and values
+    pub fn tokens(&self) -> impl Iterator<Item = &CstElement> {
+        self.elements
+            .iter()
+            .filter(|e| matches!(e.kind, CstKind::Token(_)))
+    }
+}
+
+#[test]
+fn test_cst_round_trip() {
+    for src in [
+        "",
+        "build --x=y\n",
+        "b\"uil\"d':o'pt --\"x\"='y'",
+        "  build\t--a\\ b \\\r\n  --c # comment \\\n continued\r\n\n",
+        "build --a='unclosed\nbuild --b\\",
+        "build \r --x",
+        "# ünïcode 'ä'\nbuild --x=\"ö\\ü\"",
+        "\u{feff}build -x",
+    ] {
+        let cst = Cst::parse(src);
+        assert_eq!(cst.render(), src);
+        let mut pos = 0;
+        for element in &cst.elements {
+            assert_eq!(element.span.start, pos);
+            pos = element.span.end;
+            if let CstKind::Token(pieces) = &element.kind {
+                assert_eq!(
+                    pieces.iter().map(|p| p.source()).collect::<String>(),
+                    element.text
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_cst_pieces() {
+    let cst = Cst::parse("b\"uil\"d':o'pt --x=a\\ b\\\n");
+    let tokens = cst.tokens().collect::<Vec<_>>();
+    assert_eq!(
+        tokens[0].kind,
+        CstKind::Token(vec![
+            Piece::Literal("b".to_string()),
+            Piece::Quote('"'),
+            Piece::Literal("uil".to_string()),
+            Piece::Quote('"'),
+            Piece::Literal("d".to_string()),
+            Piece::Quote('\''),
+            Piece::Literal(":o".to_string()),
+            Piece::Quote('\''),
+            Piece::Literal("pt".to_string()),
+        ])
+    );
+    assert_eq!(tokens[0].value(), Some("build:opt".to_string()));
+    assert_eq!(tokens[0].quotes(), vec!['"', '\'']);
+    assert_eq!(
+        tokens[1].kind,
+        CstKind::Token(vec![
+            Piece::Literal("--x=a".to_string()),
+            Piece::Escape(' '),
+            Piece::Literal("b".to_string()),
+            Piece::EscapedNewline("\n".to_string()),
+        ])
+    );
+    assert_eq!(tokens[1].value(), Some("--x=a b".to_string()));
+}
+
+#[test]
+fn test_source_map() {
+    let chars = "a\\:'b c'\\\nd".chars().collect::<Vec<_>>();
+    assert_eq!(
+        source_map(&chars, 10),
+        vec![
+            ('a', 10..11),
+            (':', 11..13),
+            ('b', 14..15),
+            (' ', 15..16),
+            ('c', 16..17),
+            ('d', 20..21)
+        ]
+    );
+}
@@ -0,0 +1,137 @@
+use ropey::Rope;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+use crate::lsp_utils::lsp_pos_to_column;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const GUTTER: &str = "\x1b[1;34m";
+
+fn severity_style(severity: Option<DiagnosticSeverity>) -> (&'static str, &'static str) {
+    match severity {
+        Some(DiagnosticSeverity::WARNING) => ("warning", "\x1b[1;33m"),
+        Some(DiagnosticSeverity::INFORMATION) => ("info", "\x1b[1;36m"),
+        Some(DiagnosticSeverity::HINT) => ("hint", "\x1b[1;32m"),
+        _ => ("error", "\x1b[1;31m"),
+    }
+}
+
+// Renders a diagnostic for the terminal, similar to the diagnostics of rustc: a header
+// with the severity, the code and the message, followed by the affected source line with
+// the range of the diagnostic underlined.
+//
+// Ranges spanning multiple lines are underlined until the end of their first line. With
+// `color`, ANSI escape codes are used to highlight the output.
+pub fn render_diagnostic(file: &str, rope: &Rope, diagnostic: &Diagnostic, color: bool) -> String {
+    let paint = |style: &str, text: &str| {
+        if color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    };
+    let (severity, severity_color) = severity_style(diagnostic.severity);
+    let start = diagnostic.range.start;
+    let end = diagnostic.range.end;
+
+    let mut header = severity.to_string();
+    if let Some(NumberOrString::String(code)) = &diagnostic.code {
+        header += &format!("[{}]", code);
+    }
+    let mut result = format!(
+        "{}{}\n",
+        paint(severity_color, &header),
+        paint(BOLD, &format!(": {}", diagnostic.message))
+    );
+
+    let line_nr = (start.line + 1).to_string();
+    let indent = " ".repeat(line_nr.len());
+    result += &format!(
+        "{}{} {}:{}:{}\n",
+        indent,
+        paint(GUTTER, "-->"),
+        file,
+        start.line + 1,
+        lsp_pos_to_column(rope, &start) + 1
+    );
+    let line = rope
+        .get_line(start.line as usize)
+        .map(|l| l.to_string())
+        .unwrap_or_default();
+    let line = line.trim_end_matches(['\n', '\r']);
+    let chars = line.chars().collect::<Vec<_>>();
+    let start_col = lsp_pos_to_column(rope, &start).min(chars.len());
+    let end_col = if end.line == start.line {
+        lsp_pos_to_column(rope, &end).clamp(start_col, chars.len())
+    } else {
+        chars.len()
+    };
+    // Keep tabs in the padding, such that the underline is aligned with the source
+    let padding = chars[..start_col]
+        .iter()
+        .map(|c| if *c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let underline = "^".repeat((end_col - start_col).max(1));
+
+    result += &format!("{} {}\n", indent, paint(GUTTER, "|"));
+    result += &format!("{} {}\n", paint(GUTTER, &format!("{} |", line_nr)), line);
+    result += &format!(
+        "{} {} {}{}\n",
+        indent,
+        paint(GUTTER, "|"),
+        padding,
+        paint(severity_color, &underline)
+    );
+
+    for related in diagnostic.related_information.iter().flatten() {
+        let location = related
+            .location
+            .uri
+            .to_file_path()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| related.location.uri.to_string());
+        result += &format!(
+            "{} {} {}: {}:{}: {}\n",
+            indent,
+            paint(GUTTER, "="),
+            paint(BOLD, "note"),
+            location,
+            related.location.range.start.line + 1,
+            related.message
+        );
+    }
+    result
+}
+
+#[test]
+fn test_render_diagnostic() {
+    use tower_lsp::lsp_types::{Position, Range};
+
+    let rope = Rope::from_str("# comment\nbuild\t--some_flag=1\n");
+    let diagnostic = Diagnostic {
+        range: Range::new(Position::new(1, 6), Position::new(1, 17)),
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String("unknown-flag".to_string())),
+        message: "Unknown flag \"--some_flag\"".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        render_diagnostic(".bazelrc", &rope, &diagnostic, false),
+        "warning[unknown-flag]: Unknown flag \"--some_flag\"\n --> .bazelrc:2:7\n  |\n2 | build\t--some_flag=1\n  |      \t^^^^^^^^^^^\n"
+    );
+    assert_eq!(
+        render_diagnostic(".bazelrc", &rope, &diagnostic, true),
+        "\x1b[1;33mwarning[unknown-flag]\x1b[0m\x1b[1m: Unknown flag \"--some_flag\"\x1b[0m\n \x1b[1;34m-->\x1b[0m .bazelrc:2:7\n  \x1b[1;34m|\x1b[0m\n\x1b[1;34m2 |\x1b[0m build\t--some_flag=1\n  \x1b[1;34m|\x1b[0m      \t\x1b[1;33m^^^^^^^^^^^\x1b[0m\n"
+    );
+
+    // Empty ranges are still underlined, multi-line ranges until the end of the line
+    let diagnostic = Diagnostic {
+        range: Range::new(Position::new(0, 2), Position::new(1, 0)),
+        message: "Some error".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        render_diagnostic("-", &rope, &diagnostic, false),
+        "error: Some error\n --> -:1:3\n  |\n1 | # comment\n  |   ^^^^^^^\n"
+    );
+}
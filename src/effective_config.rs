@@ -0,0 +1,975 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tower_lsp::lsp_types::Range;
+
+use crate::{
+    bazel_flags::BazelFlags,
+    config_index::{get_host_platform_config, is_config_flag},
+    formatting::format_token,
+    import_graph::{FileCache, ImportGraph, ParsedFile},
+    lsp_utils::range_to_lsp,
+    parser::{Flag, Line},
+    semantic_diff::{normalize_flag, NormalizedFlag},
+    semantic_model::expand_command,
+    tokenizer::Span,
+};
+
+// The command from which a command inherits its options.
+// See the `inherits` attribute of the `@Command` annotations in Bazel's source code.
+pub fn get_parent_command(command: &str) -> Option<&'static str> {
+    match command {
+        "test" | "run" | "aquery" | "info" | "mobile-install" | "print_action" => Some("build"),
+        "coverage" | "cquery" => Some("test"),
+        _ => None,
+    }
+}
+
+// The sections which apply to a command, in the order in which Bazel applies them.
+// E.g., for `coverage`: `always`, `common`, `build`, `test`, `coverage`.
+pub fn get_command_chain(command: &str) -> Vec<&str> {
+    if command == "startup" {
+        return vec!["startup"];
+    }
+    let mut chain = vec![command];
+    while let Some(parent) = get_parent_command(chain.last().unwrap()) {
+        chain.push(parent);
+    }
+    chain.extend(["common", "always"]);
+    chain.reverse();
+    chain
+}
+
+// A single flag of the effective configuration, together with its origin
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EffectiveFlag {
+    pub flag: Flag,
+    pub file: PathBuf,
+    pub line_nr: usize,
+    // The command and config of the line which contributed this flag
+    pub command: String,
+    pub config: Option<String>,
+    // The `--config` expansions which lead to this flag, outermost first
+    pub via: Vec<String>,
+}
+
+impl EffectiveFlag {
+    // The flag as it would be passed on the command line
+    pub fn format_flag(&self) -> String {
+        match (&self.flag.name, &self.flag.value) {
+            (Some(name), Some(value)) => format!("{}={}", name.0, format_token(&value.0)),
+            (Some(name), None) => name.0.clone(),
+            (None, Some(value)) => format_token(&value.0),
+            (None, None) => String::new(),
+        }
+    }
+
+    // The `command:config` section this flag originates from
+    pub fn section(&self) -> String {
+        match &self.config {
+            Some(config) => format!("{}:{}", self.command, config),
+            None => self.command.clone(),
+        }
+    }
+}
+
+// Bazel stops config expansion at this depth.
+// To be on the safe side, we stop expanding at the same depth.
+pub const MAX_CONFIG_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExpansionError {
+    // A config which is not defined for the command or any of its parent commands
+    UndefinedConfig { config: String, via: Vec<String> },
+    // A config which (transitively) expands to itself. The chain ends with the repeated config.
+    Cycle(Vec<String>),
+    // The chain of configs exceeded `MAX_CONFIG_DEPTH`
+    TooDeep(Vec<String>),
+    // A flag set through `always`, which the command doesn't support. Bazel fails in this case.
+    UnsupportedFlag { flag: String, command: String },
+}
+
+impl std::fmt::Display for ExpansionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpansionError::UndefinedConfig { config, via } if via.len() > 1 => write!(
+                f,
+                "Config value '{}' is not defined in any .rc file (referenced via --config={})",
+                config,
+                via[..via.len() - 1].join(" -> --config=")
+            ),
+            ExpansionError::UndefinedConfig { config, .. } => {
+                write!(
+                    f,
+                    "Config value '{}' is not defined in any .rc file",
+                    config
+                )
+            }
+            ExpansionError::Cycle(chain) => write!(
+                f,
+                "Config expansion has a cycle: --config={}",
+                chain.join(" -> --config=")
+            ),
+            ExpansionError::UnsupportedFlag { flag, command } => write!(
+                f,
+                "{} is set through `always`, but is not supported by `{}`",
+                flag, command
+            ),
+            ExpansionError::TooDeep(chain) => write!(
+                f,
+                "Config expansion exceeds the maximum depth of {}: --config={}",
+                MAX_CONFIG_DEPTH,
+                chain.join(" -> --config=")
+            ),
+        }
+    }
+}
+
+// The result of expanding a command and its configs
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Expansion {
+    pub flags: Vec<EffectiveFlag>,
+    pub errors: Vec<ExpansionError>,
+}
+
+// The location of a flag within an rc file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlagLocation {
+    pub file: PathBuf,
+    pub line_nr: usize,
+    // The span of the flag name
+    pub span: Span,
+    pub range: Range,
+}
+
+// A flag whose value is always overridden by a later flag
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverriddenFlag {
+    // The canonical flag name, e.g. `--keep_going`
+    pub name: String,
+    pub overridden: FlagLocation,
+    // The flag which sets the value which is finally used
+    pub winner: FlagLocation,
+}
+
+// A flag whose value is overridden by more specific sections for all commands it applies to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShadowedFlag {
+    // The canonical flag name, e.g. `--keep_going`
+    pub name: String,
+    pub shadowed: FlagLocation,
+    // The flags setting the values which are finally used, e.g. one for `build` and one
+    // for `test`
+    pub winners: Vec<FlagLocation>,
+}
+
+// A flag which is set to the same value multiple times within the same `command:config` section
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateFlag {
+    // The normalized flag, e.g. `--keep_going=true`
+    pub flag: String,
+    pub locations: Vec<FlagLocation>,
+}
+
+// A flag which is set to different values by multiple rc files
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConflictingFlag {
+    // The canonical flag name, e.g. `--output_base`
+    pub name: String,
+    // All assignments, in rc order. The last one wins.
+    pub assignments: Vec<(FlagLocation, Option<String>)>,
+}
+
+struct RcLine {
+    file: PathBuf,
+    parsed: Arc<ParsedFile>,
+    line_nr: usize,
+}
+
+impl RcLine {
+    fn line(&self) -> &Line {
+        &self.parsed.lines[self.line_nr]
+    }
+}
+
+// Computes the flags Bazel applies for a command, following Bazel's precedence rules:
+//
+// * rc files are read in the order system rc, workspace rc, user rc (`~/.bazelrc`),
+//   with imported files expanded in place of the `import` statement.
+// * Options are ordered by command first and rc file order second. I.e., all
+//   `common` options come before all `build` options, which come before all
+//   `test` options, independent of the file they are defined in.
+// * `common` options are only applied to commands which support them. Other commands
+//   silently ignore them. `always` options are applied to all commands, and Bazel fails
+//   if the command does not support them.
+// * `--config` flags are replaced by the config's flags, at the position of the `--config` flag.
+//   Configs can themselves contain `--config` flags, which are expanded recursively.
+//
+// Later flags take precedence over earlier flags.
+pub struct EffectiveConfig<'a> {
+    lines: Vec<RcLine>,
+    bazel_flags: &'a BazelFlags,
+}
+
+impl<'a> EffectiveConfig<'a> {
+    // Loads the given rc files, which must be listed from lowest to highest precedence
+    pub fn new(rc_files: &[PathBuf], cache: &FileCache, bazel_flags: &'a BazelFlags) -> Self {
+        let graph = ImportGraph::build(rc_files, cache, bazel_flags);
+        let mut lines = Vec::<RcLine>::new();
+        for (path, range) in &graph.chunks {
+            let Some(parsed) = cache.get(path, bazel_flags) else {
+                continue;
+            };
+            lines.extend(range.clone().map(|line_nr| RcLine {
+                file: path.clone(),
+                parsed: parsed.clone(),
+                line_nr,
+            }));
+        }
+        EffectiveConfig { lines, bazel_flags }
+    }
+
+    // The lines of the given `command:config` section, in rc order
+    fn section_lines<'s>(
+        &'s self,
+        command: &'s str,
+        config: Option<&'s str>,
+    ) -> impl Iterator<Item = &'s RcLine> + 's {
+        self.lines.iter().filter(move |l| {
+            let line = l.line();
+            line.command.as_ref().is_some_and(|c| c.0 == command)
+                && line.config.as_ref().map(|c| c.0.as_str()) == config
+        })
+    }
+
+    // Expands the config given as the last element of `via`
+    fn expand_config_into(&self, command: &str, via: &[String], out: &mut Expansion) {
+        let config = via.last().unwrap();
+        if via[..via.len() - 1].contains(config) {
+            out.errors.push(ExpansionError::Cycle(via.to_vec()));
+            return;
+        }
+        if via.len() > MAX_CONFIG_DEPTH {
+            out.errors.push(ExpansionError::TooDeep(via.to_vec()));
+            return;
+        }
+        let is_defined = get_command_chain(command)
+            .into_iter()
+            .any(|section| self.section_lines(section, Some(config)).next().is_some());
+        if !is_defined {
+            out.errors.push(ExpansionError::UndefinedConfig {
+                config: config.clone(),
+                via: via.to_vec(),
+            });
+            return;
+        }
+        self.collect(command, Some(config), via, out);
+    }
+
+    fn collect(&self, command: &str, config: Option<&str>, via: &[String], out: &mut Expansion) {
+        for section in get_command_chain(command) {
+            for rc_line in self.section_lines(section, config) {
+                for flag in &rc_line.line().flags {
+                    let config_name = flag
+                        .name
+                        .as_ref()
+                        .filter(|n| is_config_flag(&n.0, self.bazel_flags))
+                        .and(flag.value.as_ref());
+                    if let Some((name, _)) = config_name {
+                        // Expand `--config` in place
+                        let mut nested_via = via.to_vec();
+                        nested_via.push(name.clone());
+                        self.expand_config_into(command, &nested_via, out);
+                        continue;
+                    }
+                    let flag_info = flag
+                        .name
+                        .as_ref()
+                        .and_then(|n| self.bazel_flags.get_by_invocation(&n.0));
+                    let is_supported = flag_info.map_or(true, |f| f.supports_command(command));
+                    if section == "common" && !is_supported {
+                        continue;
+                    }
+                    if section == "always" && !is_supported {
+                        out.errors.push(ExpansionError::UnsupportedFlag {
+                            flag: flag.name.as_ref().unwrap().0.clone(),
+                            command: command.to_string(),
+                        });
+                    }
+                    out.flags.push(EffectiveFlag {
+                        flag: flag.clone(),
+                        file: rc_line.file.clone(),
+                        line_nr: rc_line.line_nr,
+                        command: section.to_string(),
+                        config: config.map(|c| c.to_string()),
+                        via: via.to_vec(),
+                    });
+                }
+            }
+        }
+    }
+
+    // Expands `bazel <command> --config=<configs>...` on the host platform
+    pub fn expand(&self, command: &str, configs: &[String]) -> Expansion {
+        self.expand_on_platform(command, configs, get_host_platform_config())
+    }
+
+    // Expands `bazel <command> --config=<configs>...`, as if running on the platform
+    // with the given platform-specific config (e.g., `linux`)
+    pub fn expand_on_platform(
+        &self,
+        command: &str,
+        configs: &[String],
+        platform_config: Option<&str>,
+    ) -> Expansion {
+        let mut expansion = Expansion::default();
+        self.collect(command, None, &[], &mut expansion);
+        // With `--enable_platform_specific_config`, the platform's config is applied after
+        // the rc file options, if it is defined for the command
+        let platform_configs_enabled = expansion
+            .flags
+            .iter()
+            .rev()
+            .find_map(|f| match normalize_flag(&f.flag, self.bazel_flags) {
+                NormalizedFlag::SingleValued { name, value }
+                    if name == "--enable_platform_specific_config" =>
+                {
+                    Some(value.as_deref() == Some("true"))
+                }
+                _ => None,
+            })
+            .unwrap_or(false);
+        if let Some(platform_config) = platform_config.filter(|_| platform_configs_enabled) {
+            let is_defined = get_command_chain(command).into_iter().any(|section| {
+                self.section_lines(section, Some(platform_config))
+                    .next()
+                    .is_some()
+            });
+            if is_defined {
+                self.expand_config_into(command, &[platform_config.to_string()], &mut expansion);
+            }
+        }
+        for config in configs {
+            self.expand_config_into(command, std::slice::from_ref(config), &mut expansion);
+        }
+        expansion
+    }
+
+    // Expands only the given config, ignoring the flags which apply to the command in general
+    pub fn expand_config(&self, command: &str, config: &str) -> Expansion {
+        let mut expansion = Expansion::default();
+        self.expand_config_into(command, &[config.to_string()], &mut expansion);
+        expansion
+    }
+
+    // The effective flags for running `bazel <command> --config=<configs>...`
+    pub fn flags_for(&self, command: &str, configs: &[String]) -> Vec<EffectiveFlag> {
+        self.expand(command, configs).flags
+    }
+
+    // All named flags, grouped by their `command:config` section, in rc order.
+    // Each section is returned together with its command and config.
+    #[allow(clippy::type_complexity)]
+    fn flags_by_section(&self) -> Vec<((&str, Option<&str>), Vec<(FlagLocation, NormalizedFlag)>)> {
+        let mut sections =
+            Vec::<((&str, Option<&str>), Vec<(FlagLocation, NormalizedFlag)>)>::new();
+        for rc_line in &self.lines {
+            let line = rc_line.line();
+            let Some(command) = &line.command else {
+                continue;
+            };
+            let key = (
+                command.0.as_str(),
+                line.config.as_ref().map(|c| c.0.as_str()),
+            );
+            let section_idx = match sections.iter().position(|s| s.0 == key) {
+                Some(idx) => idx,
+                None => {
+                    sections.push((key, Vec::new()));
+                    sections.len() - 1
+                }
+            };
+            for flag in &line.flags {
+                let Some(name) = &flag.name else {
+                    continue;
+                };
+                let location = FlagLocation {
+                    file: rc_line.file.clone(),
+                    line_nr: rc_line.line_nr,
+                    span: name.1.clone(),
+                    range: range_to_lsp(&rc_line.parsed.rope, &name.1).unwrap_or_default(),
+                };
+                sections[section_idx]
+                    .1
+                    .push((location, normalize_flag(flag, self.bazel_flags)));
+            }
+        }
+        sections
+    }
+
+    // Finds flags whose value is guaranteed to be overridden by a later flag.
+    //
+    // This is the case if the same `command:config` section sets a flag multiple
+    // times, because the later flag is always applied after the earlier flag, no
+    // matter which command is executed. For flags in different sections (e.g.
+    // `common` and `build`), the later flag only wins for some commands.
+    // Flags which can be repeated are never reported. Startup flags overridden by
+    // other files are reported by `find_conflicting_startup_flags` instead.
+    pub fn find_overridden_flags(&self) -> Vec<OverriddenFlag> {
+        let mut overridden_flags = Vec::<OverriddenFlag>::new();
+        for ((command, _), section) in self.flags_by_section() {
+            let mut assignments = Vec::<(&str, Vec<(&FlagLocation, &Option<String>)>)>::new();
+            for (location, flag) in &section {
+                let NormalizedFlag::SingleValued { name, value } = flag else {
+                    continue;
+                };
+                match assignments.iter_mut().find(|a| a.0 == name) {
+                    Some(a) => a.1.push((location, value)),
+                    None => assignments.push((name, vec![(location, value)])),
+                }
+            }
+            for (name, assignments) in assignments {
+                let (winner, final_value) = assignments.last().unwrap();
+                for (location, value) in &assignments[..assignments.len() - 1] {
+                    if command == "startup" && location.file != winner.file {
+                        continue;
+                    }
+                    if value != final_value {
+                        overridden_flags.push(OverriddenFlag {
+                            name: name.to_string(),
+                            overridden: (*location).clone(),
+                            winner: (*winner).clone(),
+                        });
+                    }
+                }
+            }
+        }
+        overridden_flags
+    }
+
+    // Finds flags whose value never takes effect, because flags of later applied sections
+    // override it for every command the flag applies to.
+    //
+    // E.g., `common -c dbg` is shadowed by `build -c opt`, since only `build` and the commands
+    // inheriting from `build` support `--compilation_mode`. Only sections with the same config
+    // are considered. Flags overridden within their own section are reported by
+    // `find_overridden_flags` instead.
+    pub fn find_shadowed_flags(&self) -> Vec<ShadowedFlag> {
+        let sections = self.flags_by_section();
+        // The last assignment of the given flag within a section
+        let last_assignment = |section: &[(FlagLocation, NormalizedFlag)], flag: &str| {
+            section.iter().rev().find_map(|(location, f)| match f {
+                NormalizedFlag::SingleValued { name, value } if name == flag => {
+                    Some((location.clone(), value.clone()))
+                }
+                _ => None,
+            })
+        };
+        let all_commands = expand_command("common", self.bazel_flags);
+
+        let mut shadowed_flags = Vec::<ShadowedFlag>::new();
+        for ((command, config), section) in &sections {
+            if *command == "startup" {
+                continue;
+            }
+            for (i, (location, flag)) in section.iter().enumerate() {
+                let NormalizedFlag::SingleValued { name, value } = flag else {
+                    continue;
+                };
+                let is_last = section[i + 1..].iter().all(
+                    |f| !matches!(&f.1, NormalizedFlag::SingleValued { name: n, .. } if n == name),
+                );
+                if !is_last {
+                    continue;
+                }
+                let Some(info) = self.bazel_flags.get_by_invocation(name) else {
+                    continue;
+                };
+                let commands = all_commands
+                    .iter()
+                    .filter(|c| info.supports_command(c))
+                    .filter(|c| get_command_chain(c).contains(command))
+                    .collect::<Vec<_>>();
+                // The location of the value which finally wins, per command
+                let winners = commands
+                    .iter()
+                    .map(|c| {
+                        let chain = get_command_chain(c);
+                        let position = chain.iter().position(|s| s == command).unwrap();
+                        chain[position + 1..].iter().rev().find_map(|later| {
+                            let later_section =
+                                sections.iter().find(|s| s.0 == (*later, *config))?;
+                            last_assignment(&later_section.1, name)
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>();
+                let Some(winners) = winners.filter(|w| !w.is_empty()) else {
+                    continue;
+                };
+                if winners.iter().any(|w| w.1 == *value) {
+                    continue;
+                }
+                let mut winner_locations = Vec::<FlagLocation>::new();
+                for (location, _) in winners {
+                    if !winner_locations.contains(&location) {
+                        winner_locations.push(location);
+                    }
+                }
+                shadowed_flags.push(ShadowedFlag {
+                    name: name.clone(),
+                    shadowed: location.clone(),
+                    winners: winner_locations,
+                });
+            }
+        }
+        shadowed_flags
+    }
+
+    // Finds flags which are set to the same value in the same `command:config`
+    // section of multiple rc files
+    pub fn find_cross_file_duplicates(&self) -> Vec<DuplicateFlag> {
+        let mut duplicates = Vec::<DuplicateFlag>::new();
+        for (_, section) in self.flags_by_section() {
+            let mut occurrences = Vec::<DuplicateFlag>::new();
+            for (location, flag) in section {
+                let flag = flag.format();
+                match occurrences.iter_mut().find(|o| o.flag == flag) {
+                    Some(o) => o.locations.push(location),
+                    None => occurrences.push(DuplicateFlag {
+                        flag,
+                        locations: vec![location],
+                    }),
+                }
+            }
+            duplicates.extend(
+                occurrences
+                    .into_iter()
+                    .filter(|o| o.locations.iter().any(|l| l.file != o.locations[0].file)),
+            );
+        }
+        duplicates
+    }
+
+    // Finds startup flags which are set to different values by multiple rc files.
+    //
+    // Startup options are only read once when starting the Bazel server, and changing
+    // them restarts the server. Confusingly, the rc file read last silently wins,
+    // e.g. the user's `~/.bazelrc` wins over the workspace `.bazelrc`.
+    pub fn find_conflicting_startup_flags(&self) -> Vec<ConflictingFlag> {
+        let mut conflicts = Vec::<ConflictingFlag>::new();
+        for ((command, _), section) in self.flags_by_section() {
+            if command != "startup" {
+                continue;
+            }
+            let mut assignments = Vec::<ConflictingFlag>::new();
+            for (location, flag) in section {
+                let NormalizedFlag::SingleValued { name, value } = flag else {
+                    continue;
+                };
+                match assignments.iter_mut().find(|a| a.name == name) {
+                    Some(a) => a.assignments.push((location, value)),
+                    None => assignments.push(ConflictingFlag {
+                        name,
+                        assignments: vec![(location, value)],
+                    }),
+                }
+            }
+            conflicts.extend(assignments.into_iter().filter(|a| {
+                let (first_location, first_value) = &a.assignments[0];
+                a.assignments.iter().any(|(_, v)| v != first_value)
+                    && a.assignments
+                        .iter()
+                        .any(|(l, _)| l.file != first_location.file)
+            }));
+        }
+        conflicts
+    }
+
+    // The names of all configs defined by the rc files
+    pub fn configs(&self) -> BTreeSet<String> {
+        self.lines
+            .iter()
+            .filter_map(|l| Some(l.line().config.as_ref()?.0.clone()))
+            .collect()
+    }
+
+    pub fn files(&self) -> Vec<&Path> {
+        let mut files = Vec::<&Path>::new();
+        for line in &self.lines {
+            if !files.contains(&line.file.as_path()) {
+                files.push(&line.file);
+            }
+        }
+        files
+    }
+}
+
+#[test]
+fn test_command_chain() {
+    assert_eq!(
+        get_command_chain("build"),
+        vec!["always", "common", "build"]
+    );
+    assert_eq!(
+        get_command_chain("coverage"),
+        vec!["always", "common", "build", "test", "coverage"]
+    );
+    assert_eq!(get_command_chain("startup"), vec!["startup"]);
+}
+
+#[test]
+fn test_effective_flags() {
+    let ws = crate::import_graph::create_test_workspace(
+        "effective-flags",
+        &[
+            (
+                ".bazelrc",
+                "test --test_output=errors\nbuild -c opt --config=ci\nimport %workspace%/ci.bazelrc\ncommon --announce_rc",
+            ),
+            ("ci.bazelrc", "build:ci -k\nbuild --jobs=4"),
+            ("user.bazelrc", "build -c dbg\ntest:ci --flaky_test_attempts=2"),
+        ],
+    );
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let cache = FileCache::default();
+    let config = EffectiveConfig::new(
+        &[ws.join(".bazelrc"), ws.join("user.bazelrc")],
+        &cache,
+        &bazel_flags,
+    );
+    let flags = config.flags_for("test", &[]);
+    assert_eq!(
+        flags
+            .iter()
+            .map(|f| (f.format_flag(), f.section(), f.via.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("--announce_rc".to_string(), "common".to_string(), vec![]),
+            ("-c=opt".to_string(), "build".to_string(), vec![]),
+            (
+                "-k".to_string(),
+                "build:ci".to_string(),
+                vec!["ci".to_string()]
+            ),
+            (
+                "--flaky_test_attempts=2".to_string(),
+                "test:ci".to_string(),
+                vec!["ci".to_string()]
+            ),
+            ("--jobs=4".to_string(), "build".to_string(), vec![]),
+            ("-c=dbg".to_string(), "build".to_string(), vec![]),
+            (
+                "--test_output=errors".to_string(),
+                "test".to_string(),
+                vec![]
+            ),
+        ]
+    );
+    assert_eq!(flags[4].file, ws.join("ci.bazelrc"));
+    assert_eq!(flags[4].line_nr, 1);
+}
+
+#[test]
+fn test_chained_configs() {
+    let ws = crate::import_graph::create_test_workspace(
+        "chained-configs",
+        &[(
+            ".bazelrc",
+            "build:a --config=b -c opt\nbuild:b -k\ntest:b --config=c\nbuild:c --config=a\nbuild:d --config=undefined",
+        )],
+    );
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let cache = FileCache::default();
+    let config = EffectiveConfig::new(&[ws.join(".bazelrc")], &cache, &bazel_flags);
+
+    // Nested configs are expanded in place
+    let expansion = config.expand("build", &["a".to_string()]);
+    assert_eq!(
+        expansion
+            .flags
+            .iter()
+            .map(|f| (f.format_flag(), f.via.join(",")))
+            .collect::<Vec<_>>(),
+        vec![
+            ("-k".to_string(), "a,b".to_string()),
+            ("-c=opt".to_string(), "a".to_string())
+        ]
+    );
+    assert_eq!(expansion.errors, vec![]);
+
+    // Cycles are detected
+    let expansion = config.expand_config("test", "a");
+    assert_eq!(
+        expansion.errors,
+        vec![ExpansionError::Cycle(
+            ["a", "b", "c", "a"].map(String::from).to_vec()
+        )]
+    );
+    assert_eq!(
+        expansion.errors[0].to_string(),
+        "Config expansion has a cycle: --config=a -> --config=b -> --config=c -> --config=a"
+    );
+
+    // Undefined configs are reported
+    assert_eq!(
+        config.expand_config("build", "d").errors[0].to_string(),
+        "Config value 'undefined' is not defined in any .rc file (referenced via --config=d)"
+    );
+}
+
+#[test]
+fn test_overridden_flags() {
+    let ws = crate::import_graph::create_test_workspace(
+        "overridden-flags",
+        &[
+            (
+                ".bazelrc",
+                "build --jobs=4 -k\ntest --jobs=8\nbuild:ci --jobs=2\nbuild --keep_going\ncommon --copt=a",
+            ),
+            ("user.bazelrc", "build --jobs=16\nbuild --copt=b --nokeep_going"),
+        ],
+    );
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let cache = FileCache::default();
+    let config = EffectiveConfig::new(
+        &[ws.join(".bazelrc"), ws.join("user.bazelrc")],
+        &cache,
+        &bazel_flags,
+    );
+    assert_eq!(
+        config
+            .find_overridden_flags()
+            .iter()
+            .map(|o| (
+                o.name.as_str(),
+                o.overridden.line_nr,
+                o.winner.file.strip_prefix(&ws).unwrap().to_str().unwrap(),
+                o.winner.line_nr
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            ("--jobs", 0, "user.bazelrc", 0),
+            ("--keep_going", 0, "user.bazelrc", 1),
+            ("--keep_going", 3, "user.bazelrc", 1),
+        ]
+    );
+}
+
+#[test]
+fn test_shadowed_flags() {
+    let ws = crate::import_graph::create_test_workspace(
+        "shadowed-flags",
+        &[
+            (
+                ".bazelrc",
+                "always --jobs=1\ncommon --jobs=2\ncommon --script_path=a\nrun --script_path=b\nbuild -c opt\ncommon -c dbg\nbuild:ci --jobs=4",
+            ),
+            ("user.bazelrc", "common --keep_going\nbuild --nokeep_going\nalways:ci --jobs=3"),
+        ],
+    );
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let cache = FileCache::default();
+    let config = EffectiveConfig::new(
+        &[ws.join(".bazelrc"), ws.join("user.bazelrc")],
+        &cache,
+        &bazel_flags,
+    );
+    assert_eq!(
+        config
+            .find_shadowed_flags()
+            .iter()
+            .map(|s| (
+                s.name.as_str(),
+                s.shadowed.line_nr,
+                s.winners
+                    .iter()
+                    .map(|w| format!(
+                        "{}:{}",
+                        w.file.strip_prefix(&ws).unwrap().to_str().unwrap(),
+                        w.line_nr
+                    ))
+                    .collect::<Vec<_>>()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            // `common` always applies after `always`
+            ("--jobs", 0, vec![".bazelrc:1".to_string()]),
+            // `--script_path` is only supported by `run`
+            ("--script_path", 2, vec![".bazelrc:3".to_string()]),
+            // Not reported: `-c dbg` still applies to e.g. `clean`, `--keep_going` to
+            // `query` and `build:ci` only overrides `always:ci` for the commands
+            // inheriting from `build`
+        ]
+    );
+}
+
+#[test]
+fn test_cross_file_duplicates() {
+    let ws = crate::import_graph::create_test_workspace(
+        "cross-file-duplicates",
+        &[
+            (
+                ".bazelrc",
+                "build -k --copt=-O2\nbuild --jobs=4\nbuild --jobs=4\ntest -k\nimport %workspace%/ci.bazelrc",
+            ),
+            ("ci.bazelrc", "build --keep_going\nbuild --copt -O2\nbuild:ci -k"),
+        ],
+    );
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let cache = FileCache::default();
+    let config = EffectiveConfig::new(&[ws.join(".bazelrc")], &cache, &bazel_flags);
+    assert_eq!(
+        config
+            .find_cross_file_duplicates()
+            .iter()
+            .map(|d| (
+                d.flag.as_str(),
+                d.locations
+                    .iter()
+                    .map(|l| (l.file.file_name().unwrap().to_str().unwrap(), l.line_nr))
+                    .collect::<Vec<_>>()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "--keep_going=true",
+                vec![(".bazelrc", 0), ("ci.bazelrc", 0)]
+            ),
+            ("--copt=-O2", vec![(".bazelrc", 0), ("ci.bazelrc", 1)]),
+        ]
+    );
+}
+
+#[test]
+fn test_conflicting_startup_flags() {
+    let ws = crate::import_graph::create_test_workspace(
+        "conflicting-startup-flags",
+        &[
+            (
+                ".bazelrc",
+                "startup --output_base=/a --host_jvm_args=-Xmx1g\nimport %workspace%/ci.bazelrc\nstartup --batch",
+            ),
+            (
+                "ci.bazelrc",
+                "startup --output_base=/b --host_jvm_args=-Xmx2g\nstartup --batch=true",
+            ),
+        ],
+    );
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let cache = FileCache::default();
+    let config = EffectiveConfig::new(&[ws.join(".bazelrc")], &cache, &bazel_flags);
+    assert_eq!(
+        config
+            .find_conflicting_startup_flags()
+            .iter()
+            .map(|c| (
+                c.name.as_str(),
+                c.assignments
+                    .iter()
+                    .map(|(l, v)| (
+                        l.file.file_name().unwrap().to_str().unwrap(),
+                        v.as_deref().unwrap_or_default()
+                    ))
+                    .collect::<Vec<_>>()
+            ))
+            .collect::<Vec<_>>(),
+        vec![(
+            "--output_base",
+            vec![(".bazelrc", "/a"), ("ci.bazelrc", "/b")]
+        )]
+    );
+    // Not reported twice
+    assert_eq!(config.find_overridden_flags(), vec![]);
+}
+
+#[test]
+fn test_common_and_always() {
+    let ws = crate::import_graph::create_test_workspace(
+        "common-and-always",
+        &[(
+            ".bazelrc",
+            "common --keep_going --//my:setting=1\nalways --announce_rc --test_output=errors",
+        )],
+    );
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let cache = FileCache::default();
+    let config = EffectiveConfig::new(&[ws.join(".bazelrc")], &cache, &bazel_flags);
+
+    // `common` flags are applied to all commands which support them
+    let expansion = config.expand("test", &[]);
+    assert_eq!(
+        expansion
+            .flags
+            .iter()
+            .map(|f| f.format_flag())
+            .collect::<Vec<_>>(),
+        vec![
+            "--announce_rc",
+            "--test_output=errors",
+            "--keep_going",
+            "--//my:setting=1"
+        ]
+    );
+    assert_eq!(expansion.errors, vec![]);
+
+    // Unsupported `common` flags are dropped, unsupported `always` flags are errors
+    let expansion = config.expand("build", &[]);
+    assert_eq!(
+        expansion
+            .flags
+            .iter()
+            .map(|f| f.format_flag())
+            .collect::<Vec<_>>(),
+        vec![
+            "--announce_rc",
+            "--test_output=errors",
+            "--keep_going",
+            "--//my:setting=1"
+        ]
+    );
+    let expansion = config.expand("version", &[]);
+    assert!(!expansion
+        .flags
+        .iter()
+        .any(|f| f.format_flag() == "--keep_going"));
+    assert_eq!(
+        expansion
+            .errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>(),
+        vec!["--test_output is set through `always`, but is not supported by `version`"]
+    );
+}
+
+#[test]
+fn test_platform_specific_configs() {
+    let ws = crate::import_graph::create_test_workspace(
+        "platform-configs",
+        &[(
+            ".bazelrc",
+            "build:linux --copt=-fPIC\nbuild:ci -k\nbuild --enable_platform_specific_config",
+        )],
+    );
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let cache = FileCache::default();
+    let config = EffectiveConfig::new(&[ws.join(".bazelrc")], &cache, &bazel_flags);
+    let format_flags = |e: Expansion| e.flags.iter().map(|f| f.format_flag()).collect::<Vec<_>>();
+    assert_eq!(
+        format_flags(config.expand_on_platform("build", &["ci".to_string()], Some("linux"))),
+        vec!["--enable_platform_specific_config", "--copt=-fPIC", "-k"]
+    );
+    assert_eq!(
+        format_flags(config.expand_on_platform("build", &[], Some("windows"))),
+        vec!["--enable_platform_specific_config"]
+    );
+    assert_eq!(
+        config
+            .expand_on_platform("build", &[], Some("windows"))
+            .errors,
+        vec![]
+    );
+}
@@ -1,4 +1,4 @@
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 // See https://github.com/bazelbuild/bazel/blob/20c49b49d6d616aeb97d30454656ebbf9cbacd21/src/main/cpp/workspace_layout.cc#L35
 const ROOT_FILE_NAME: [&str; 4] = ["MODULE.bazel", "REPO.bazel", "WORKSPACE.bazel", "WORKSPACE"];
@@ -20,10 +20,147 @@ pub fn get_workspace_path(path: &Path) -> Option<PathBuf> {
     None
 }
 
+pub fn get_home_path() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+// Lexically normalizes a path by removing `.` and `..` components.
+// In contrast to `std::fs::canonicalize`, this also works for non-existing files
+// and does not resolve symlinks.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push(component);
+                }
+            }
+            _ => result.push(component),
+        }
+    }
+    result
+}
+
+// Resolves the path of an `import` / `try-import` statement.
+//
+// `%workspace%` is replaced by the workspace root, and a leading `~` by the home directory.
+// Bazel resolves relative paths against its working directory, which usually is the
+// workspace root. Hence, we resolve relative paths against the workspace root, too.
+// For files outside of any workspace, we fall back to the directory of the importing file.
 pub fn resolve_bazelrc_path(file_path: &Path, raw_path: &str) -> Option<PathBuf> {
     let mut path = raw_path.to_string();
     if path.contains("%workspace%") {
         path = path.replace("%workspace%", get_workspace_path(file_path)?.to_str()?);
     }
-    Some(file_path.join(Path::new(&path)))
+    if path == "~" || path.starts_with("~/") {
+        path = path.replacen('~', get_home_path()?.to_str()?, 1);
+    }
+    let base_path = get_workspace_path(file_path).or_else(|| Some(file_path.parent()?.into()))?;
+    Some(normalize_path(&base_path.join(Path::new(&path))))
+}
+
+// The rc files Bazel reads by default, from lowest to highest precedence:
+// the system rc, the workspace rc and the user rc.
+// See https://bazel.build/run/bazelrc#bazelrc-file-locations
+pub fn get_default_rc_files(workspace: Option<&Path>) -> Vec<PathBuf> {
+    let mut rc_files = Vec::<PathBuf>::new();
+    if cfg!(windows) {
+        if let Some(program_data) = std::env::var_os("ProgramData") {
+            rc_files.push(Path::new(&program_data).join("bazel.bazelrc"));
+        }
+    } else {
+        rc_files.push(PathBuf::from("/etc/bazel.bazelrc"));
+    }
+    if let Some(workspace) = workspace {
+        rc_files.push(workspace.join(".bazelrc"));
+    }
+    if let Some(home) = get_home_path() {
+        rc_files.push(home.join(".bazelrc"));
+    }
+    rc_files
+}
+
+// Finds the BUILD file of the package a label (e.g., `//my/pkg:setting`) belongs to.
+// Labels in external repositories (`@repo//...`) are not supported.
+pub fn get_build_file(workspace: &Path, label: &str) -> Option<PathBuf> {
+    let label = label
+        .strip_prefix("@@")
+        .or(label.strip_prefix('@'))
+        .unwrap_or(label);
+    let package = label.strip_prefix("//")?;
+    let package = package.split_once(':').map_or(package, |(p, _)| p);
+    ["BUILD.bazel", "BUILD"]
+        .iter()
+        .map(|name| workspace.join(package).join(name))
+        .find(|path| path.is_file())
+}
+
+// Checks if the file name looks like a bazelrc file, e.g. `.bazelrc`, `user.bazelrc` or `bazelrc`
+pub fn is_bazelrc_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n == "bazelrc" || n.ends_with(".bazelrc"))
+}
+
+// Recursively finds all bazelrc files within a directory.
+//
+// Hidden directories (such as `.git`) are skipped, and symlinked directories are
+// not followed, such that Bazel's `bazel-*` output directories are not searched.
+pub fn find_bazelrc_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    let mut result = Vec::<PathBuf>::new();
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if !entry.file_name().to_string_lossy().starts_with('.') {
+                result.extend(find_bazelrc_files(&path)?);
+            }
+        } else if is_bazelrc_file(&path) {
+            result.push(path);
+        }
+    }
+    Ok(result)
+}
+
+// Decodes the contents of a bazelrc file. A leading byte order mark is removed, such that
+// spans match the editor's view of the file, and invalid UTF-8 sequences are replaced
+// by `U+FFFD` instead of failing to read the whole file.
+pub fn decode_bazelrc(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix("\u{feff}".as_bytes()).unwrap_or(bytes);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+pub fn read_bazelrc(path: &Path) -> std::io::Result<String> {
+    Ok(decode_bazelrc(&std::fs::read(path)?))
+}
+
+#[test]
+fn test_is_bazelrc_file() {
+    assert!(is_bazelrc_file(Path::new("/my/workspace/.bazelrc")));
+    assert!(is_bazelrc_file(Path::new("tools/ci.bazelrc")));
+    assert!(is_bazelrc_file(Path::new("/etc/bazel.bazelrc")));
+    assert!(is_bazelrc_file(Path::new("bazelrc")));
+    assert!(!is_bazelrc_file(Path::new("BUILD.bazel")));
+    assert!(!is_bazelrc_file(Path::new(".bazelrc/BUILD")));
+}
+
+#[test]
+fn test_normalize_path() {
+    assert_eq!(
+        normalize_path(Path::new("/a/./b/../c.bazelrc")),
+        PathBuf::from("/a/c.bazelrc")
+    );
+    assert_eq!(normalize_path(Path::new("../a")), PathBuf::from("../a"));
+}
+
+#[test]
+fn test_decode_bazelrc() {
+    assert_eq!(decode_bazelrc(b"build -x"), "build -x");
+    assert_eq!(decode_bazelrc(b"\xef\xbb\xbfbuild -x"), "build -x");
+    assert_eq!(decode_bazelrc(b"build --x=\xff\n"), "build --x=\u{fffd}\n");
 }
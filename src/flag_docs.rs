@@ -0,0 +1,197 @@
+use crate::bazel_flags_proto::FlagInfo;
+
+// The command line reference, containing anchors for each flag
+pub const FLAG_REFERENCE_URL: &str = "https://bazel.build/reference/command-line-reference";
+
+// The formats in which flag documentation can be rendered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DocFormat {
+    Markdown,
+    PlainText,
+    Html,
+}
+
+impl DocFormat {
+    fn escape(&self, str: &str) -> String {
+        match self {
+            DocFormat::Markdown => escape_markdown(str),
+            DocFormat::PlainText => str.to_string(),
+            DocFormat::Html => escape_html(str),
+        }
+    }
+
+    fn code(&self, str: &str) -> String {
+        match self {
+            DocFormat::Markdown => format!("`{}`", str),
+            DocFormat::PlainText => str.to_string(),
+            DocFormat::Html => format!("<code>{}</code>", escape_html(str)),
+        }
+    }
+
+    fn link(&self, text: &str, url: &str) -> String {
+        match self {
+            DocFormat::Markdown => format!("[{}]({})", self.code(text), url),
+            DocFormat::PlainText => format!("{} ({})", text, url),
+            DocFormat::Html => format!("<a href=\"{}\">{}</a>", escape_html(url), self.code(text)),
+        }
+    }
+
+    // Joins the lines of a paragraph using hard line breaks
+    fn lines(&self, lines: &[String]) -> String {
+        match self {
+            DocFormat::Markdown => lines.join("\\\n"),
+            DocFormat::PlainText => lines.join("\n"),
+            DocFormat::Html => lines.join("<br>\n"),
+        }
+    }
+
+    fn paragraph(&self, str: &str) -> String {
+        match self {
+            DocFormat::Markdown | DocFormat::PlainText => format!("{}\n\n", str),
+            DocFormat::Html => format!("<p>{}</p>\n", str),
+        }
+    }
+}
+
+fn escape_markdown(str: &str) -> String {
+    let mut res = String::with_capacity(str.len());
+    for c in str.chars() {
+        match c {
+            '\\' => res.push_str("\\\\"),
+            '`' => res.push_str("\\`"),
+            '*' => res.push_str("\\*"),
+            '_' => res.push_str("\\_"),
+            '#' => res.push_str("\\#"),
+            '+' => res.push_str("\\+"),
+            '-' => res.push_str("\\-"),
+            '.' => res.push_str("\\."),
+            '!' => res.push_str("\\!"),
+            '~' => res.push_str("\\~"),
+            '{' => res.push_str("\\{"),
+            '}' => res.push_str("\\}"),
+            '[' => res.push_str("\\["),
+            ']' => res.push_str("\\]"),
+            '(' => res.push_str("\\("),
+            ')' => res.push_str("\\)"),
+            '<' => res.push_str("\\<"),
+            '>' => res.push_str("\\>"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+fn escape_html(str: &str) -> String {
+    let mut res = String::with_capacity(str.len());
+    for c in str.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&#39;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+// Renders the documentation of a flag.
+//
+// The documentation consists of the flag name and its abbreviation, the documentation
+// text and the tags of the flag. If given, the host / target counterpart of the flag
+// (e.g. `--host_copt` for `--copt`) is linked at the end.
+pub fn render_flag_documentation(
+    flag: &FlagInfo,
+    counterpart: Option<&FlagInfo>,
+    format: DocFormat,
+) -> String {
+    let mut result = String::new();
+
+    // First line: Flag name and short hand (if any)
+    let mut header = format.code(&format!("--{}", flag.name));
+    if let Some(abbr) = &flag.abbreviation {
+        header += &format!(" [{}]", format.code(&format!("-{}", abbr)));
+    }
+    if flag.has_negative_flag() {
+        header += &format!(", {}", format.code(&format!("--no{}", flag.name)));
+    }
+    result += &format.paragraph(&header);
+    // Followed by the documentation text
+    if let Some(doc) = &flag.documentation {
+        result += &format.paragraph(&format.escape(&doc.replace("%{product}", "Bazel")));
+    }
+    // And a list of tags
+    let mut tags = Vec::<String>::new();
+    let join_tags = |tags: &[String]| {
+        tags.iter()
+            .map(|t| t.to_lowercase())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if !flag.effect_tags.is_empty() {
+        tags.push(format!("Effect tags: {}", join_tags(&flag.effect_tags)));
+    }
+    if !flag.metadata_tags.is_empty() {
+        tags.push(format!("Tags: {}", join_tags(&flag.metadata_tags)));
+    }
+    if let Some(category) = &flag.documentation_category {
+        tags.push(format!("Category: {}", category.to_lowercase()));
+    }
+    if !tags.is_empty() {
+        result += &format.paragraph(&format.lines(&tags));
+    }
+
+    if let Some(counterpart) = counterpart {
+        let kind = if counterpart.name.starts_with("host_") {
+            "Host"
+        } else {
+            "Target"
+        };
+        let name = format!("--{}", counterpart.name);
+        let url = format!("{}#flag{}", FLAG_REFERENCE_URL, name);
+        result += &format.paragraph(&format!(
+            "{} counterpart: {}",
+            kind,
+            format.link(&name, &url)
+        ));
+    }
+
+    match format {
+        DocFormat::Html => result,
+        _ => result.trim_end().to_string() + "\n",
+    }
+}
+
+#[test]
+fn test_render_flag_documentation() {
+    let flag = FlagInfo {
+        name: "some_flag".to_string(),
+        abbreviation: Some("s".to_string()),
+        documentation: Some("Does <things> & `stuff`.".to_string()),
+        effect_tags: vec!["AFFECTS_OUTPUTS".to_string()],
+        documentation_category: Some("OUTPUT_PARAMETERS".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        render_flag_documentation(&flag, None, DocFormat::Markdown),
+        "`--some_flag` [`-s`]\n\nDoes \\<things\\> & \\`stuff\\`\\.\n\nEffect tags: affects_outputs\\\nCategory: output_parameters\n"
+    );
+    assert_eq!(
+        render_flag_documentation(&flag, None, DocFormat::PlainText),
+        "--some_flag [-s]\n\nDoes <things> & `stuff`.\n\nEffect tags: affects_outputs\nCategory: output_parameters\n"
+    );
+    assert_eq!(
+        render_flag_documentation(&flag, None, DocFormat::Html),
+        "<p><code>--some_flag</code> [<code>-s</code>]</p>\n<p>Does &lt;things&gt; &amp; `stuff`.</p>\n<p>Effect tags: affects_outputs<br>\nCategory: output_parameters</p>\n"
+    );
+
+    let host_flag = FlagInfo {
+        name: "host_some_flag".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        render_flag_documentation(&host_flag, Some(&flag), DocFormat::PlainText),
+        "--host_some_flag\n\nTarget counterpart: --some_flag (https://bazel.build/reference/command-line-reference#flag--some_flag)\n"
+    );
+}
@@ -198,6 +198,11 @@ fn test_pretty_print_combined_flags() {
         pretty_print("build --keep_going --foobar").unwrap(),
         "build --keep_going --foobar\n"
     );
+    // Flags which already have a value don't consume the following flag
+    assert_eq!(
+        pretty_print("build --copt=-O2 --copt -g").unwrap(),
+        "build --copt=-O2 --copt=-g\n"
+    );
 }
 
 #[test]
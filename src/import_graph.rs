@@ -0,0 +1,308 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chumsky::error::Simple;
+use dashmap::DashMap;
+use ropey::Rope;
+
+use crate::{
+    bazel_flags::{combine_key_value_flags, BazelFlags},
+    file_utils::{normalize_path, read_bazelrc, resolve_bazelrc_path},
+    parser::{parse_from_str, Line, ParserResult},
+    tokenizer::Span,
+};
+
+// A parsed bazelrc file
+#[derive(Debug)]
+pub struct ParsedFile {
+    pub rope: Rope,
+    pub lines: Vec<Line>,
+    pub parser_errors: Vec<Simple<char>>,
+}
+
+impl ParsedFile {
+    pub fn from_str(src: &str, bazel_flags: &BazelFlags) -> ParsedFile {
+        let ParserResult {
+            tokens: _,
+            mut lines,
+            errors,
+        } = parse_from_str(src);
+        combine_key_value_flags(&mut lines, bazel_flags);
+        ParsedFile {
+            rope: Rope::from_str(src),
+            lines,
+            parser_errors: errors,
+        }
+    }
+}
+
+// Cache of parsed bazelrc files.
+//
+// Files are loaded from disk on first access. The contents of open documents
+// can be provided using `update`, such that the editor's (potentially unsaved)
+// version of a document is used instead of the version on disk.
+#[derive(Debug, Default)]
+pub struct FileCache {
+    files: DashMap<PathBuf, Arc<ParsedFile>>,
+}
+
+impl FileCache {
+    // Returns the parsed file, loading it from disk if necessary.
+    // Returns `None` if the file does not exist or can't be read.
+    pub fn get(&self, path: &Path, bazel_flags: &BazelFlags) -> Option<Arc<ParsedFile>> {
+        let path = normalize_path(path);
+        if let Some(file) = self.files.get(&path) {
+            return Some(file.clone());
+        }
+        let src = read_bazelrc(&path).ok()?;
+        let file = Arc::new(ParsedFile::from_str(&src, bazel_flags));
+        self.files.insert(path, file.clone());
+        Some(file)
+    }
+
+    pub fn update(&self, path: &Path, file: Arc<ParsedFile>) {
+        self.files.insert(normalize_path(path), file);
+    }
+
+    // Forgets the cached version of a file, such that it is reloaded from disk on next access
+    pub fn invalidate(&self, path: &Path) {
+        self.files.remove(&normalize_path(path));
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportStatus {
+    Loaded,
+    // The imported file does not exist or can't be read
+    Missing,
+    // The imported file is already being imported, i.e. this import closes an import cycle
+    Cycle,
+    // The path could not be resolved, e.g. because `%workspace%` is used outside of a workspace
+    Unresolvable,
+}
+
+// An `import` or `try-import` statement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Import {
+    // The importing file
+    pub file: PathBuf,
+    pub line_nr: usize,
+    // The span of the imported path
+    pub span: Span,
+    // `true` for `try-import`
+    pub optional: bool,
+    pub target: Option<PathBuf>,
+    pub status: ImportStatus,
+}
+
+// The import graph, starting at one or more root bazelrc files
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportGraph {
+    pub roots: Vec<PathBuf>,
+    // All imports, in the order in which they are processed by Bazel
+    pub imports: Vec<Import>,
+    // The lines in the order in which Bazel reads them.
+    //
+    // Imports are expanded in place, i.e. a file importing another file in its
+    // 3rd line is split into two chunks, one for the lines before the import
+    // (including the import itself) and one for the lines after the import.
+    pub chunks: Vec<(PathBuf, Range<usize>)>,
+}
+
+// If the line is an `import` or `try-import`, returns the imported path and whether it is optional
+pub fn get_import_path(line: &Line) -> Option<(&(String, Span), bool)> {
+    let import = line.import.as_ref()?;
+    Some((import.path.as_ref()?, import.optional))
+}
+
+impl ImportGraph {
+    pub fn build(roots: &[PathBuf], cache: &FileCache, bazel_flags: &BazelFlags) -> ImportGraph {
+        let mut graph = ImportGraph::default();
+        for root in roots {
+            let root = normalize_path(root);
+            graph.roots.push(root.clone());
+            if let Some(file) = cache.get(&root, bazel_flags) {
+                graph.visit(&root, &file, cache, bazel_flags, &mut Vec::new());
+            }
+        }
+        graph
+    }
+
+    fn visit(
+        &mut self,
+        path: &Path,
+        file: &ParsedFile,
+        cache: &FileCache,
+        bazel_flags: &BazelFlags,
+        stack: &mut Vec<PathBuf>,
+    ) {
+        stack.push(path.to_path_buf());
+        let mut chunk_start = 0;
+        for (line_nr, line) in file.lines.iter().enumerate() {
+            let Some((raw_path, optional)) = get_import_path(line) else {
+                continue;
+            };
+            let target = resolve_bazelrc_path(path, &raw_path.0);
+            let mut import = Import {
+                file: path.to_path_buf(),
+                line_nr,
+                span: raw_path.1.clone(),
+                optional,
+                target: target.clone(),
+                status: ImportStatus::Unresolvable,
+            };
+            let Some(target) = target else {
+                self.imports.push(import);
+                continue;
+            };
+            if stack.contains(&target) {
+                import.status = ImportStatus::Cycle;
+                self.imports.push(import);
+                continue;
+            }
+            let Some(imported_file) = cache.get(&target, bazel_flags) else {
+                import.status = ImportStatus::Missing;
+                self.imports.push(import);
+                continue;
+            };
+            import.status = ImportStatus::Loaded;
+            self.imports.push(import);
+            self.chunks
+                .push((path.to_path_buf(), chunk_start..line_nr + 1));
+            chunk_start = line_nr + 1;
+            self.visit(&target, &imported_file, cache, bazel_flags, stack);
+        }
+        self.chunks
+            .push((path.to_path_buf(), chunk_start..file.lines.len()));
+        stack.pop();
+    }
+
+    // All files reachable from the roots, in the order they are first read by Bazel
+    pub fn files(&self) -> Vec<&Path> {
+        let mut files = Vec::<&Path>::new();
+        for (path, _) in &self.chunks {
+            if !files.contains(&path.as_path()) {
+                files.push(path);
+            }
+        }
+        files
+    }
+
+    // The files which are always read, i.e. the roots and all files reachable from them
+    // through `import` statements. Files only reachable through `try-import` are excluded.
+    pub fn required_files(&self) -> Vec<&Path> {
+        let mut files = self
+            .roots
+            .iter()
+            .filter(|r| self.chunks.iter().any(|c| &c.0 == *r))
+            .map(|r| r.as_path())
+            .collect::<Vec<_>>();
+        // Imports are listed in the order they are visited, i.e. an import is
+        // always listed after the import which led to the importing file.
+        for import in &self.imports {
+            if import.optional || import.status != ImportStatus::Loaded {
+                continue;
+            }
+            let target = import.target.as_deref().unwrap();
+            if files.contains(&import.file.as_path()) && !files.contains(&target) {
+                files.push(target);
+            }
+        }
+        files
+    }
+
+    pub fn imports_from<'a>(&'a self, file: &'a Path) -> impl Iterator<Item = &'a Import> + 'a {
+        self.imports.iter().filter(move |i| i.file == file)
+    }
+}
+
+#[cfg(test)]
+pub fn create_test_workspace(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bazelrc-lsp-test-{}", name));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("MODULE.bazel"), "").unwrap();
+    for (name, contents) in files {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_resolve_import_paths() {
+    let ws = create_test_workspace("resolve", &[("tools/ci.bazelrc", "")]);
+    let rc = ws.join("tools/ci.bazelrc");
+    // `%workspace%` is replaced by the workspace root
+    assert_eq!(
+        resolve_bazelrc_path(&rc, "%workspace%/tools/x.bazelrc"),
+        Some(ws.join("tools/x.bazelrc"))
+    );
+    // Relative paths are resolved against the workspace root
+    assert_eq!(
+        resolve_bazelrc_path(&rc, "user.bazelrc"),
+        Some(ws.join("user.bazelrc"))
+    );
+    assert_eq!(
+        resolve_bazelrc_path(&rc, "./tools/../user.bazelrc"),
+        Some(ws.join("user.bazelrc"))
+    );
+    // Absolute paths are kept as is
+    assert_eq!(
+        resolve_bazelrc_path(&rc, "/etc/bazel.bazelrc"),
+        Some(PathBuf::from("/etc/bazel.bazelrc"))
+    );
+}
+
+#[test]
+fn test_import_graph() {
+    let ws = create_test_workspace(
+        "graph",
+        &[
+            (
+                ".bazelrc",
+                "build -k\nimport %workspace%/tools/ci.bazelrc\ntry-import %workspace%/user.bazelrc\nbuild -c opt",
+            ),
+            ("tools/ci.bazelrc", "build:ci -k\nimport %workspace%/.bazelrc"),
+        ],
+    );
+    let bazel_flags = crate::bazel_flags::load_bazel_flags();
+    let cache = FileCache::default();
+    let graph = ImportGraph::build(&[ws.join(".bazelrc")], &cache, &bazel_flags);
+
+    // Imports are expanded in place
+    assert_eq!(
+        graph.chunks,
+        vec![
+            (ws.join(".bazelrc"), 0..2),
+            (ws.join("tools/ci.bazelrc"), 0..2),
+            (ws.join(".bazelrc"), 2..4),
+        ]
+    );
+    assert_eq!(
+        graph.files(),
+        vec![ws.join(".bazelrc"), ws.join("tools/ci.bazelrc")]
+    );
+    assert_eq!(
+        graph.required_files(),
+        vec![ws.join(".bazelrc"), ws.join("tools/ci.bazelrc")]
+    );
+
+    // Import cycles and missing files are detected
+    assert_eq!(
+        graph
+            .imports
+            .iter()
+            .map(|i| (i.file.clone(), i.line_nr, i.optional, i.status.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (ws.join(".bazelrc"), 1, false, ImportStatus::Loaded),
+            (ws.join("tools/ci.bazelrc"), 1, false, ImportStatus::Cycle),
+            (ws.join(".bazelrc"), 2, true, ImportStatus::Missing),
+        ]
+    );
+}
@@ -1,13 +1,45 @@
+//! Code intelligence for bazelrc files.
+//!
+//! Besides the language server, the analysis is available as a library, such that other
+//! tools (e.g. pre-commit hooks) can use the same logic without speaking LSP:
+//!
+//! ```
+//! use bazelrc_lsp::{analyze, bazel_flags::load_bazel_flags, format, AnalyzeOptions};
+//!
+//! let bazel_flags = load_bazel_flags();
+//! let diagnostics = analyze("build --unknown_flag", &bazel_flags, &AnalyzeOptions::default());
+//! assert_eq!(diagnostics.len(), 1);
+//! assert_eq!(format("build  --jobs 8").as_deref(), Some("build --jobs=8\n"));
+//! ```
+
+pub mod analysis;
+pub mod bazel_cli;
 pub mod bazel_flags;
+pub mod bazel_version;
+pub mod code_actions;
 pub mod completion;
+pub mod config_index;
+pub mod cst;
 pub mod diagnostic;
+pub mod diagnostic_renderer;
+pub mod effective_config;
 pub mod file_utils;
+pub mod flag_docs;
 pub mod formatting;
+pub mod import_graph;
 pub mod line_index;
 pub mod lsp_utils;
 pub mod parser;
+pub mod project;
+pub mod semantic_diff;
+pub mod semantic_model;
 pub mod semantic_token;
+pub mod settings;
+pub mod suggestions;
 pub mod tokenizer;
+pub mod upgrade_check;
+
+pub use analysis::{analyze, format, parse, AnalyzeOptions};
 
 pub mod bazel_flags_proto {
     include!(concat!(env!("OUT_DIR"), "/protobuf/bazel_flags.rs"));
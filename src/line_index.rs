@@ -69,17 +69,14 @@ impl IndexedLines {
         }
     }
 
+    // Lines and tokens don't overlap, i.e. only the last entry starting at or before
+    // the position can contain it
     pub fn find_linenr_at_position(&self, pos: usize) -> Option<usize> {
         self.reverse_line_idx
-            .values()
-            .find(|e| self.lines[**e].span.contains(&pos))
-            .copied()
-        /* TODO use 'upper_bound'
-        self.reverse_idx
-            .upper_bound(Bound::Included(&pos))
-            .value()
-            .filter(|s| s.span.contains(&pos))
-        */
+            .range(..=pos)
+            .next_back()
+            .map(|(_, line_nr)| *line_nr)
+            .filter(|line_nr| self.lines[*line_nr].span.contains(&pos))
     }
 
     pub fn find_line_at_position(&self, pos: usize) -> Option<&Line> {
@@ -89,9 +86,10 @@ impl IndexedLines {
 
     pub fn find_symbol_at_position(&self, pos: usize) -> Option<&IndexEntry> {
         self.reverse_token_idx
-            .values()
-            .find(|e| e.span.contains(&pos))
-        /* TODO use 'upper_bound' */
+            .range(..=pos)
+            .next_back()
+            .map(|(_, entry)| entry)
+            .filter(|e| e.span.contains(&pos))
     }
 }
 
@@ -137,4 +135,9 @@ build:opt --upload_results=false
     ]));
 
     assert_eq!(index.find_symbol_at_position(20).unwrap().kind, IndexEntryKind::FlagName(0));
+    assert_eq!(index.find_symbol_at_position(30).unwrap().kind, IndexEntryKind::FlagValue(0));
+    assert_eq!(index.find_symbol_at_position(31), None);
+    assert_eq!(index.find_symbol_at_position(8), None);
+    assert_eq!(index.find_symbol_at_position(77).unwrap().line_nr, 2);
+    assert_eq!(index.find_symbol_at_position(78), None);
 }
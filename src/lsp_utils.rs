@@ -3,15 +3,30 @@ use tower_lsp::lsp_types::{Position, Range};
 
 use crate::tokenizer::Span;
 
+// Conversion between `Span`s and LSP positions.
+//
+// Spans are char offsets into the document, while LSP positions count UTF-16 code units
+// within a line, i.e. characters outside of the Basic Multilingual Plane (e.g. emojis)
+// take up two columns. Positions pointing into the middle of such a character or past
+// the end of a line are clamped to the character boundary or to the end of the line.
+
 pub fn lsp_pos_to_offset(rope: &Rope, pos: &Position) -> Option<usize> {
-    let char = rope.try_line_to_char(pos.line as usize).ok()?;
-    Some(char + pos.character as usize)
+    let line_start = rope.try_line_to_char(pos.line as usize).ok()?;
+    let line = rope.get_line(pos.line as usize)?;
+    let mut line_len = line.len_chars();
+    while line_len > 0 && matches!(line.char(line_len - 1), '\n' | '\r') {
+        line_len -= 1;
+    }
+    let column = line
+        .try_utf16_cu_to_char((pos.character as usize).min(line.len_utf16_cu()))
+        .ok()?;
+    Some(line_start + column.min(line_len))
 }
 
 pub fn offset_to_lsp_pos(rope: &Rope, pos: usize) -> Option<Position> {
-    let line = rope.try_byte_to_line(pos).ok()?;
+    let line = rope.try_char_to_line(pos).ok()?;
     let first = rope.try_line_to_char(line).ok()?;
-    let character = rope.try_byte_to_char(pos).ok()? - first;
+    let character = rope.try_char_to_utf16_cu(pos).ok()? - rope.char_to_utf16_cu(first);
     Some(Position {
         line: line.try_into().ok()?,
         character: character.try_into().ok()?,
@@ -24,3 +39,110 @@ pub fn range_to_lsp(rope: &Rope, span: &Span) -> Option<Range> {
         end: offset_to_lsp_pos(rope, span.end)?,
     })
 }
+
+// The 0-based char column of an LSP position, e.g. for printing positions on the
+// command line
+pub fn lsp_pos_to_column(rope: &Rope, pos: &Position) -> usize {
+    match lsp_pos_to_offset(rope, pos) {
+        Some(offset) => offset - rope.line_to_char(pos.line as usize),
+        None => pos.character as usize,
+    }
+}
+
+// Finds `http://` and `https://` URLs within the text, e.g. within a comment.
+// The returned spans are char offsets relative to the start of the text.
+pub fn find_urls(text: &str) -> Vec<(String, Span)> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let is_url_char = |c: char| !c.is_whitespace() && !matches!(c, '"' | '\'' | '<' | '>' | '`');
+    let mut urls = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let rest = chars[pos..].iter().take(8).collect::<String>();
+        let preceded_by_word = pos > 0 && chars[pos - 1].is_alphanumeric();
+        if preceded_by_word || !(rest.starts_with("http://") || rest.starts_with("https://")) {
+            pos += 1;
+            continue;
+        }
+        let mut end = pos;
+        while end < chars.len() && is_url_char(chars[end]) {
+            end += 1;
+        }
+        // Trailing punctuation usually belongs to the surrounding sentence
+        while end > pos {
+            let url = &chars[pos..end];
+            let last = url[url.len() - 1];
+            let unbalanced_paren = last == ')'
+                && url.iter().filter(|c| **c == '(').count()
+                    < url.iter().filter(|c| **c == ')').count();
+            if matches!(last, '.' | ',' | ';' | ':' | '!' | '?') || unbalanced_paren {
+                end -= 1;
+            } else {
+                break;
+            }
+        }
+        let url = chars[pos..end].iter().collect::<String>();
+        if !url.ends_with("//") {
+            urls.push((url, pos..end));
+        }
+        pos = end.max(pos + 1);
+    }
+    urls
+}
+
+#[test]
+fn test_find_urls() {
+    assert_eq!(
+        find_urls("# See https://github.com/bazelbuild/bazel/issues/1234."),
+        vec![(
+            "https://github.com/bazelbuild/bazel/issues/1234".to_string(),
+            6..53
+        )]
+    );
+    assert_eq!(
+        find_urls("#(http://a.com/x_(y)) and http://b.com"),
+        vec![
+            ("http://a.com/x_(y)".to_string(), 2..20),
+            ("http://b.com".to_string(), 26..38)
+        ]
+    );
+    assert_eq!(
+        find_urls("# ünïcode http://ä.de"),
+        vec![("http://ä.de".to_string(), 10..21)]
+    );
+    assert_eq!(find_urls("# no urls: http:// fttp://x.com"), vec![]);
+}
+
+#[test]
+fn test_position_conversion() {
+    let rope = Rope::from_str("# ä😀x\nbuild --a=😀 --b\r\n");
+    // `😀` takes up two UTF-16 code units, but is a single char
+    assert_eq!(offset_to_lsp_pos(&rope, 3), Some(Position::new(0, 3)));
+    assert_eq!(offset_to_lsp_pos(&rope, 4), Some(Position::new(0, 5)));
+    assert_eq!(offset_to_lsp_pos(&rope, 6), Some(Position::new(1, 0)));
+    assert_eq!(offset_to_lsp_pos(&rope, 17), Some(Position::new(1, 12)));
+    assert_eq!(offset_to_lsp_pos(&rope, 100), None);
+    assert_eq!(lsp_pos_to_offset(&rope, &Position::new(0, 5)), Some(4));
+    assert_eq!(lsp_pos_to_offset(&rope, &Position::new(1, 13)), Some(18));
+    // Positions within a surrogate pair or past the end of the line are clamped
+    assert_eq!(lsp_pos_to_offset(&rope, &Position::new(0, 4)), Some(3));
+    assert_eq!(lsp_pos_to_offset(&rope, &Position::new(1, 100)), Some(21));
+    assert_eq!(lsp_pos_to_offset(&rope, &Position::new(5, 0)), None);
+    for offset in 0..rope.len_chars() {
+        let pos = offset_to_lsp_pos(&rope, offset).unwrap();
+        if pos == Position::new(1, 17) {
+            // The `\n` of a `\r\n` line ending maps back to the end of the line
+            continue;
+        }
+        assert_eq!(lsp_pos_to_offset(&rope, &pos), Some(offset));
+    }
+    assert_eq!(lsp_pos_to_column(&rope, &Position::new(1, 12)), 11);
+    assert_eq!(
+        range_to_lsp(&rope, &(16..17)),
+        Some(Range::new(Position::new(1, 10), Position::new(1, 12)))
+    );
+
+    // Only `\n`, `\r\n` and `\r` are line breaks
+    let rope = Rope::from_str("# a\u{2028}b\u{85}c\rd");
+    assert_eq!(offset_to_lsp_pos(&rope, 7), Some(Position::new(0, 7)));
+    assert_eq!(offset_to_lsp_pos(&rope, 8), Some(Position::new(1, 0)));
+}
@@ -1,22 +1,111 @@
+use bazelrc_lsp::analysis::{analyze_lines, analyze_parsed, postprocess_diagnostics};
+use bazelrc_lsp::bazel_cli::{
+    canonicalize_flags, get_canonical_flags_edit, get_line_args, BazelError,
+};
 use bazelrc_lsp::bazel_flags::{
-    combine_key_value_flags, load_bazel_flags, BazelFlags, COMMAND_DOCS,
+    combine_key_value_flags, load_bazel_flags, load_bazel_flags_for_release,
+    load_bazel_flags_for_version, BazelFlags, COMMAND_DOCS,
+};
+use bazelrc_lsp::bazel_version::{
+    determine_bazel_version, BazelVersion, VersionSource, FLAG_DUMP_VERSIONS,
+};
+use bazelrc_lsp::code_actions::{get_config_rename_edit, get_config_stub_edit};
+use bazelrc_lsp::completion::{get_completion_items, FlagCompletions};
+use bazelrc_lsp::config_index::{collect_flag_aliases, is_config_flag, ConfigIndex};
+use bazelrc_lsp::diagnostic::{
+    diagnostics_from_validation, AbbreviatedFlagData, DiagnosticKind, UndefinedConfigData,
+    UnknownFlagData,
+};
+use bazelrc_lsp::diagnostic_renderer::render_diagnostic;
+use bazelrc_lsp::effective_config::{get_parent_command, EffectiveConfig};
+use bazelrc_lsp::file_utils::{
+    decode_bazelrc, find_bazelrc_files, get_default_rc_files, get_home_path, get_workspace_path,
+    is_bazelrc_file, normalize_path, read_bazelrc, resolve_bazelrc_path,
 };
-use bazelrc_lsp::completion::get_completion_items;
-use bazelrc_lsp::diagnostic::{diagnostics_from_parser, diagnostics_from_rcconfig};
-use bazelrc_lsp::file_utils::resolve_bazelrc_path;
-use bazelrc_lsp::formatting::get_text_edits_for_lines;
+use bazelrc_lsp::flag_docs::DocFormat;
+use bazelrc_lsp::formatting::{get_text_edits_for_lines, pretty_print};
+use bazelrc_lsp::import_graph::{FileCache, ImportGraph, ParsedFile};
 use bazelrc_lsp::line_index::{IndexEntry, IndexEntryKind, IndexedLines};
-use bazelrc_lsp::lsp_utils::{lsp_pos_to_offset, range_to_lsp};
-use bazelrc_lsp::parser::{parse_from_str, Line, ParserResult};
+use bazelrc_lsp::lsp_utils::{
+    find_urls, lsp_pos_to_column, lsp_pos_to_offset, offset_to_lsp_pos, range_to_lsp,
+};
+use bazelrc_lsp::parser::{parse_from_str, reparse_incremental, Line, ParserResult};
+use bazelrc_lsp::project::Project;
+use bazelrc_lsp::semantic_diff::{diff_commands, diff_effective_configs, format_diff};
+use bazelrc_lsp::semantic_model::{LineKind, RcFile};
 use bazelrc_lsp::semantic_token::{
     convert_to_lsp_tokens, semantic_tokens_from_lines, RCSemanticToken, LEGEND_TYPE,
 };
+use bazelrc_lsp::settings::{BannedFlag, Policy, Settings, POLICY_FILE_NAME};
+use bazelrc_lsp::upgrade_check::{find_default_changes, find_upgrade_issues};
+use bazelrc_lsp::{parse, AnalyzeOptions};
+use chumsky::error::Simple;
+use clap::builder::PossibleValuesParser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use dashmap::DashMap;
 use ropey::Rope;
+use std::collections::BTreeSet;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, RwLock};
 use tower_lsp::jsonrpc::{Error, Result};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
+// Canonicalizes the flags of a line using `bazel canonicalize-flags`
+const CANONICALIZE_FLAGS_COMMAND: &str = "bazelrc.canonicalizeFlags";
+// Shows the references of a config, used by the code lenses
+const SHOW_REFERENCES_COMMAND: &str = "bazelrc.showReferences";
+
+// A config, as returned by the `bazelrc/listConfigs` request
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigInfo {
+    name: String,
+    flag_count: usize,
+    unused: bool,
+    definitions: Vec<ConfigLocation>,
+    references: Vec<ConfigLocation>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigLocation {
+    location: Location,
+    // The command of the line, e.g. `build` for `build:ci` or `build --config=ci`
+    command: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EffectiveFlagsParams {
+    command: String,
+    #[serde(default)]
+    configs: Vec<String>,
+    // A document within the workspace. Defaults to the first workspace folder.
+    text_document: Option<TextDocumentIdentifier>,
+}
+
+// A flag, as returned by the `bazelrc/effectiveFlags` request
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EffectiveFlagInfo {
+    flag: String,
+    location: Option<Location>,
+    // The `command:config` section the flag originates from
+    section: String,
+    // The `--config` expansions which lead to this flag, outermost first
+    via: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EffectiveFlagsResult {
+    flags: Vec<EffectiveFlagInfo>,
+    errors: Vec<String>,
+}
+
 struct TextDocumentItem {
     uri: Url,
     text: String,
@@ -28,61 +117,657 @@ struct AnalyzedDocument {
     rope: Rope,
     semantic_tokens: Vec<RCSemanticToken>,
     indexed_lines: IndexedLines,
-    parser_errors: Vec<chumsky::prelude::Simple<char>>,
+    // The lines, resolved against the flag metadata
+    model: RcFile,
+    // The parser result, before combining `--flag value` pairs. Reused when re-parsing
+    // the document after an edit
+    parser_result: ParserResult,
+    version: i32,
+    // The diagnostics computed by the language server itself
+    diagnostics: Vec<Diagnostic>,
 }
 
 #[derive(Debug)]
 struct Backend {
     client: Client,
     document_map: DashMap<String, AnalyzedDocument>,
-    bazel_flags: BazelFlags,
+    file_cache: FileCache,
+    // The configs defined across all open, discovered and imported bazelrc files
+    config_index: RwLock<ConfigIndex>,
+    // The Bazel version used in the workspace, as determined by Bazelisk's rules
+    bazel_version: RwLock<Option<BazelVersion>>,
+    // The first workspace folder
+    workspace_root: RwLock<Option<PathBuf>>,
+    settings: RwLock<Settings>,
+    // The diagnostics reported by `bazel canonicalize-flags` for each document
+    bazel_diagnostics: DashMap<String, Vec<Diagnostic>>,
+    bazel_flags: Arc<BazelFlags>,
+    flag_completions: FlagCompletions,
+    // Whether the client pulls diagnostics instead of receiving them via `publishDiagnostics`
+    pull_diagnostics: RwLock<bool>,
+    // Whether the client supports snippets in completion items
+    snippet_support: RwLock<bool>,
+    // The client's support for watching files for us, see `did_change_watched_files`
+    watch_files: RwLock<Option<DidChangeWatchedFilesClientCapabilities>>,
+    // The policy files of the workspaces, loaded on first use. Reloaded when the client
+    // reports changes to them. Without file watching, changes apply after a restart.
+    policies: DashMap<PathBuf, Policy>,
 }
 
 impl Backend {
+    // Adds all bazelrc files reachable from the given roots to the config index.
+    // Files which are already indexed are only re-indexed if listed in `updated`.
+    fn index_files(&self, roots: &[PathBuf], updated: Option<&Path>) {
+        let graph = ImportGraph::build(roots, &self.file_cache, &self.bazel_flags);
+        let mut config_index = self.config_index.write().unwrap();
+        for path in graph.files() {
+            let Ok(uri) = Url::from_file_path(path) else {
+                continue;
+            };
+            if config_index.contains_file(uri.as_str()) && updated != Some(path) {
+                continue;
+            }
+            if let Some(file) = self.file_cache.get(path, &self.bazel_flags) {
+                config_index.update_file(uri.as_str(), &file.rope, &file.lines, &self.bazel_flags);
+            }
+        }
+    }
+
+    // The effective configuration for the workspace of the given file. In case the file
+    // is not read by Bazel by default, it is treated as if it were passed as `--bazelrc`.
+    fn get_effective_config(&self, file_path: &Path) -> EffectiveConfig<'_> {
+        EffectiveConfig::new(
+            &self.get_rc_files(file_path),
+            &self.file_cache,
+            &self.bazel_flags,
+        )
+    }
+
+    // All rc files Bazel reads for the workspace of the given file
+    fn get_project(&self, file_path: &Path) -> Project {
+        Project::for_file(file_path, &self.file_cache, &self.bazel_flags)
+    }
+
+    // The rc files Bazel reads for the workspace of the given file, plus the file itself
+    // if it is not read by default
+    fn get_rc_files(&self, file_path: &Path) -> Vec<PathBuf> {
+        self.get_project(file_path).rc_files
+    }
+
+    // All places within the project setting the flag at the given position
+    fn find_flag_locations(&self, position: &TextDocumentPositionParams) -> Option<Vec<Location>> {
+        let doc = self.document_map.get(position.text_document.uri.as_str())?;
+        let pos = lsp_pos_to_offset(&doc.rope, &position.position)?;
+        let symbol = doc.indexed_lines.find_symbol_at_position(pos)?;
+        let IndexEntryKind::FlagName(flag_nr) = symbol.kind else {
+            return None;
+        };
+        let name = doc.model.flag(symbol.line_nr, flag_nr)?.flag.name.clone()?;
+        drop(doc);
+        let file_path = position.text_document.uri.to_file_path().ok()?;
+        let project = self.get_project(&file_path);
+        let locations = project
+            .find_flag(&name.0, &self.bazel_flags)
+            .into_iter()
+            .filter_map(|l| {
+                let file = project.get_file(&l.file)?;
+                Some(Location {
+                    uri: Url::from_file_path(&l.file).ok()?,
+                    range: range_to_lsp(&file.rope, &l.span)?,
+                })
+            })
+            .collect();
+        Some(locations)
+    }
+
+    fn get_config_hover(
+        &self,
+        uri: &Url,
+        rope: &Rope,
+        span: &std::ops::Range<usize>,
+        command: Option<&str>,
+    ) -> Option<Hover> {
+        let config_index = self.config_index.read().unwrap();
+        let name = config_index.find_config_at(uri.as_str(), span.end)?;
+        let mut docs = config_index.get_config_documentation(name)?;
+
+        // Show the flags this config expands to
+        if let (Some(command), Ok(file_path)) = (command, uri.to_file_path()) {
+            let command = match command {
+                "common" | "always" => "build",
+                _ => command,
+            };
+            let expansion = self
+                .get_effective_config(&file_path)
+                .expand_config(command, name);
+            docs += &format!("\n\nExpands to (for `{}`):\n```bazelrc\n", command);
+            for flag in &expansion.flags {
+                docs += &flag.format_flag();
+                docs += "\n";
+            }
+            docs += "```";
+            for error in &expansion.errors {
+                docs += &format!("\n\n⚠️ {}", error);
+            }
+        }
+
+        Some(Hover {
+            contents: HoverContents::Scalar(MarkedString::String(docs)),
+            range: range_to_lsp(rope, span),
+        })
+    }
+
+    // All locations at which the config at the given position is defined or referenced
+    fn find_config_locations(
+        &self,
+        position: &TextDocumentPositionParams,
+        include_definitions: bool,
+    ) -> Result<Option<(String, Vec<Location>)>> {
+        let uri = position.text_document.uri.to_string();
+        let doc = self
+            .document_map
+            .get(&uri)
+            .ok_or(Error::invalid_params("Unknown document!"))?;
+        let pos = lsp_pos_to_offset(&doc.rope, &position.position)
+            .ok_or(Error::invalid_params("Position out of range"))?;
+        let config_index = self.config_index.read().unwrap();
+        let Some(name) = config_index.find_config_at(&uri, pos) else {
+            return Ok(None);
+        };
+        let entry = &config_index.configs[name];
+        let definitions = entry
+            .definitions
+            .iter()
+            .filter(|_| include_definitions)
+            .map(|d| (&d.file, d.range));
+        let usages = entry.usages.iter().map(|u| (&u.file, u.range));
+        let locations = definitions
+            .chain(usages)
+            .filter_map(|(file, range)| {
+                Some(Location {
+                    uri: Url::parse(file).ok()?,
+                    range,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Some((name.to_string(), locations)))
+    }
+
     async fn on_change(&self, params: TextDocumentItem) {
         let rope = ropey::Rope::from_str(&params.text);
-        let src = rope.to_string();
+        let parser_result = parse_from_str(&params.text);
+        self.update_document(params.uri, params.version, rope, parser_result)
+            .await
+    }
 
-        let file_path_buf = params.uri.to_file_path().ok();
+    async fn update_document(
+        &self,
+        uri: Url,
+        version: i32,
+        rope: Rope,
+        parser_result: ParserResult,
+    ) {
+        let file_path_buf = uri.to_file_path().ok();
         let file_path = file_path_buf.as_deref();
 
         let ParserResult {
             tokens: _,
             mut lines,
             errors,
-        } = parse_from_str(&src);
+        } = parser_result.clone();
         combine_key_value_flags(&mut lines, &self.bazel_flags);
         let semantic_tokens = semantic_tokens_from_lines(&lines);
+
+        // Make the editor's version of the document available to other documents importing it
+        if let Some(file_path) = file_path {
+            self.file_cache.update(
+                file_path,
+                Arc::new(ParsedFile {
+                    rope: rope.clone(),
+                    lines: lines.clone(),
+                    parser_errors: errors.clone(),
+                }),
+            );
+        }
+        let model = RcFile::new(&lines, &self.bazel_flags);
         let indexed_lines = IndexedLines::from_lines(lines);
 
-        let mut diagnostics: Vec<Diagnostic> = Vec::<Diagnostic>::new();
-        diagnostics.extend(diagnostics_from_parser(&rope, &errors));
-        diagnostics.extend(diagnostics_from_rcconfig(
-            &rope,
-            &indexed_lines.lines,
-            &self.bazel_flags,
-            file_path,
-        ));
+        // Update the config index, also indexing all files imported by this document
+        match file_path {
+            Some(file_path) => {
+                let file_path = normalize_path(file_path);
+                self.index_files(std::slice::from_ref(&file_path), Some(&file_path));
+            }
+            None => self.config_index.write().unwrap().update_file(
+                uri.as_str(),
+                &rope,
+                &indexed_lines.lines,
+                &self.bazel_flags,
+            ),
+        }
+
+        let diagnostics = self
+            .compute_diagnostics(&rope, &indexed_lines.lines, &errors, file_path)
+            .await;
 
         self.document_map.insert(
-            params.uri.to_string(),
+            uri.to_string(),
             AnalyzedDocument {
                 rope,
-                parser_errors: errors,
+                parser_result,
                 semantic_tokens,
                 indexed_lines,
+                model,
+                version,
+                diagnostics: diagnostics.clone(),
             },
         );
+        // Bazel's diagnostics refer to the previous version of the document
+        self.bazel_diagnostics.remove(uri.as_str());
 
-        self.client
-            .publish_diagnostics(params.uri.clone(), diagnostics, Some(params.version))
+        self.publish_diagnostics(uri, diagnostics, version).await;
+    }
+
+    // Pushes the diagnostics to the client, unless the client pulls them itself
+    async fn publish_diagnostics(&self, uri: Url, diagnostics: Vec<Diagnostic>, version: i32) {
+        let pull_diagnostics = *self.pull_diagnostics.read().unwrap();
+        if !pull_diagnostics {
+            self.client
+                .publish_diagnostics(uri, diagnostics, Some(version))
+                .await;
+        }
+    }
+
+    // The policy of the given workspace. Cached, since diagnostics are computed on every change.
+    async fn load_policy(&self, workspace: &Path) -> Policy {
+        if let Some(policy) = self.policies.get(workspace) {
+            return policy.clone();
+        }
+        let policy = match Policy::load(workspace) {
+            Ok(policy) => policy,
+            Err(e) => {
+                self.client.log_message(MessageType::WARNING, e).await;
+                Policy::default()
+            }
+        };
+        self.policies
+            .insert(workspace.to_path_buf(), policy.clone());
+        policy
+    }
+
+    // Recomputes the diagnostics of all open documents, e.g. after the settings changed
+    async fn recompute_diagnostics(&self) {
+        let documents = self
+            .document_map
+            .iter()
+            .filter_map(|doc| {
+                Some(TextDocumentItem {
+                    uri: Url::parse(doc.key()).ok()?,
+                    text: doc.rope.to_string(),
+                    version: doc.version,
+                })
+            })
+            .collect::<Vec<_>>();
+        for document in documents {
+            self.on_change(document).await;
+        }
+    }
+
+    // Asks the client to pull the diagnostics again, e.g. after Bazel's validation finished
+    async fn refresh_diagnostics(&self) {
+        let pull_diagnostics = *self.pull_diagnostics.read().unwrap();
+        if pull_diagnostics {
+            let _ = self.client.workspace_diagnostic_refresh().await;
+        }
+    }
+
+    // The diagnostics for the given file. Open documents use their stored diagnostics,
+    // other files are analyzed from disk.
+    async fn document_diagnostics(&self, uri: &Url) -> (Vec<Diagnostic>, Option<i32>) {
+        if let Some((mut diagnostics, version)) = self
+            .document_map
+            .get(uri.as_str())
+            .map(|doc| (doc.diagnostics.clone(), doc.version))
+        {
+            if let Some(bazel_diagnostics) = self.bazel_diagnostics.get(uri.as_str()) {
+                diagnostics.extend(bazel_diagnostics.iter().cloned());
+            }
+            return (diagnostics, Some(version));
+        }
+        let Some(file_path) = uri.to_file_path().ok() else {
+            return (vec![], None);
+        };
+        let Some(file) = self.file_cache.get(&file_path, &self.bazel_flags) else {
+            return (vec![], None);
+        };
+        let diagnostics = self
+            .compute_diagnostics(
+                &file.rope,
+                &file.lines,
+                &file.parser_errors,
+                Some(&file_path),
+            )
+            .await;
+        (diagnostics, None)
+    }
+
+    // The diagnostics computed by the language server itself, i.e. without the diagnostics
+    // from `bazel canonicalize-flags`
+    async fn compute_diagnostics(
+        &self,
+        rope: &Rope,
+        lines: &[Line],
+        errors: &[Simple<char>],
+        file_path: Option<&Path>,
+    ) -> Vec<Diagnostic> {
+        let flag_aliases = self
+            .config_index
+            .read()
+            .unwrap()
+            .flag_aliases
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let settings = self.settings.read().unwrap().clone();
+        let mut banned_flags = settings.banned_flags.clone();
+        if let Some(workspace) = file_path.and_then(get_workspace_path) {
+            banned_flags.extend(self.load_policy(&workspace).await.banned_flags);
+        }
+        let options = self.analyze_options(&settings, file_path, banned_flags);
+        analyze_lines(
+            rope,
+            lines,
+            errors,
+            &self.bazel_flags,
+            &self.file_cache,
+            &flag_aliases,
+            &options,
+        )
+    }
+
+    // The options of the diagnostics pipeline shared with `bazelrc-lsp lint`, as configured by
+    // the settings
+    fn analyze_options(
+        &self,
+        settings: &Settings,
+        file_path: Option<&Path>,
+        banned_flags: Vec<BannedFlag>,
+    ) -> AnalyzeOptions {
+        AnalyzeOptions {
+            file_path: file_path.map(|p| p.to_path_buf()),
+            strict: settings.strict,
+            detect_secrets: settings.detect_secrets,
+            report_abbreviations: settings.report_abbreviations,
+            report_experimental_flags: settings.report_experimental_flags,
+            report_redundant_defaults: settings.report_redundant_defaults,
+            disabled: Vec::new(),
+            severity_overrides: settings.diagnostics.clone(),
+            bazel_version: self.bazel_version.read().unwrap().clone(),
+            banned_flags,
+            upgrade_target_version: settings
+                .upgrade_target_version
+                .as_deref()
+                .and_then(|v| BazelVersion::parse(v, VersionSource::Settings)),
+        }
+    }
+
+    // Handler for `bazelrc/listConfigs`: all configs from the config index, e.g. for
+    // showing a tree view of the configs within the client
+    async fn list_configs(&self) -> Result<Vec<ConfigInfo>> {
+        let config_index = self.config_index.read().unwrap();
+        let unused = config_index.unused_configs().collect::<Vec<_>>();
+        let to_location = |file: &str, range: Range, command: Option<&str>| {
+            Some(ConfigLocation {
+                location: Location {
+                    uri: Url::parse(file).ok()?,
+                    range,
+                },
+                command: command.map(|c| c.to_string()),
+            })
+        };
+        Ok(config_index
+            .configs
+            .iter()
+            .map(|(name, entry)| ConfigInfo {
+                name: name.clone(),
+                flag_count: entry.flag_count(),
+                unused: unused.contains(&name.as_str()),
+                definitions: entry
+                    .definitions
+                    .iter()
+                    .filter_map(|d| to_location(&d.file, d.range, Some(&d.command)))
+                    .collect(),
+                references: entry
+                    .usages
+                    .iter()
+                    .filter_map(|u| to_location(&u.file, u.range, u.command.as_deref()))
+                    .collect(),
+            })
+            .collect())
+    }
+
+    // Handler for `bazelrc/effectiveFlags`: the flags Bazel applies for a command, in order
+    async fn effective_flags(&self, params: EffectiveFlagsParams) -> Result<EffectiveFlagsResult> {
+        if !self.bazel_flags.commands.contains(&params.command) && params.command != "startup" {
+            return Err(Error::invalid_params(format!(
+                "Unknown command {:?}",
+                params.command
+            )));
+        }
+        let document_path = params
+            .text_document
+            .map(|d| {
+                d.uri
+                    .to_file_path()
+                    .map_err(|_| Error::invalid_params("Unsupported URI scheme!"))
+            })
+            .transpose()?;
+        let effective_config = match &document_path {
+            Some(path) => self.get_effective_config(path),
+            None => {
+                let workspace = self.workspace_root.read().unwrap().clone();
+                let rc_files = get_default_rc_files(workspace.as_deref());
+                EffectiveConfig::new(&rc_files, &self.file_cache, &self.bazel_flags)
+            }
+        };
+        let expansion = effective_config.expand(&params.command, &params.configs);
+
+        let flags = expansion
+            .flags
+            .iter()
+            .map(|flag| {
+                let location = || -> Option<Location> {
+                    let file = self.file_cache.get(&flag.file, &self.bazel_flags)?;
+                    let name = flag.flag.name.as_ref().or(flag.flag.value.as_ref())?;
+                    let value = flag.flag.value.as_ref().unwrap_or(name);
+                    Some(Location {
+                        uri: Url::from_file_path(&flag.file).ok()?,
+                        range: range_to_lsp(&file.rope, &(name.1.start..value.1.end))?,
+                    })
+                }();
+                EffectiveFlagInfo {
+                    flag: flag.format_flag(),
+                    location,
+                    section: flag.section(),
+                    via: flag.via.clone(),
+                }
+            })
+            .collect();
+        Ok(EffectiveFlagsResult {
+            flags,
+            errors: expansion.errors.iter().map(|e| e.to_string()).collect(),
+        })
+    }
+
+    // Handler for the `bazelrc.showReferences` command of the code lenses. Other clients than
+    // VS Code have no generic way to show a list of locations, so the first one is opened.
+    async fn show_references(
+        &self,
+        arguments: &[serde_json::Value],
+    ) -> Result<Option<serde_json::Value>> {
+        // Expects the document URI, the position of the definition and the references
+        let locations = match arguments {
+            [_, _, locations] => serde_json::from_value::<Vec<Location>>(locations.clone()).ok(),
+            _ => None,
+        }
+        .ok_or(Error::invalid_params(
+            "Expected a URI, a position and the references",
+        ))?;
+        if let Some(location) = locations.first() {
+            let params = ShowDocumentParams {
+                uri: location.uri.clone(),
+                external: Some(false),
+                take_focus: Some(true),
+                selection: Some(location.range),
+            };
+            if let Err(e) = self.client.show_document(params).await {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Unable to show the reference: {}", e),
+                    )
+                    .await;
+            }
+        }
+        Ok(serde_json::to_value(locations).ok())
+    }
+
+    // Validates the document using `bazel canonicalize-flags`, if enabled in the settings
+    async fn validate_with_bazel(&self, uri: &Url) {
+        if !self.settings.read().unwrap().validate_with_bazel {
+            return;
+        }
+        let Some(workspace) = uri.to_file_path().ok().and_then(|p| get_workspace_path(&p)) else {
+            return;
+        };
+        let Some((rope, lines, version)) = self.document_map.get(uri.as_str()).map(|doc| {
+            (
+                doc.rope.clone(),
+                doc.indexed_lines.lines.clone(),
+                doc.version,
+            )
+        }) else {
+            return;
+        };
+
+        // Bazel might take a while. Run it in the background instead of blocking the server.
+        let bazel_flags = self.bazel_flags.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let spawn_error = std::cell::RefCell::new(None);
+            let diagnostics =
+                diagnostics_from_validation(&rope, &lines, &bazel_flags, |command, args| {
+                    if spawn_error.borrow().is_some() {
+                        return Ok(());
+                    }
+                    match canonicalize_flags(&workspace, command, args) {
+                        Ok(_) => Ok(()),
+                        Err(BazelError::Spawn(e)) => {
+                            *spawn_error.borrow_mut() = Some(BazelError::Spawn(e));
+                            Ok(())
+                        }
+                        Err(e) => Err(e.to_string()),
+                    }
+                });
+            match spawn_error.into_inner() {
+                Some(e) => Err(e),
+                None => Ok(diagnostics),
+            }
+        })
+        .await;
+        let mut bazel_diagnostics = match result {
+            Ok(Ok(diagnostics)) => diagnostics,
+            Ok(Err(e)) => {
+                self.client.log_message(MessageType::WARNING, e).await;
+                return;
+            }
+            Err(_) => return,
+        };
+
+        // Bazel's diagnostics are post-processed like the server's own diagnostics
+        let settings = self.settings.read().unwrap().clone();
+        let options = self.analyze_options(&settings, None, Vec::new());
+
+        // Skip outdated results, in case the document was changed in the meantime
+        let Some(mut diagnostics) = self
+            .document_map
+            .get(uri.as_str())
+            .filter(|doc| doc.version == version)
+            .map(|doc| {
+                postprocess_diagnostics(
+                    &doc.rope,
+                    &doc.indexed_lines.lines,
+                    &mut bazel_diagnostics,
+                    &options,
+                );
+                doc.diagnostics.clone()
+            })
+        else {
+            return;
+        };
+        diagnostics.extend(bazel_diagnostics.iter().cloned());
+        self.bazel_diagnostics
+            .insert(uri.to_string(), bazel_diagnostics);
+        self.publish_diagnostics(uri.clone(), diagnostics, version)
             .await;
+        self.refresh_diagnostics().await;
     }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        if let Some(settings) = params
+            .initialization_options
+            .as_ref()
+            .and_then(Settings::from_json)
+        {
+            *self.settings.write().unwrap() = settings;
+        }
+        *self.pull_diagnostics.write().unwrap() = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|t| t.diagnostic.as_ref())
+            .is_some();
+        *self.snippet_support.write().unwrap() = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|t| {
+                t.completion
+                    .as_ref()?
+                    .completion_item
+                    .as_ref()?
+                    .snippet_support
+            })
+            .unwrap_or(false);
+        *self.watch_files.write().unwrap() = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|w| w.did_change_watched_files);
+
+        // Index the configs from the workspace's `.bazelrc` and all files imported from it
+        #[allow(deprecated)]
+        let workspace_uris = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|f| f.uri).collect::<Vec<_>>(),
+            None => params.root_uri.into_iter().collect::<Vec<_>>(),
+        };
+        let roots = workspace_uris
+            .iter()
+            .filter_map(|uri| Some(uri.to_file_path().ok()?.join(".bazelrc")))
+            .collect::<Vec<_>>();
+        self.index_files(&roots, None);
+
+        let workspace = workspace_uris
+            .first()
+            .and_then(|uri| uri.to_file_path().ok());
+        *self.bazel_version.write().unwrap() =
+            determine_bazel_version(workspace.as_deref(), get_home_path().as_deref(), |key| {
+                std::env::var(key).ok()
+            });
+        *self.workspace_root.write().unwrap() = workspace;
+
         Ok(InitializeResult {
             server_info: Some(ServerInfo {
                 name: "bazelrc Language Server".to_string(),
@@ -90,8 +775,15 @@ impl LanguageServer for Backend {
             }),
             offset_encoding: None,
             capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                // All positions are exchanged in UTF-16 code units, see `lsp_utils`
+                position_encoding: Some(PositionEncodingKind::UTF16),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..Default::default()
+                    },
                 )),
                 semantic_tokens_provider: Some(
                     SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
@@ -119,16 +811,53 @@ impl LanguageServer for Backend {
                     ),
                 ),
                 completion_provider: Some(CompletionOptions {
-                    trigger_characters: Some(vec!["-".to_string()]),
+                    trigger_characters: Some(vec![
+                        "-".to_string(),
+                        ":".to_string(),
+                        "=".to_string(),
+                        "/".to_string(),
+                    ]),
                     ..Default::default()
                 }),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
                 document_formatting_provider: Some(OneOf::Left(true)),
                 document_range_formatting_provider: Some(OneOf::Left(true)),
                 document_link_provider: Some(DocumentLinkOptions {
                     resolve_provider: None,
                     work_done_progress_options: Default::default(),
                 }),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![CodeActionKind::QUICKFIX]),
+                        ..Default::default()
+                    },
+                )),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        CANONICALIZE_FLAGS_COMMAND.to_string(),
+                        SHOW_REFERENCES_COMMAND.to_string(),
+                    ],
+                    work_done_progress_options: Default::default(),
+                }),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("bazelrc".to_string()),
+                        // Diagnostics depend on imported files and the configs defined elsewhere
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: true,
+                        ..Default::default()
+                    },
+                )),
                 ..ServerCapabilities::default()
             },
         })
@@ -138,6 +867,62 @@ impl LanguageServer for Backend {
         self.client
             .log_message(MessageType::INFO, "server initialized!")
             .await;
+        // We currently only ship a single flag dump. The version is logged such that
+        // users can tell if the flags might differ from their Bazel version.
+        let bazel_version = self.bazel_version.read().unwrap().clone();
+        let status = match bazel_version {
+            Some(version) => format!(
+                "Bazel version: {}. Using the flags of Bazel {}.",
+                version,
+                version.flag_dump_version()
+            ),
+            None => format!(
+                "No Bazel version configured. Using the flags of Bazel {}.",
+                FLAG_DUMP_VERSIONS.last().unwrap()
+            ),
+        };
+        self.client.log_message(MessageType::INFO, status).await;
+
+        let watch_files = *self.watch_files.read().unwrap();
+        if watch_files.is_some_and(|w| w.dynamic_registration == Some(true)) {
+            // Policy files and rc files changed outside of the editor, e.g. by `git checkout`
+            let mut watchers = [
+                format!("**/{}", POLICY_FILE_NAME),
+                "**/*.bazelrc".to_string(),
+                "**/.bazelrc".to_string(),
+            ]
+            .map(|pattern| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(pattern),
+                kind: None,
+            })
+            .to_vec();
+            // The system and user rc files are outside of the workspace
+            if watch_files.is_some_and(|w| w.relative_pattern_support == Some(true)) {
+                watchers.extend(get_default_rc_files(None).iter().filter_map(|path| {
+                    Some(FileSystemWatcher {
+                        glob_pattern: GlobPattern::Relative(RelativePattern {
+                            base_uri: OneOf::Right(Url::from_file_path(path.parent()?).ok()?),
+                            pattern: path.file_name()?.to_str()?.to_string(),
+                        }),
+                        kind: None,
+                    })
+                }));
+            }
+            let options = DidChangeWatchedFilesRegistrationOptions { watchers };
+            let registration = Registration {
+                id: "watched-files".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(options).ok(),
+            };
+            if let Err(e) = self.client.register_capability(vec![registration]).await {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Unable to watch files: {}", e),
+                    )
+                    .await;
+            }
+        }
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -145,26 +930,211 @@ impl LanguageServer for Backend {
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let uri = params.text_document.uri.clone();
         self.on_change(TextDocumentItem {
             uri: params.text_document.uri,
             text: params.text_document.text,
             version: params.text_document.version,
         })
-        .await
+        .await;
+        self.validate_with_bazel(&uri).await
     }
 
-    async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
-        self.on_change(TextDocumentItem {
-            uri: params.text_document.uri,
-            text: std::mem::take(&mut params.content_changes[0].text),
-            version: params.text_document.version,
-        })
-        .await
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        self.validate_with_bazel(&params.text_document.uri).await
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let mut changed = false;
+        for change in params.changes {
+            let Ok(path) = change.uri.to_file_path() else {
+                continue;
+            };
+            if path.file_name().is_some_and(|n| n == POLICY_FILE_NAME) {
+                if let Some(workspace) = path.parent() {
+                    self.policies.remove(workspace);
+                    changed = true;
+                }
+            } else if is_bazelrc_file(&path) && !self.document_map.contains_key(change.uri.as_str())
+            {
+                // Open documents are up to date, the editor's version takes precedence
+                let path = normalize_path(&path);
+                self.file_cache.invalidate(&path);
+                if let Ok(uri) = Url::from_file_path(&path) {
+                    self.config_index.write().unwrap().remove_file(uri.as_str());
+                }
+                self.index_files(std::slice::from_ref(&path), Some(&path));
+                changed = true;
+            }
+        }
+        if changed {
+            self.recompute_diagnostics().await;
+            self.refresh_diagnostics().await;
+        }
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        let Some(settings) = Settings::from_json(&params.settings) else {
+            return;
+        };
+        let validate_with_bazel = settings.validate_with_bazel;
+        let old_settings = self.settings.read().unwrap().clone();
+        let diagnostics_changed = old_settings.strict != settings.strict
+            || old_settings.detect_secrets != settings.detect_secrets
+            || old_settings.report_abbreviations != settings.report_abbreviations
+            || old_settings.report_experimental_flags != settings.report_experimental_flags
+            || old_settings.report_redundant_defaults != settings.report_redundant_defaults
+            || old_settings.upgrade_target_version != settings.upgrade_target_version
+            || old_settings.banned_flags != settings.banned_flags
+            || old_settings.diagnostics != settings.diagnostics;
+        *self.settings.write().unwrap() = settings;
+        if diagnostics_changed {
+            // Recompute the diagnostics with the new severities
+            self.recompute_diagnostics().await;
+        }
+        if !validate_with_bazel {
+            // Drop the diagnostics from previous validations
+            let uris = self
+                .bazel_diagnostics
+                .iter()
+                .map(|e| e.key().clone())
+                .collect::<Vec<_>>();
+            for uri in uris {
+                self.bazel_diagnostics.remove(&uri);
+                let Some((diagnostics, version)) = self
+                    .document_map
+                    .get(&uri)
+                    .map(|doc| (doc.diagnostics.clone(), doc.version))
+                else {
+                    continue;
+                };
+                if let Ok(uri) = Url::parse(&uri) {
+                    self.publish_diagnostics(uri, diagnostics, version).await;
+                }
+            }
+        }
+        if diagnostics_changed || !validate_with_bazel {
+            self.refresh_diagnostics().await;
+        }
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let (mut rope, mut parser_result) = match self.document_map.get(uri.as_str()) {
+            Some(doc) => (doc.rope.clone(), doc.parser_result.clone()),
+            None => (Rope::new(), parse_from_str("")),
+        };
+        // Apply the changes one after another, only re-parsing the lines affected by them
+        for change in params.content_changes {
+            match change.range {
+                Some(range) => {
+                    let offset = |pos: &Position| {
+                        lsp_pos_to_offset(&rope, pos)
+                            .unwrap_or(usize::MAX)
+                            .min(rope.len_chars())
+                    };
+                    let end = offset(&range.end);
+                    let changed = offset(&range.start).min(end)..end;
+                    rope.remove(changed.clone());
+                    rope.insert(changed.start, &change.text);
+                    parser_result = reparse_incremental(
+                        &parser_result,
+                        &rope,
+                        &changed,
+                        change.text.chars().count(),
+                    );
+                }
+                None => {
+                    rope = Rope::from_str(&change.text);
+                    parser_result = parse_from_str(&change.text);
+                }
+            }
+        }
+        self.update_document(uri, params.text_document.version, rope, parser_result)
+            .await
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
         self.document_map
             .remove(&params.text_document.uri.to_string());
+        // Fall back to the version on disk
+        match params.text_document.uri.to_file_path() {
+            Ok(file_path) => {
+                let file_path = normalize_path(&file_path);
+                self.file_cache.invalidate(&file_path);
+                self.config_index
+                    .write()
+                    .unwrap()
+                    .remove_file(params.text_document.uri.as_str());
+                self.index_files(std::slice::from_ref(&file_path), Some(&file_path));
+            }
+            Err(_) => self
+                .config_index
+                .write()
+                .unwrap()
+                .remove_file(params.text_document.uri.as_str()),
+        }
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let (diagnostics, _) = self.document_diagnostics(&params.text_document.uri).await;
+        Ok(DocumentDiagnosticReportResult::Report(
+            DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: None,
+                    items: diagnostics,
+                },
+            }),
+        ))
+    }
+
+    async fn workspace_diagnostic(
+        &self,
+        _: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        // All bazelrc files within the workspace, plus the files imported from the
+        // default rc files, e.g. from the user's home directory
+        let workspace = self.workspace_root.read().unwrap().clone();
+        let mut files = BTreeSet::<PathBuf>::new();
+        if let Some(workspace) = &workspace {
+            files.extend(
+                find_bazelrc_files(workspace)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|p| normalize_path(p)),
+            );
+        }
+        let graph = ImportGraph::build(
+            &get_default_rc_files(workspace.as_deref()),
+            &self.file_cache,
+            &self.bazel_flags,
+        );
+        files.extend(graph.files().into_iter().map(normalize_path));
+
+        let mut items = Vec::<WorkspaceDocumentDiagnosticReport>::new();
+        for file in files {
+            let Ok(uri) = Url::from_file_path(&file) else {
+                continue;
+            };
+            let (diagnostics, version) = self.document_diagnostics(&uri).await;
+            items.push(WorkspaceDocumentDiagnosticReport::Full(
+                WorkspaceFullDocumentDiagnosticReport {
+                    uri,
+                    version: version.map(|v| v as i64),
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: None,
+                        items: diagnostics,
+                    },
+                },
+            ));
+        }
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
     }
 
     async fn semantic_tokens_full(
@@ -195,6 +1165,16 @@ impl LanguageServer for Backend {
 
         Ok(Some(CompletionResponse::Array(get_completion_items(
             &self.bazel_flags,
+            &self.flag_completions,
+            &self.config_index.read().unwrap(),
+            &self.settings.read().unwrap(),
+            *self.snippet_support.read().unwrap(),
+            text_document_position
+                .text_document
+                .uri
+                .to_file_path()
+                .ok()
+                .as_deref(),
             &doc.rope,
             &doc.indexed_lines,
             pos,
@@ -225,22 +1205,51 @@ impl LanguageServer for Backend {
 
                     line.command
                         .as_ref()
-                        .and_then(|cmd| COMMAND_DOCS.get(cmd.0.as_str()))
-                        .map(|docs| {
-                            let contents =
-                                HoverContents::Scalar(MarkedString::String(docs.to_string()));
+                        .and_then(|cmd| Some((cmd, COMMAND_DOCS.get(cmd.0.as_str())?)))
+                        .map(|(cmd, docs)| {
+                            let mut docs = docs.to_string();
+                            if let Some(parent) = get_parent_command(&cmd.0) {
+                                docs +=
+                                    &format!("\n\nInherits all options from `{}` lines.", parent);
+                            }
+                            let contents = HoverContents::Scalar(MarkedString::String(docs));
                             Hover {
                                 contents,
                                 range: range_to_lsp(&doc.rope, span),
                             }
                         })
                 }
-                IndexEntryKind::Config => None,
+                IndexEntryKind::Config => {
+                    let command = doc.indexed_lines.lines[*line_nr].command.as_ref();
+                    self.get_config_hover(
+                        &text_document_position.text_document.uri,
+                        &doc.rope,
+                        span,
+                        command.map(|c| c.0.as_str()),
+                    )
+                }
                 IndexEntryKind::FlagValue(flag_nr) | IndexEntryKind::FlagName(flag_nr) => {
                     let line = &doc.indexed_lines.lines[*line_nr];
-                    let flag_name = &line.flags.get(*flag_nr)?.name.as_ref()?.0;
-                    let flag_info = self.bazel_flags.get_by_invocation(flag_name)?;
-                    let content = flag_info.get_documentation_markdown();
+                    let flag = doc.model.flag(*line_nr, *flag_nr)?;
+                    let flag_name = &flag.flag.name.as_ref()?.0;
+                    if matches!(kind, IndexEntryKind::FlagValue(_))
+                        && is_config_flag(flag_name, &self.bazel_flags)
+                    {
+                        return self.get_config_hover(
+                            &text_document_position.text_document.uri,
+                            &doc.rope,
+                            span,
+                            line.command.as_ref().map(|c| c.0.as_str()),
+                        );
+                    }
+                    let content = match flag.info(&self.bazel_flags) {
+                        Some(flag_info) => self.bazel_flags.get_documentation_markdown(flag_info),
+                        None => {
+                            let config_index = self.config_index.read().unwrap();
+                            let (alias, _) = config_index.get_flag_alias(flag_name)?;
+                            config_index.get_flag_alias_documentation(alias)?
+                        }
+                    };
                     let contents = HoverContents::Scalar(MarkedString::String(content));
                     Some(Hover {
                         contents,
@@ -251,6 +1260,496 @@ impl LanguageServer for Backend {
         }())
     }
 
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri.to_string();
+        let doc = self
+            .document_map
+            .get(&uri)
+            .ok_or(Error::invalid_params("Unknown document!"))?;
+        let pos = lsp_pos_to_offset(&doc.rope, &position.position)
+            .ok_or(Error::invalid_params("Position out of range"))?;
+        let config_index = self.config_index.read().unwrap();
+
+        // Flag aliases jump to their `--flag_alias` flags, configs to their definitions
+        let symbol = doc.indexed_lines.find_symbol_at_position(pos);
+        let alias_definitions = symbol.and_then(|s| match s.kind {
+            IndexEntryKind::FlagName(flag_nr) => {
+                let flag = doc.model.flag(s.line_nr, flag_nr)?;
+                if flag.info.is_some() {
+                    return None;
+                }
+                let flag_name = &flag.flag.name.as_ref()?.0;
+                let (_, definitions) = config_index.get_flag_alias(flag_name)?;
+                Some(
+                    definitions
+                        .iter()
+                        .map(|d| (&d.file, d.range))
+                        .collect::<Vec<_>>(),
+                )
+            }
+            _ => None,
+        });
+        let definitions = match alias_definitions {
+            Some(definitions) => definitions,
+            None => {
+                let Some(name) = config_index.find_config_at(&uri, pos) else {
+                    return Ok(None);
+                };
+                config_index.configs[name]
+                    .definitions
+                    .iter()
+                    .map(|d| (&d.file, d.range))
+                    .collect()
+            }
+        };
+        let locations = definitions
+            .into_iter()
+            .filter_map(|(file, range)| {
+                Some(Location {
+                    uri: Url::parse(file).ok()?,
+                    range,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Some(GotoDefinitionResponse::Array(locations)))
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        if let Some(locations) = self.find_flag_locations(&params.text_document_position) {
+            return Ok(Some(locations));
+        }
+        Ok(self
+            .find_config_locations(
+                &params.text_document_position,
+                params.context.include_declaration,
+            )?
+            .map(|(_, locations)| locations))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let Some((name, locations)) = self.find_config_locations(&params, true)? else {
+            return Ok(None);
+        };
+        Ok(locations
+            .into_iter()
+            .find(|l| {
+                l.uri == params.text_document.uri
+                    && l.range.start <= params.position
+                    && params.position <= l.range.end
+            })
+            .map(|l| PrepareRenameResponse::RangeWithPlaceholder {
+                range: l.range,
+                placeholder: name,
+            }))
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let Some((_, locations)) =
+            self.find_config_locations(&params.text_document_position, true)?
+        else {
+            return Ok(None);
+        };
+        let mut changes = std::collections::HashMap::<Url, Vec<TextEdit>>::new();
+        for location in locations {
+            changes.entry(location.uri).or_default().push(TextEdit {
+                range: location.range,
+                new_text: params.new_name.clone(),
+            });
+        }
+        Ok(Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let file_path = uri.to_file_path().ok().map(|p| normalize_path(&p));
+        let workspace_rc = file_path
+            .as_deref()
+            .and_then(get_workspace_path)
+            .map(|ws| normalize_path(&ws.join(".bazelrc")));
+        let mut actions = Vec::<CodeActionOrCommand>::new();
+        for diagnostic in &params.context.diagnostics {
+            // Offer to replace misspelled flags by similar flags
+            if DiagnosticKind::of(diagnostic) == Some(DiagnosticKind::UnknownFlag) {
+                let data = diagnostic
+                    .data
+                    .clone()
+                    .and_then(|d| serde_json::from_value::<UnknownFlagData>(d).ok());
+                for (i, suggestion) in data.iter().flat_map(|d| &d.suggestions).enumerate() {
+                    let edit = TextEdit {
+                        range: diagnostic.range,
+                        new_text: suggestion.clone(),
+                    };
+                    actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                        title: format!("Replace by `{}`", suggestion),
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: Some(vec![diagnostic.clone()]),
+                        edit: Some(WorkspaceEdit {
+                            changes: Some(std::collections::HashMap::from([(
+                                uri.clone(),
+                                vec![edit],
+                            )])),
+                            ..Default::default()
+                        }),
+                        is_preferred: Some(i == 0),
+                        ..Default::default()
+                    }));
+                }
+                continue;
+            }
+            // Offer to replace abbreviated flags by their long names
+            if DiagnosticKind::of(diagnostic) == Some(DiagnosticKind::AbbreviatedFlag) {
+                let Some(data) = diagnostic
+                    .data
+                    .clone()
+                    .and_then(|d| serde_json::from_value::<AbbreviatedFlagData>(d).ok())
+                else {
+                    continue;
+                };
+                let edit = TextEdit {
+                    range: diagnostic.range,
+                    new_text: data.replacement.clone(),
+                };
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Replace by `{}`", data.replacement),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(std::collections::HashMap::from([(uri.clone(), vec![edit])])),
+                        ..Default::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..Default::default()
+                }));
+                continue;
+            }
+            if DiagnosticKind::of(diagnostic) != Some(DiagnosticKind::UndefinedConfig) {
+                continue;
+            }
+            let Some(data) = diagnostic
+                .data
+                .clone()
+                .and_then(|d| serde_json::from_value::<UndefinedConfigData>(d).ok())
+            else {
+                continue;
+            };
+            // `always` lines are expanded for all commands, `common:config` covers those, too
+            let command = match data.command.as_str() {
+                "always" => "common",
+                c => c,
+            };
+
+            // Offer to fix typos in the config name...
+            if let Some(suggestion) = &data.suggestion {
+                let edit = self.document_map.get(uri.as_str()).and_then(|doc| {
+                    get_config_rename_edit(&doc.rope, &diagnostic.range, &data.config, suggestion)
+                });
+                if let Some(edit) = edit {
+                    actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                        title: format!("Replace by `{}`", suggestion),
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: Some(vec![diagnostic.clone()]),
+                        edit: Some(WorkspaceEdit {
+                            changes: Some(std::collections::HashMap::from([(
+                                uri.clone(),
+                                vec![edit],
+                            )])),
+                            ..Default::default()
+                        }),
+                        is_preferred: Some(true),
+                        ..Default::default()
+                    }));
+                }
+            }
+
+            // ... to create the config in the current file...
+            let mut targets = Vec::<(Url, String, TextEdit)>::new();
+            if let Some(doc) = self.document_map.get(uri.as_str()) {
+                let edit = get_config_stub_edit(
+                    &doc.rope,
+                    &doc.indexed_lines.lines,
+                    command,
+                    &data.config,
+                );
+                targets.push((uri.clone(), "this file".to_string(), edit));
+            }
+            // ... and in the workspace `.bazelrc`
+            if let Some(workspace_rc) = workspace_rc
+                .as_ref()
+                .filter(|rc| file_path.as_ref() != Some(rc))
+            {
+                let file = self.file_cache.get(workspace_rc, &self.bazel_flags);
+                if let (Some(file), Ok(rc_uri)) = (file, Url::from_file_path(workspace_rc)) {
+                    let edit = get_config_stub_edit(&file.rope, &file.lines, command, &data.config);
+                    targets.push((rc_uri, "`.bazelrc`".to_string(), edit));
+                }
+            }
+
+            for (target_uri, target_name, edit) in targets {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Create config `{}` in {}", data.config, target_name),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(std::collections::HashMap::from([(target_uri, vec![edit])])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }));
+            }
+        }
+        Ok(Some(actions))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let settings = self.settings.read().unwrap().clone();
+        let uri = params.text_document.uri;
+        let is_in_range = |pos: Position| pos >= params.range.start && pos <= params.range.end;
+        let mut hints = Vec::<InlayHint>::new();
+
+        // Show the implicit `=true` after boolean flags without value
+        if let Some(doc) = settings
+            .implicit_value_hints
+            .then(|| self.document_map.get(uri.as_str()))
+            .flatten()
+        {
+            for flag in doc.model.lines.iter().flat_map(|l| &l.flags) {
+                let Some(name) = flag
+                    .flag
+                    .name
+                    .as_ref()
+                    .filter(|_| flag.flag.value.is_none())
+                else {
+                    continue;
+                };
+                let Some(info) = flag.info(&self.bazel_flags) else {
+                    continue;
+                };
+                if !info.has_negative_flag() || flag.negated {
+                    continue;
+                }
+                let Some(position) = offset_to_lsp_pos(&doc.rope, name.1.end) else {
+                    continue;
+                };
+                if is_in_range(position) {
+                    hints.push(InlayHint {
+                        position,
+                        label: InlayHintLabel::String("=true".to_string()),
+                        kind: None,
+                        text_edits: None,
+                        tooltip: None,
+                        padding_left: None,
+                        padding_right: None,
+                        data: None,
+                    });
+                }
+            }
+        }
+
+        if !settings.config_location_hints {
+            hints.sort_by_key(|h| h.position);
+            return Ok(Some(hints));
+        }
+        // Show the files defining the config after each `--config` flag
+        let workspace = uri.to_file_path().ok().and_then(|p| get_workspace_path(&p));
+        let display_name = |file: &str| -> String {
+            let path = Url::parse(file).ok().and_then(|u| u.to_file_path().ok());
+            let Some(path) = path else {
+                return file.to_string();
+            };
+            match workspace
+                .as_deref()
+                .and_then(|ws| path.strip_prefix(ws).ok())
+            {
+                Some(relative) => relative.display().to_string(),
+                None => path.display().to_string(),
+            }
+        };
+        let config_index = self.config_index.read().unwrap();
+        for entry in config_index.configs.values() {
+            let mut files = Vec::<&str>::new();
+            for definition in &entry.definitions {
+                if !files.contains(&&*definition.file) {
+                    files.push(&definition.file);
+                }
+            }
+            // Configs defined in the current file only don't need a hint
+            if files.is_empty() || files == [uri.as_str()] {
+                continue;
+            }
+            let label = format!(
+                "({})",
+                files
+                    .iter()
+                    .map(|f| display_name(f))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            for usage in entry.usages.iter().filter(|u| *u.file == *uri.as_str()) {
+                if !is_in_range(usage.range.end) {
+                    continue;
+                }
+                hints.push(InlayHint {
+                    position: usage.range.end,
+                    label: InlayHintLabel::String(label.clone()),
+                    kind: None,
+                    text_edits: None,
+                    tooltip: None,
+                    padding_left: Some(true),
+                    padding_right: None,
+                    data: None,
+                });
+            }
+        }
+        hints.sort_by_key(|h| h.position);
+        Ok(Some(hints))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        // Show the number of references above each config definition
+        let uri = params.text_document.uri;
+        let config_index = self.config_index.read().unwrap();
+        let mut lenses = Vec::<CodeLens>::new();
+        for entry in config_index.configs.values() {
+            let references = entry
+                .usages
+                .iter()
+                .filter_map(|u| {
+                    Some(Location {
+                        uri: Url::parse(&u.file).ok()?,
+                        range: u.range,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let title = match references.len() {
+                1 => "1 reference".to_string(),
+                n => format!("{} references", n),
+            };
+            for definition in entry
+                .definitions
+                .iter()
+                .filter(|d| *d.file == *uri.as_str())
+            {
+                // The VS Code extension intercepts the command and forwards it to the built-in
+                // `editor.action.showReferences` to show the references in a peek view
+                let arguments = [
+                    serde_json::to_value(&uri),
+                    serde_json::to_value(definition.range.start),
+                    serde_json::to_value(&references),
+                ]
+                .into_iter()
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .ok();
+                lenses.push(CodeLens {
+                    range: definition.range,
+                    command: Some(Command {
+                        title: title.clone(),
+                        command: SHOW_REFERENCES_COMMAND.to_string(),
+                        arguments,
+                    }),
+                    data: None,
+                });
+            }
+        }
+        lenses.sort_by_key(|l| l.range.start);
+        Ok(Some(lenses))
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        if params.command == SHOW_REFERENCES_COMMAND {
+            return self.show_references(&params.arguments).await;
+        }
+        if params.command != CANONICALIZE_FLAGS_COMMAND {
+            return Err(Error::invalid_params("Unknown command!"));
+        }
+        // Expects the document URI and a position within the line to canonicalize
+        let (uri, position) = match params.arguments.as_slice() {
+            [uri, position] => (
+                serde_json::from_value::<Url>(uri.clone()),
+                serde_json::from_value::<Position>(position.clone()),
+            ),
+            _ => return Err(Error::invalid_params("Expected a URI and a position")),
+        };
+        let (Ok(uri), Ok(position)) = (uri, position) else {
+            return Err(Error::invalid_params("Expected a URI and a position"));
+        };
+        let workspace = uri
+            .to_file_path()
+            .ok()
+            .and_then(|p| get_workspace_path(&p))
+            .ok_or(Error::invalid_params(
+                "The document is not within a workspace",
+            ))?;
+
+        let (command, args) = {
+            let doc = self
+                .document_map
+                .get(uri.as_str())
+                .ok_or(Error::invalid_params("Unknown document!"))?;
+            let pos = lsp_pos_to_offset(&doc.rope, &position)
+                .ok_or(Error::invalid_params("Position out of range"))?;
+            let line = doc
+                .indexed_lines
+                .find_linenr_at_position(pos)
+                .map(|i| &doc.model.lines[i])
+                .ok_or(Error::invalid_params("No line at the given position"))?;
+            let command = line
+                .command()
+                .filter(|_| line.kind == LineKind::Command)
+                .ok_or(Error::invalid_params("The line has no Bazel command"))?;
+            (command.to_string(), get_line_args(&line.line))
+        };
+        if args.is_empty() {
+            return Ok(None);
+        }
+
+        // `canonicalize-flags` does not accept `common` or `always`. Flags on those lines
+        // can be used with `build`, though.
+        let for_command = match command.as_str() {
+            "common" | "always" => "build".to_string(),
+            _ => command,
+        };
+        let canonical = tokio::task::spawn_blocking(move || {
+            canonicalize_flags(&workspace, &for_command, &args)
+        })
+        .await
+        .map_err(|_| Error::internal_error())?
+        .map_err(|e| Error {
+            code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+            message: e.to_string().into(),
+            data: None,
+        })?;
+
+        // The document might have changed while Bazel was running
+        let doc = self
+            .document_map
+            .get(uri.as_str())
+            .ok_or(Error::invalid_params("Unknown document!"))?;
+        let Some(edit) = lsp_pos_to_offset(&doc.rope, &position)
+            .and_then(|pos| doc.indexed_lines.find_line_at_position(pos))
+            .and_then(|line| get_canonical_flags_edit(&doc.rope, line, &canonical))
+        else {
+            return Ok(None);
+        };
+        let edit = WorkspaceEdit {
+            changes: Some(std::collections::HashMap::from([(uri, vec![edit])])),
+            ..Default::default()
+        };
+        Ok(serde_json::to_value(edit).ok())
+    }
+
     async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
         // Find the right document
         let uri = params.text_document.uri.to_string();
@@ -260,7 +1759,7 @@ impl LanguageServer for Backend {
             .ok_or(Error::invalid_params("Unknown document!"))?;
         let rope = &doc.rope;
 
-        if !doc.parser_errors.is_empty() {
+        if !doc.parser_result.errors.is_empty() {
             return Err(Error::invalid_params(
                 "Formatting can only be applied if there are no parsing errors",
             ));
@@ -283,7 +1782,7 @@ impl LanguageServer for Backend {
             .ok_or(Error::invalid_params("Unknown document!"))?;
         let rope = &doc.rope;
 
-        if !doc.parser_errors.is_empty() {
+        if !doc.parser_result.errors.is_empty() {
             return Err(Error::invalid_params(
                 "Formatting can only be applied if there are no parsing errors",
             ));
@@ -321,23 +1820,11 @@ impl LanguageServer for Backend {
             .ok_or(Error::invalid_params("Unsupported URI scheme!"))?;
 
         // Link all `import` and `try-import` lines
-        let links = doc
-            .indexed_lines
-            .lines
-            .iter()
-            .filter_map(|line| {
-                let command = line.command.as_ref()?;
-                if command.0 != "import" && command.0 != "try-import" {
-                    return None;
-                }
-                if line.flags.len() != 1 {
-                    return None;
-                }
-                let flag = &line.flags[0];
-                if flag.name.is_some() {
-                    return None;
-                }
-                let value = flag.value.as_ref()?;
+        let mut links = doc
+            .model
+            .imports()
+            .filter_map(|import| {
+                let value = import.path.as_ref()?;
                 let path = resolve_bazelrc_path(&file_path, &value.0)?;
                 let url = Url::from_file_path(path).ok()?;
                 Some(DocumentLink {
@@ -348,19 +1835,1003 @@ impl LanguageServer for Backend {
                 })
             })
             .collect::<Vec<_>>();
+
+        // Link URLs within comments, e.g. references to tracking issues
+        links.extend(doc.indexed_lines.lines.iter().flat_map(|line| {
+            let Some((_, span)) = &line.comment else {
+                return vec![];
+            };
+            let text = rope.slice(span.clone()).to_string();
+            find_urls(&text)
+                .into_iter()
+                .filter_map(|(url, url_span)| {
+                    let url_span = span.start + url_span.start..span.start + url_span.end;
+                    Some(DocumentLink {
+                        range: range_to_lsp(rope, &url_span)?,
+                        target: Some(Url::parse(&url).ok()?),
+                        tooltip: None,
+                        data: None,
+                    })
+                })
+                .collect()
+        }));
         Ok(Some(links))
     }
 }
 
-#[tokio::main]
-async fn main() {
+#[derive(Parser)]
+#[command(version, about = "Code intelligence for bazelrc config files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Starts the language server. This is the default if no command is given.
+    Lsp,
+    /// Compares the flags set by two bazelrc files, per command and config
+    Diff {
+        /// Only compares the given commands. Defaults to `startup` and all of Bazel's commands.
+        #[arg(long = "command")]
+        commands: Vec<String>,
+        /// The old file(s). A file name, a directory, a glob pattern or `-` for stdin
+        old: String,
+        /// The new file(s). A file name, a directory, a glob pattern or `-` for stdin
+        new: String,
+    },
+    /// Checks bazelrc files for errors and prints the diagnostics
+    Lint {
+        /// The output format
+        #[arg(long, value_enum, default_value_t = LintFormat::Pretty)]
+        format: LintFormat,
+        /// Whether to use colors. By default, colors are used when printing to a terminal
+        /// and the `NO_COLOR` environment variable is not set
+        #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+        color: ColorChoice,
+        /// Disables the given diagnostics
+        #[arg(long, value_delimiter = ',', value_parser = PossibleValuesParser::new(DiagnosticKind::ALL.iter().map(|k| k.name())))]
+        disable: Vec<String>,
+        /// Reports warnings indicating that Bazel might fail (e.g., unsupported flags) as errors
+        #[arg(long)]
+        strict: bool,
+        /// Warns about flag values which look like secrets, e.g. access tokens
+        #[arg(long)]
+        detect_secrets: bool,
+        /// Reports abbreviated flags like `-k`, which are less readable than their long names
+        #[arg(long)]
+        report_abbreviations: bool,
+        /// Reports experimental flags, which might break between Bazel releases
+        #[arg(long)]
+        report_experimental_flags: bool,
+        /// Reports flags which are set to their default value
+        #[arg(long)]
+        report_redundant_defaults: bool,
+        /// The Bazel version to check the flags against. Defaults to the version Bazelisk would use
+        #[arg(long)]
+        bazel_version: Option<String>,
+        /// Reports flags whose default value changes when upgrading to this Bazel version
+        #[arg(long)]
+        upgrade_to: Option<String>,
+        /// File names, directories, glob patterns or `-` for stdin
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Lists all configs, where they are defined and referenced, unused configs and cycles
+    AnalyzeConfigs {
+        /// The output format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Table)]
+        format: ReportFormat,
+        /// File names, directories, glob patterns or `-` for stdin
+        #[arg(default_value = ".")]
+        files: Vec<String>,
+    },
+    /// Generates shell completions for bazelrc-lsp
+    Completions {
+        /// The shell to generate the completions for
+        shell: clap_complete::Shell,
+    },
+    /// Prints the flags Bazel applies for a command, in the order in which Bazel applies them
+    Expand {
+        /// The Bazel command, e.g. `build` or `test`
+        command: String,
+        /// Configs passed on the command line, i.e. `bazel <command> --config=<config>`
+        #[arg(long = "config")]
+        configs: Vec<String>,
+        /// The rc files to read, from lowest to highest precedence. File names, directories,
+        /// glob patterns or `-` for stdin. Defaults to the system rc, the rc file of the
+        /// workspace containing the current directory, and `~/.bazelrc`.
+        #[arg(long = "rc")]
+        rc_files: Vec<String>,
+    },
+    /// Prints information about Bazel's flags
+    Flags {
+        #[command(subcommand)]
+        command: FlagsCommand,
+    },
+    /// Reports flags which are removed, renamed or behave differently in another Bazel version
+    UpgradeCheck {
+        /// The Bazel version to upgrade to, e.g. `8.0.0`
+        #[arg(long)]
+        to: String,
+        /// The Bazel version currently used. Defaults to the version Bazelisk would use.
+        #[arg(long)]
+        from: Option<String>,
+        /// File names, directories, glob patterns or `-` for stdin
+        #[arg(default_value = ".")]
+        files: Vec<String>,
+    },
+    /// Formats bazelrc files. By default, the formatted files are printed to stdout.
+    Format {
+        /// Check if the files are already formatted. Prints a diff and fails, if not.
+        #[arg(long, conflicts_with = "inplace")]
+        check: bool,
+        /// Format the files in place
+        #[arg(long)]
+        inplace: bool,
+        /// Whether to use colors for the diff of `--check`. By default, colors are used when
+        /// printing to a terminal and the `NO_COLOR` environment variable is not set
+        #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+        color: ColorChoice,
+        /// File names, directories, glob patterns or `-` for stdin
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum FlagsCommand {
+    /// Prints the documentation of a flag
+    Info {
+        /// The flag, e.g. `--keep_going`, `keep_going` or `-k`
+        #[arg(allow_hyphen_values = true)]
+        flag: String,
+        /// The output format
+        #[arg(long, value_enum, default_value_t = DocFormatArg::Plain)]
+        format: DocFormatArg,
+    },
+    /// Lists the flags whose default value changes between two Bazel versions. Only covers a
+    /// curated set of commonly set flags, not all of Bazel's flags.
+    ChangedDefaults {
+        /// The Bazel version to upgrade to, e.g. `8.0.0`
+        #[arg(long)]
+        to: String,
+        /// The Bazel version currently used. Defaults to the version Bazelisk would use.
+        #[arg(long)]
+        from: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DocFormatArg {
+    Plain,
+    Markdown,
+    Html,
+}
+
+impl From<DocFormatArg> for DocFormat {
+    fn from(format: DocFormatArg) -> Self {
+        match format {
+            DocFormatArg::Plain => DocFormat::PlainText,
+            DocFormatArg::Markdown => DocFormat::Markdown,
+            DocFormatArg::Html => DocFormat::Html,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LintFormat {
+    /// The affected source lines with the diagnostics' ranges underlined
+    Pretty,
+    /// One line per diagnostic, in the format `file:line:column: severity: message`
+    Human,
+    /// Workflow commands, such that GitHub Actions shows the diagnostics inline on pull requests
+    Github,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn use_color(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::io::stdout().is_terminal()
+                    && std::env::var_os("NO_COLOR").map_or(true, |v| v.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Table,
+    Json,
+}
+
+// An input file passed on the command line
+#[derive(PartialEq, Eq)]
+enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl std::fmt::Display for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Input::Stdin => write!(f, "<stdin>"),
+            Input::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+// Expands a command line argument into the list of input files.
+//
+// The argument can be `-` for stdin, a directory which will be searched recursively
+// for bazelrc files, a glob pattern or a plain file name.
+fn expand_input(arg: &str) -> std::result::Result<Vec<Input>, String> {
+    if arg == "-" {
+        return Ok(vec![Input::Stdin]);
+    }
+    let path = Path::new(arg);
+    if path.is_dir() {
+        let files = find_bazelrc_files(path)
+            .map_err(|e| format!("Failed to search {}: {}", path.display(), e))?;
+        if files.is_empty() {
+            return Err(format!("No bazelrc files found in {}", path.display()));
+        }
+        return Ok(files.into_iter().map(Input::File).collect());
+    }
+    if !path.exists() && arg.contains(['*', '?', '[']) {
+        let files = glob::glob(arg)
+            .map_err(|e| format!("Invalid glob pattern {:?}: {}", arg, e))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to expand {:?}: {}", arg, e))?;
+        if files.is_empty() {
+            return Err(format!("No files match {:?}", arg));
+        }
+        return Ok(files.into_iter().map(Input::File).collect());
+    }
+    Ok(vec![Input::File(path.to_path_buf())])
+}
+
+fn expand_inputs(args: &[String]) -> std::result::Result<Vec<Input>, String> {
+    let mut inputs = Vec::<Input>::new();
+    for arg in args {
+        inputs.extend(expand_input(arg)?);
+    }
+    if inputs.iter().filter(|i| **i == Input::Stdin).count() > 1 {
+        return Err("`-` (stdin) can only be used once".to_string());
+    }
+    Ok(inputs)
+}
+
+fn read_input(input: &Input) -> std::result::Result<String, String> {
+    match input {
+        Input::Stdin => {
+            let mut bytes = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut bytes)
+                .map(|_| decode_bazelrc(&bytes))
+        }
+        Input::File(path) => read_bazelrc(path),
+    }
+    .map_err(|e| format!("Failed to read {}: {}", input, e))
+}
+
+// Reads and parses a bazelrc file for usage from the command line.
+// Returns both the file contents and the parsed lines.
+fn parse_input(
+    input: &Input,
+    bazel_flags: &BazelFlags,
+) -> std::result::Result<(Rope, Vec<Line>), String> {
+    let src = read_input(input)?;
+    let ParserResult {
+        tokens: _,
+        mut lines,
+        errors,
+    } = parse_from_str(&src);
+    if !errors.is_empty() {
+        return Err(format!("Failed to parse {}", input));
+    }
+    combine_key_value_flags(&mut lines, bazel_flags);
+    Ok((Rope::from_str(&src), lines))
+}
+
+fn run_diff(old: &str, new: &str, commands: &[String]) -> std::result::Result<ExitCode, String> {
+    let bazel_flags = load_bazel_flags();
+    let old_inputs = expand_input(old)?;
+    let new_inputs = expand_input(new)?;
+    if old_inputs.contains(&Input::Stdin) && new_inputs.contains(&Input::Stdin) {
+        return Err("`-` (stdin) can only be used once".to_string());
+    }
+    let all_commands = diff_commands(&bazel_flags);
+    if let Some(unknown) = commands.iter().find(|c| !all_commands.contains(c)) {
+        return Err(format!("Unknown command {:?}", unknown));
+    }
+    let commands = if commands.is_empty() {
+        all_commands
+    } else {
+        commands.to_vec()
+    };
+    let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+    let cache = FileCache::default();
+    let old_files = resolve_rc_inputs(&old_inputs, &cwd, &cache, &bazel_flags)?;
+    let new_files = resolve_rc_inputs(&new_inputs, &cwd, &cache, &bazel_flags)?;
+    let old_config = EffectiveConfig::new(&old_files, &cache, &bazel_flags);
+    let new_config = EffectiveConfig::new(&new_files, &cache, &bazel_flags);
+    let diffs = diff_effective_configs(&old_config, &new_config, &commands, &bazel_flags);
+    print!("{}", format_diff(&diffs));
+    // Similar to `diff`, signal differences through the exit code
+    Ok(if diffs.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+// Escapes a string for usage in GitHub workflow commands.
+// See https://github.com/actions/toolkit/blob/main/packages/core/src/command.ts
+fn escape_github_data(str: &str) -> String {
+    str.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_github_property(str: &str) -> String {
+    escape_github_data(str)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+fn format_diagnostic(
+    file: &str,
+    rope: &Rope,
+    diagnostic: &Diagnostic,
+    format: LintFormat,
+    color: bool,
+) -> String {
+    let start = &diagnostic.range.start;
+    let end = &diagnostic.range.end;
+    match format {
+        LintFormat::Pretty => render_diagnostic(file, rope, diagnostic, color),
+        LintFormat::Human => {
+            let severity = match diagnostic.severity {
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                Some(DiagnosticSeverity::INFORMATION) => "info",
+                Some(DiagnosticSeverity::HINT) => "hint",
+                _ => "error",
+            };
+            format!(
+                "{}:{}:{}: {}: {}",
+                file,
+                start.line + 1,
+                lsp_pos_to_column(rope, start) + 1,
+                severity,
+                diagnostic.message
+            )
+        }
+        LintFormat::Github => {
+            let command = match diagnostic.severity {
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                Some(DiagnosticSeverity::INFORMATION) | Some(DiagnosticSeverity::HINT) => "notice",
+                _ => "error",
+            };
+            format!(
+                "::{} file={},line={},col={},endLine={},endColumn={}::{}",
+                command,
+                escape_github_property(file),
+                start.line + 1,
+                lsp_pos_to_column(rope, start) + 1,
+                end.line + 1,
+                lsp_pos_to_column(rope, end) + 1,
+                escape_github_data(&diagnostic.message)
+            )
+        }
+    }
+}
+
+// Lints the files. The file path, Bazel versions and banned flags of `options` are filled in
+// by this function.
+fn run_lint(
+    files: &[String],
+    format: LintFormat,
+    color: ColorChoice,
+    bazel_version: Option<&str>,
+    upgrade_to: Option<&str>,
+    options: AnalyzeOptions,
+) -> std::result::Result<ExitCode, String> {
+    let bazel_flags = load_bazel_flags();
+    let upgrade_target_version = match upgrade_to {
+        Some(version) => Some(
+            BazelVersion::parse(version, VersionSource::CommandLine)
+                .ok_or_else(|| format!("Invalid Bazel version {:?}", version))?,
+        ),
+        None => None,
+    };
+    let bazel_version = match bazel_version {
+        Some(version) => Some(
+            BazelVersion::parse(version, VersionSource::CommandLine)
+                .ok_or_else(|| format!("Invalid Bazel version {:?}", version))?,
+        ),
+        None => {
+            let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+            determine_bazel_version(
+                get_workspace_path(&cwd).as_deref(),
+                get_home_path().as_deref(),
+                |key| std::env::var(key).ok(),
+            )
+        }
+    };
+    // The policy of the workspace the command is run in
+    let banned_flags =
+        match get_workspace_path(&std::env::current_dir().map_err(|e| e.to_string())?) {
+            Some(workspace) => Policy::load(&workspace)?.banned_flags,
+            None => Vec::new(),
+        };
+    let color = color.use_color();
+    let mut has_errors = false;
+    let file_cache = FileCache::default();
+    let mut parsed_inputs = Vec::new();
+    for input in expand_inputs(files)? {
+        let src = read_input(&input)?;
+        parsed_inputs.push((input, parse(&src, &bazel_flags)));
+    }
+    // Flag aliases are usable in all files, no matter where they are defined
+    let flag_aliases = parsed_inputs
+        .iter()
+        .flat_map(|(_, file)| collect_flag_aliases(&file.lines, &bazel_flags))
+        .collect::<BTreeSet<_>>();
+    for (input, file) in parsed_inputs {
+        let options = AnalyzeOptions {
+            file_path: match &input {
+                Input::Stdin => None,
+                Input::File(path) => Some(path.clone()),
+            },
+            bazel_version: bazel_version.clone(),
+            banned_flags: banned_flags.clone(),
+            upgrade_target_version: upgrade_target_version.clone(),
+            ..options.clone()
+        };
+        let mut diagnostics =
+            analyze_parsed(&file, &bazel_flags, &file_cache, &flag_aliases, &options);
+        // Report the diagnostics in source order, the most severe first
+        diagnostics.sort_by_key(|d| {
+            (
+                d.range.start,
+                d.severity.unwrap_or(DiagnosticSeverity::ERROR),
+            )
+        });
+        let rope = &file.rope;
+        for diagnostic in &diagnostics {
+            has_errors |= diagnostic.severity.unwrap_or(DiagnosticSeverity::ERROR)
+                == DiagnosticSeverity::ERROR;
+            println!(
+                "{}",
+                format_diagnostic(&input.to_string(), rope, diagnostic, format, color)
+            );
+        }
+    }
+    Ok(if has_errors {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+#[derive(serde::Serialize)]
+struct ReportLocation {
+    file: String,
+    line: u32,
+    column: u32,
+    command: Option<String>,
+}
+
+impl std::fmt::Display for ReportLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if let Some(command) = &self.command {
+            write!(f, " ({})", command)?;
+        }
+        Ok(())
+    }
+}
+
+impl ReportLocation {
+    fn new(file: &str, range: &Range, command: Option<&str>) -> ReportLocation {
+        ReportLocation {
+            file: file.to_string(),
+            line: range.start.line + 1,
+            column: range.start.character + 1,
+            command: command.map(|c| c.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ConfigReportEntry {
+    name: String,
+    flag_count: usize,
+    unused: bool,
+    definitions: Vec<ReportLocation>,
+    references: Vec<ReportLocation>,
+}
+
+#[derive(serde::Serialize)]
+struct ConfigReport {
+    configs: Vec<ConfigReportEntry>,
+    cycles: Vec<Vec<String>>,
+}
+
+fn format_config_report_table(report: &ConfigReport) -> String {
+    let mut rows = vec![[
+        "CONFIG".to_string(),
+        "FLAGS".to_string(),
+        "DEFINED AT".to_string(),
+        "REFERENCED AT".to_string(),
+    ]];
+    for config in &report.configs {
+        let definitions = if config.definitions.is_empty() {
+            vec!["(undefined)".to_string()]
+        } else {
+            config.definitions.iter().map(|d| d.to_string()).collect()
+        };
+        let references = if config.unused {
+            vec!["(unused)".to_string()]
+        } else if config.references.is_empty() {
+            vec!["(platform-specific)".to_string()]
+        } else {
+            config.references.iter().map(|r| r.to_string()).collect()
+        };
+        // Additional definitions and references are listed in continuation rows
+        for i in 0..definitions.len().max(references.len()) {
+            let (name, flag_count) = if i == 0 {
+                (config.name.clone(), config.flag_count.to_string())
+            } else {
+                (String::new(), String::new())
+            };
+            rows.push([
+                name,
+                flag_count,
+                definitions.get(i).cloned().unwrap_or_default(),
+                references.get(i).cloned().unwrap_or_default(),
+            ]);
+        }
+    }
+
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    for row in &rows {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        out += line.trim_end();
+        out.push('\n');
+    }
+    if !report.cycles.is_empty() {
+        out += "\nCyclic config chains:\n";
+        for cycle in &report.cycles {
+            out += &format!("  {}\n", cycle.join(" -> "));
+        }
+    }
+    out
+}
+
+fn run_analyze_configs(
+    files: &[String],
+    format: ReportFormat,
+) -> std::result::Result<ExitCode, String> {
+    let bazel_flags = load_bazel_flags();
+    let mut index = ConfigIndex::default();
+    for input in expand_inputs(files)? {
+        let (rope, lines) = parse_input(&input, &bazel_flags)?;
+        index.add_file(&input.to_string(), &rope, &lines, &bazel_flags);
+    }
+
+    let unused = index.unused_configs().collect::<Vec<_>>();
+    let report = ConfigReport {
+        configs: index
+            .configs
+            .iter()
+            .map(|(name, entry)| ConfigReportEntry {
+                name: name.clone(),
+                flag_count: entry.flag_count(),
+                unused: unused.contains(&name.as_str()),
+                definitions: entry
+                    .definitions
+                    .iter()
+                    .map(|d| ReportLocation::new(&d.file, &d.range, Some(&d.command)))
+                    .collect(),
+                references: entry
+                    .usages
+                    .iter()
+                    .map(|u| ReportLocation::new(&u.file, &u.range, u.command.as_deref()))
+                    .collect(),
+            })
+            .collect(),
+        cycles: index.find_cycles(),
+    };
+    match format {
+        ReportFormat::Table => print!("{}", format_config_report_table(&report)),
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_flag_info(flag: &str, format: DocFormat) -> std::result::Result<ExitCode, String> {
+    let bazel_flags = load_bazel_flags();
+    // Also accept flag names without leading dashes
+    let invocation = if flag.starts_with('-') {
+        flag.to_string()
+    } else {
+        format!("--{}", flag)
+    };
+    let flag_info = bazel_flags
+        .get_by_invocation(&invocation)
+        .ok_or_else(|| format!("Unknown flag {:?}", flag))?;
+    print!("{}", bazel_flags.render_documentation(flag_info, format));
+    Ok(ExitCode::SUCCESS)
+}
+
+// The flags of the given Bazel version, see `load_bazel_flags_for_release`
+fn load_flags_for_cli_version(
+    version: &BazelVersion,
+) -> std::result::Result<Arc<BazelFlags>, String> {
+    load_bazel_flags_for_release(&version.version).ok_or_else(|| {
+        format!(
+            "No flag information available for Bazel {}. Pass a release, e.g. `8.0.0`.",
+            version
+        )
+    })
+}
+
+// The Bazel versions to compare for an upgrade. The current version defaults to the
+// version Bazelisk would use.
+fn determine_upgrade_versions(
+    from: Option<&str>,
+    to: &str,
+) -> std::result::Result<(BazelVersion, BazelVersion), String> {
+    let from_version = match from {
+        Some(from) => BazelVersion::parse(from, VersionSource::CommandLine),
+        None => {
+            let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+            determine_bazel_version(
+                get_workspace_path(&cwd).as_deref(),
+                get_home_path().as_deref(),
+                |key| std::env::var(key).ok(),
+            )
+        }
+    }
+    .ok_or("Unable to determine the current Bazel version. Pass it using `--from`.")?;
+    let to_version = BazelVersion::parse(to, VersionSource::CommandLine)
+        .ok_or_else(|| format!("Invalid Bazel version {:?}", to))?;
+    Ok((from_version, to_version))
+}
+
+fn run_changed_defaults(from: Option<&str>, to: &str) -> std::result::Result<ExitCode, String> {
+    let (from_version, to_version) = determine_upgrade_versions(from, to)?;
+    let current_flags = load_flags_for_cli_version(&from_version)?;
+    let target_flags = load_flags_for_cli_version(&to_version)?;
+    let changes = find_default_changes(&current_flags, &target_flags);
+    for change in &changes {
+        let mut line = format!("--{}: {} -> {}", change.flag, change.current, change.target);
+        if let Some(added_in) = &change.added_in {
+            line += &format!(" (added in Bazel {})", added_in);
+        }
+        println!("{}", line);
+    }
+    if changes.is_empty() {
+        eprintln!(
+            "No known default value changes between Bazel {} and {}",
+            from_version.version, to_version.version
+        );
+    }
+    eprintln!("Note: Default value changes are only known for a curated set of commonly set flags");
+    Ok(ExitCode::SUCCESS)
+}
+
+// Notes which versions' flags are only approximated, such that an empty report isn't
+// mistaken for a safe upgrade
+fn note_derived_flags(versions: &[&BazelVersion]) {
+    for version in versions {
+        if load_bazel_flags_for_version(&version.version).is_none() {
+            eprintln!(
+                "Note: No flag dump is bundled for Bazel {}. Its flags are approximated from the flags of Bazel {} and a partial list of flag additions and removals, so issues might be missing.",
+                version.version,
+                FLAG_DUMP_VERSIONS.last().unwrap()
+            );
+        }
+    }
+}
+
+fn run_upgrade_check(
+    files: &[String],
+    from: Option<&str>,
+    to: &str,
+) -> std::result::Result<ExitCode, String> {
+    let (from_version, to_version) = determine_upgrade_versions(from, to)?;
+    let current_flags = load_flags_for_cli_version(&from_version)?;
+    let target_flags = load_flags_for_cli_version(&to_version)?;
+    note_derived_flags(&[&from_version, &to_version]);
+
+    let mut has_issues = false;
+    for input in expand_inputs(files)? {
+        let (rope, lines) = parse_input(&input, &current_flags)?;
+        for issue in find_upgrade_issues(&rope, &lines, &current_flags, &target_flags) {
+            has_issues = true;
+            let mut message = issue.message;
+            if let Some(replacement) = &issue.replacement {
+                message += &format!(". Use {} instead.", replacement);
+            }
+            println!(
+                "{}:{}:{}: {}",
+                input,
+                issue.range.start.line + 1,
+                lsp_pos_to_column(&rope, &issue.range.start) + 1,
+                message
+            );
+        }
+    }
+    Ok(if has_issues {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+// The path under which stdin is read as an rc file. Stdin is treated as a file in the current
+// directory, such that its imports are resolved the same way as for other rc files.
+fn stdin_rc_path(cwd: &Path) -> PathBuf {
+    cwd.join(Input::Stdin.to_string())
+}
+
+// The paths of the inputs, for reading them as rc files through the cache
+fn resolve_rc_inputs(
+    inputs: &[Input],
+    cwd: &Path,
+    cache: &FileCache,
+    bazel_flags: &BazelFlags,
+) -> std::result::Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        match input {
+            Input::Stdin => {
+                let src = read_input(input)?;
+                let path = stdin_rc_path(cwd);
+                cache.update(&path, Arc::new(ParsedFile::from_str(&src, bazel_flags)));
+                paths.push(path);
+            }
+            Input::File(path) => paths.push(cwd.join(path)),
+        }
+    }
+    Ok(paths)
+}
+
+fn run_expand(
+    command: &str,
+    configs: &[String],
+    rc_files: &[String],
+) -> std::result::Result<ExitCode, String> {
+    let bazel_flags = load_bazel_flags();
+    if !bazel_flags.commands.iter().any(|c| c == command) && command != "startup" {
+        return Err(format!("Unknown command {:?}", command));
+    }
+    let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+    let cache = FileCache::default();
+    let stdin_path = stdin_rc_path(&cwd);
+    let rc_files = if rc_files.is_empty() {
+        get_default_rc_files(get_workspace_path(&cwd).as_deref())
+    } else {
+        resolve_rc_inputs(&expand_inputs(rc_files)?, &cwd, &cache, &bazel_flags)?
+    };
+    let effective_config = EffectiveConfig::new(&rc_files, &cache, &bazel_flags);
+    let expansion = effective_config.expand(command, configs);
+    for flag in expansion.flags {
+        let file = if flag.file == stdin_path {
+            Input::Stdin.to_string()
+        } else {
+            flag.file.display().to_string()
+        };
+        let mut origin = format!("{}:{} ({})", file, flag.line_nr + 1, flag.section());
+        if !flag.via.is_empty() {
+            origin += &format!(" via --config={}", flag.via.join(" -> --config="));
+        }
+        println!("{:<40} # {}", flag.format_flag(), origin);
+    }
+    for error in &expansion.errors {
+        eprintln!("error: {}", error);
+    }
+    Ok(if expansion.errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+// Renders a unified diff, optionally colorized
+fn render_unified_diff(old: &str, new: &str, name: &str, color: bool) -> String {
+    let diff = similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(name, name)
+        .to_string();
+    if !color {
+        return diff;
+    }
+    diff.lines()
+        .map(|l| {
+            let color = if l.starts_with("+++") || l.starts_with("---") {
+                "\x1b[1m"
+            } else if l.starts_with('+') {
+                "\x1b[32m"
+            } else if l.starts_with('-') {
+                "\x1b[31m"
+            } else if l.starts_with("@@") {
+                "\x1b[36m"
+            } else {
+                return format!("{}\n", l);
+            };
+            format!("{}{}\x1b[0m\n", color, l)
+        })
+        .collect::<String>()
+}
+
+fn run_format(
+    files: &[String],
+    check: bool,
+    inplace: bool,
+    color: ColorChoice,
+) -> std::result::Result<ExitCode, String> {
+    let color = color.use_color();
+    let inputs = expand_inputs(files)?;
+    if inplace && inputs.contains(&Input::Stdin) {
+        return Err("`-` (stdin) can't be formatted in place".to_string());
+    }
+    let mut unformatted = 0;
+    for input in &inputs {
+        let src = read_input(input)?;
+        let formatted = pretty_print(&src).ok_or(format!("Failed to parse {}", input))?;
+        if check {
+            if formatted != src {
+                print!(
+                    "{}",
+                    render_unified_diff(&src, &formatted, &input.to_string(), color)
+                );
+                unformatted += 1;
+            }
+        } else if inplace {
+            if let Input::File(path) = input {
+                if formatted != src {
+                    std::fs::write(path, formatted)
+                        .map_err(|e| format!("Failed to write {}: {}", input, e))?;
+                }
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+    if unformatted > 0 {
+        eprintln!("{} file(s) would be reformatted", unformatted);
+        return Ok(ExitCode::from(1));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn run_language_server() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(|client| Backend {
+    // The flags and their completion items are shared by all documents
+    let bazel_flags = load_bazel_flags();
+    let flag_completions = FlagCompletions::new(&bazel_flags);
+    let (service, socket) = LspService::build(|client| Backend {
         client,
         document_map: Default::default(),
-        bazel_flags: load_bazel_flags(),
-    });
+        file_cache: Default::default(),
+        config_index: Default::default(),
+        bazel_version: Default::default(),
+        workspace_root: Default::default(),
+        settings: Default::default(),
+        bazel_diagnostics: Default::default(),
+        bazel_flags,
+        flag_completions,
+        pull_diagnostics: Default::default(),
+        snippet_support: Default::default(),
+        watch_files: Default::default(),
+        policies: Default::default(),
+    })
+    .custom_method("bazelrc/listConfigs", Backend::list_configs)
+    .custom_method("bazelrc/effectiveFlags", Backend::effective_flags)
+    .finish();
     Server::new(stdin, stdout, socket).serve(service).await;
 }
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command.unwrap_or(Commands::Lsp) {
+        Commands::Lsp => {
+            run_language_server().await;
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Diff { commands, old, new } => run_diff(&old, &new, &commands),
+        Commands::Lint {
+            format,
+            color,
+            disable,
+            strict,
+            detect_secrets,
+            report_abbreviations,
+            report_experimental_flags,
+            report_redundant_defaults,
+            bazel_version,
+            upgrade_to,
+            files,
+        } => run_lint(
+            &files,
+            format,
+            color,
+            bazel_version.as_deref(),
+            upgrade_to.as_deref(),
+            AnalyzeOptions {
+                strict,
+                detect_secrets,
+                report_abbreviations,
+                report_experimental_flags,
+                report_redundant_defaults,
+                disabled: disable
+                    .iter()
+                    .filter_map(|name| DiagnosticKind::from_name(name))
+                    .collect(),
+                ..Default::default()
+            },
+        ),
+        Commands::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                "bazelrc-lsp",
+                &mut std::io::stdout(),
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::AnalyzeConfigs { format, files } => run_analyze_configs(&files, format),
+        Commands::Expand {
+            command,
+            configs,
+            rc_files,
+        } => run_expand(&command, &configs, &rc_files),
+        Commands::Flags {
+            command: FlagsCommand::Info { flag, format },
+        } => run_flag_info(&flag, format.into()),
+        Commands::Flags {
+            command: FlagsCommand::ChangedDefaults { to, from },
+        } => run_changed_defaults(from.as_deref(), &to),
+        Commands::UpgradeCheck { to, from, files } => {
+            run_upgrade_check(&files, from.as_deref(), &to)
+        }
+        Commands::Format {
+            check,
+            inplace,
+            color,
+            files,
+        } => run_format(&files, check, inplace, color),
+    };
+    result.unwrap_or_else(|msg| {
+        eprintln!("{}", msg);
+        ExitCode::from(2)
+    })
+}
@@ -1,54 +1,81 @@
-use chumsky::{error::Simple, Parser};
+use chumsky::{
+    error::{Simple, SimpleReason},
+    Error, Parser,
+};
+use ropey::Rope;
 
-use crate::tokenizer::{tokenizer, Span, Spanned, Token};
+use crate::{
+    cst::source_map,
+    tokenizer::{tokenizer, Span, Spanned, Token},
+};
 
 #[derive(Clone, Debug, PartialEq, Eq, Default)]
 pub struct Flag {
+    // The flag name including its leading dashes, e.g. `--copt`. Missing for
+    // tokens which are only a value, e.g. the `foo` in `build foo`
     pub name: Option<Spanned<String>>,
+    // The flag value. For `--name=value` flags, the span starts at the `=`
     pub value: Option<Spanned<String>>,
 }
 
+// An `import` or `try-import` statement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportLine {
+    // The `import` or `try-import` keyword
+    pub keyword: Spanned<String>,
+    // `true` for `try-import`, which does not fail if the file is missing
+    pub optional: bool,
+    // The imported path. Missing if the line does not consist of exactly one path
+    pub path: Option<Spanned<String>>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Default)]
 pub struct Line {
     pub command: Option<Spanned<String>>,
     pub config: Option<Spanned<String>>,
     pub flags: Vec<Flag>,
     pub comment: Option<Spanned<String>>,
+    // Set for `import` and `try-import` lines, in addition to the command and flags
+    pub import: Option<ImportLine>,
     // The span of this line (without the comment)
     pub span: Span,
 }
 
+#[derive(Clone, Debug)]
 pub struct ParserResult {
     pub tokens: Vec<Spanned<Token>>,
     pub lines: Vec<Line>,
     pub errors: Vec<Simple<char>>,
 }
 
-// Splits a token at a given separator, keeping the position tracking
+// Splits a token at a given separator, keeping the position tracking.
+//
+// The separator is located in the source code using the token's source map, such that
+// quoted or escaped separators (e.g. in `bu'ild\:o'pt`) are found at their exact position.
+// The second part's span starts at the separator.
 fn split_token(
     str: &str,
     span: &Span,
-    orig: &str,
+    chars: &[char],
     sep: char,
 ) -> Option<(Spanned<String>, Spanned<String>)> {
-    if let Some(split_pos) = str.find(sep) {
-        let orig_slice = &orig[span.start..span.end];
-        let orig_offset = orig_slice.find(sep).unwrap();
-        let (p1, p2_) = str.split_at(split_pos);
-        let (_, p2) = p2_.split_at(1);
-        Some((
-            (p1.to_string(), span.start..span.start + orig_offset),
-            (p2.to_string(), (span.start + orig_offset..span.end)),
-        ))
-    } else {
-        None
-    }
+    let split_pos = str.find(sep)?;
+    let (p1, p2_) = str.split_at(split_pos);
+    let (_, p2) = p2_.split_at(1);
+    let map = source_map(&chars[span.clone()], span.start);
+    let sep_start = map
+        .get(p1.chars().count())
+        .map_or(span.start, |(_, s)| s.start);
+    Some((
+        (p1.to_string(), span.start..sep_start),
+        (p2.to_string(), (sep_start..span.end)),
+    ))
 }
 
-fn parse_flag(str: &str, span: &Span, orig: &str) -> Flag {
+fn parse_flag(str: &str, span: &Span, chars: &[char]) -> Flag {
     if str.starts_with('-') {
         // This is flag. Try to split at `=`
-        if let Some((name, value)) = split_token(str, span, orig, '=') {
+        if let Some((name, value)) = split_token(str, span, chars, '=') {
             Flag {
                 name: Some(name),
                 value: Some(value),
@@ -68,8 +95,27 @@ fn parse_flag(str: &str, span: &Span, orig: &str) -> Flag {
     }
 }
 
+fn parse_import(line: &Line) -> Option<ImportLine> {
+    let keyword = line.command.as_ref()?;
+    let optional = match keyword.0.as_str() {
+        "import" => false,
+        "try-import" => true,
+        _ => return None,
+    };
+    let path = match line.flags.as_slice() {
+        [Flag { name: None, value }] => value.clone(),
+        _ => None,
+    };
+    Some(ImportLine {
+        keyword: keyword.clone(),
+        optional,
+        path,
+    })
+}
+
 fn parse(tokens: &[(Token, Span)], orig: &str) -> Vec<Line> {
     let mut result_lines = Vec::<Line>::new();
+    let chars = orig.chars().collect::<Vec<_>>();
 
     let mut current_line_start = 0;
     let mut current_line = Option::<Line>::None;
@@ -79,7 +125,7 @@ fn parse(tokens: &[(Token, Span)], orig: &str) -> Vec<Line> {
                 let line = current_line.get_or_insert_with(Default::default);
                 // The first token is the command name
                 if line.command.is_none() && line.flags.is_empty() && !s.starts_with('-') {
-                    if let Some((command, config)) = split_token(s, &t.1, orig, ':') {
+                    if let Some((command, config)) = split_token(s, &t.1, &chars, ':') {
                         line.command = if command.0.is_empty() {
                             None
                         } else {
@@ -92,7 +138,7 @@ fn parse(tokens: &[(Token, Span)], orig: &str) -> Vec<Line> {
                     }
                 } else {
                     // All other tokens are flags
-                    line.flags.push(parse_flag(s, &t.1, orig));
+                    line.flags.push(parse_flag(s, &t.1, &chars));
                 }
             }
             Token::Comment(s) => {
@@ -103,6 +149,7 @@ fn parse(tokens: &[(Token, Span)], orig: &str) -> Vec<Line> {
             Token::Newline => {
                 if let Some(mut l) = current_line.take() {
                     l.span = current_line_start..t.1.end;
+                    l.import = parse_import(&l);
                     result_lines.push(l);
                 }
                 current_line_start = t.1.end;
@@ -111,22 +158,44 @@ fn parse(tokens: &[(Token, Span)], orig: &str) -> Vec<Line> {
         };
     }
     if let Some(mut l) = current_line.take() {
-        let implicit_final_newline = orig.chars().count();
+        let implicit_final_newline = chars.len();
         l.span = current_line_start..implicit_final_newline;
+        l.import = parse_import(&l);
         result_lines.push(l);
     }
 
     result_lines
 }
 
+// Why Bazel rejects a config name, if it does. Empty config names are accepted by Bazel,
+// they are only diagnosed as pointless.
+pub fn invalid_config_name(name: &str) -> Option<&'static str> {
+    if name.starts_with('-') {
+        Some("Config names can't start with `-`")
+    } else if name.contains('=') {
+        Some("Config names can't contain `=`")
+    } else {
+        None
+    }
+}
+
+fn validate_config_names(lines: &[Line]) -> impl Iterator<Item = Simple<char>> + '_ {
+    lines.iter().filter_map(|line| {
+        let (name, span) = line.config.as_ref()?;
+        let message = invalid_config_name(name)?;
+        Some(Simple::custom(span.clone(), message))
+    })
+}
+
 // Parser for bazelrc files.
 pub fn parse_from_str(str: &str) -> ParserResult {
     // Tokenize
-    let (tokens_opt, errors) = tokenizer().parse_recovery(str);
+    let (tokens_opt, mut errors) = tokenizer().parse_recovery(str);
     let tokens = tokens_opt.unwrap_or(Vec::new());
 
     // Parse
     let lines = parse(&tokens, str);
+    errors.extend(validate_config_names(&lines));
 
     ParserResult {
         tokens,
@@ -135,6 +204,170 @@ pub fn parse_from_str(str: &str) -> ParserResult {
     }
 }
 
+fn shift_span(span: &Span, offset: isize) -> Span {
+    span.start.saturating_add_signed(offset)..span.end.saturating_add_signed(offset)
+}
+
+fn shift_line(line: &Line, offset: isize) -> Line {
+    let shift = |s: &Option<Spanned<String>>| {
+        s.as_ref()
+            .map(|(str, span)| (str.clone(), shift_span(span, offset)))
+    };
+    Line {
+        command: shift(&line.command),
+        config: shift(&line.config),
+        flags: line
+            .flags
+            .iter()
+            .map(|f| Flag {
+                name: shift(&f.name),
+                value: shift(&f.value),
+            })
+            .collect(),
+        comment: shift(&line.comment),
+        import: line.import.as_ref().map(|import| ImportLine {
+            keyword: (
+                import.keyword.0.clone(),
+                shift_span(&import.keyword.1, offset),
+            ),
+            optional: import.optional,
+            path: shift(&import.path),
+        }),
+        span: shift_span(&line.span, offset),
+    }
+}
+
+fn shift_error(error: &Simple<char>, offset: isize) -> Simple<char> {
+    let span = shift_span(&error.span(), offset);
+    let shifted = match error.reason() {
+        SimpleReason::Custom(msg) => Simple::custom(span, msg),
+        SimpleReason::Unclosed {
+            span: unclosed_span,
+            delimiter,
+        } => Simple::unclosed_delimiter(
+            shift_span(unclosed_span, offset),
+            *delimiter,
+            span,
+            error
+                .expected()
+                .flatten()
+                .next()
+                .copied()
+                .unwrap_or(*delimiter),
+            error.found().copied(),
+        ),
+        SimpleReason::Unexpected => {
+            Simple::expected_input_found(span, error.expected().copied(), error.found().copied())
+        }
+    };
+    match error.label() {
+        Some(label) => shifted.with_label(label),
+        None => shifted,
+    }
+}
+
+// Re-parses a document after an edit, only tokenizing the lines affected by the edit again.
+//
+// `changed` is the range of chars of the previous version of the document which got replaced
+// by `inserted_len` chars. The tokens, lines and errors of all other lines are reused, with
+// their spans shifted accordingly. The result is the same as parsing the whole document.
+pub fn reparse_incremental(
+    previous: &ParserResult,
+    src: &Rope,
+    changed: &Span,
+    inserted_len: usize,
+) -> ParserResult {
+    let offset = inserted_len as isize - changed.len() as isize;
+    let newline_ends = previous
+        .tokens
+        .iter()
+        .filter(|t| t.0 == Token::Newline)
+        .map(|t| &t.1);
+    // Lines are separated by unescaped newlines. Everything up to the last newline before the
+    // edit stays the same
+    let start = newline_ends
+        .clone()
+        .map(|span| span.end)
+        .take_while(|end| *end <= changed.start)
+        .last()
+        .unwrap_or(0);
+    // The affected region ends at one of the following newlines, or at the end of the document.
+    // The edit might escape the first newline after it, in which case the next line is affected
+    // as well
+    let candidate_ends = newline_ends
+        .filter(|span| span.start >= changed.end)
+        .map(|span| Some(span.end))
+        .chain([None]);
+    for previous_end in candidate_ends {
+        let end = previous_end.map_or(src.len_chars(), |e| e.saturating_add_signed(offset));
+        let Some(region) = src.get_slice(start..end).map(|r| r.to_string()) else {
+            break;
+        };
+        let (Some(tokens), mut errors) = tokenizer().parse_recovery(region.as_str()) else {
+            break;
+        };
+        let ends_with_newline = tokens
+            .last()
+            .is_some_and(|t| t.0 == Token::Newline && t.1.end == end - start);
+        if previous_end.is_some() && !ends_with_newline {
+            continue;
+        }
+        let lines = parse(&tokens, &region);
+        errors.extend(validate_config_names(&lines));
+
+        // Combine the unchanged parts before and after the region with the new region
+        let previous_end = previous_end.unwrap_or(usize::MAX);
+        let start_offset = start as isize;
+        let tokens = (previous
+            .tokens
+            .iter()
+            .take_while(|t| t.1.end <= start)
+            .cloned())
+        .chain(
+            tokens
+                .iter()
+                .map(|t| (t.0.clone(), shift_span(&t.1, start_offset))),
+        )
+        .chain(
+            (previous.tokens.iter())
+                .filter(|t| t.1.start >= previous_end)
+                .map(|t| (t.0.clone(), shift_span(&t.1, offset))),
+        )
+        .collect();
+        let lines = (previous
+            .lines
+            .iter()
+            .take_while(|l| l.span.end <= start)
+            .cloned())
+        .chain(lines.iter().map(|l| shift_line(l, start_offset)))
+        .chain(
+            (previous.lines.iter())
+                .filter(|l| l.span.start >= previous_end)
+                .map(|l| shift_line(l, offset)),
+        )
+        .collect();
+        let errors = (previous
+            .errors
+            .iter()
+            .filter(|e| e.span().start < start)
+            .cloned())
+        .chain(errors.iter().map(|e| shift_error(e, start_offset)))
+        .chain(
+            (previous.errors.iter())
+                .filter(|e| e.span().start >= previous_end)
+                .map(|e| shift_error(e, offset)),
+        )
+        .collect();
+        return ParserResult {
+            tokens,
+            lines,
+            errors,
+        };
+    }
+    // Fall back to parsing the whole document
+    parse_from_str(&src.to_string())
+}
+
 #[test]
 fn test_command_specifier() {
     // The first token is the command name
@@ -169,12 +402,13 @@ fn test_command_specifier() {
         })
     );
 
-    // The command combined with some actual arguments
+    // The command combined with some actual arguments.
+    // The escaped `:` belongs to the config's span
     assert_eq!(
         parse_from_str("bu'ild\\:o'pt --x=y").lines,
         vec!(Line {
-            command: Some(("build".to_string(), 0..7)),
-            config: Some(("opt".to_string(), 7..12)),
+            command: Some(("build".to_string(), 0..6)),
+            config: Some(("opt".to_string(), 6..12)),
             flags: vec!(Flag {
                 name: Some(("--x".to_string(), 13..16)),
                 value: Some(("y".to_string(), 16..18)),
@@ -220,6 +454,28 @@ fn test_command_specifier() {
     );
 }
 
+#[test]
+fn test_separator_positions() {
+    // Separators are located precisely, also behind quotes and non-ASCII characters.
+    // Like in Bazel, escaped separators still separate
+    let lines = parse_from_str("# ä\nb'uild:ö' \"--x\"='y' --ü\\=z").lines;
+    assert_eq!(lines[1].command, Some(("build".to_string(), 4..10)));
+    assert_eq!(lines[1].config, Some(("ö".to_string(), 10..13)));
+    assert_eq!(
+        lines[1].flags,
+        vec!(
+            Flag {
+                name: Some(("--x".to_string(), 14..19)),
+                value: Some(("y".to_string(), 19..23)),
+            },
+            Flag {
+                name: Some(("--ü".to_string(), 24..27)),
+                value: Some(("z".to_string(), 27..30)),
+            }
+        )
+    );
+}
+
 #[test]
 fn test_flag_parsing() {
     // An unnamed flag with only a value
@@ -318,3 +574,177 @@ fn test_comments() {
         })
     );
 }
+
+#[test]
+fn test_error_recovery() {
+    use chumsky::error::SimpleReason;
+
+    // An unclosed quote extends until the end of its line. All other lines are still parsed
+    let result = parse_from_str("build --a\nbuild --b='x y\nbuild --c");
+    assert_eq!(
+        result.lines,
+        vec!(
+            Line {
+                command: Some(("build".to_string(), 0..5)),
+                flags: vec!(Flag {
+                    name: Some(("--a".to_string(), 6..9)),
+                    value: None
+                }),
+                span: 0..10,
+                ..Default::default()
+            },
+            Line {
+                command: Some(("build".to_string(), 10..15)),
+                flags: vec!(Flag {
+                    name: Some(("--b".to_string(), 16..19)),
+                    value: Some(("x y".to_string(), 19..24)),
+                }),
+                span: 10..25,
+                ..Default::default()
+            },
+            Line {
+                command: Some(("build".to_string(), 25..30)),
+                flags: vec!(Flag {
+                    name: Some(("--c".to_string(), 31..34)),
+                    value: None
+                }),
+                span: 25..34,
+                ..Default::default()
+            }
+        )
+    );
+    assert_eq!(
+        result
+            .errors
+            .iter()
+            .map(|e| (e.reason().clone(), e.span()))
+            .collect::<Vec<_>>(),
+        vec!((
+            SimpleReason::Custom(
+                "Unterminated quoted token, missing closing quote `'`".to_string()
+            ),
+            20..24
+        ))
+    );
+
+    // A trailing `\` does not discard the rest of the file
+    let result = parse_from_str("build --a \\");
+    assert_eq!(
+        result.lines[0].flags[0].name,
+        Some(("--a".to_string(), 6..9))
+    );
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].span(), 10..11);
+}
+
+#[test]
+fn test_reparse_incremental() {
+    // Applies the edit and compares the result against parsing the whole document
+    let check = |src: &str, changed: Span, new_text: &str| {
+        let previous = parse_from_str(src);
+        let mut rope = Rope::from_str(src);
+        rope.remove(changed.clone());
+        rope.insert(changed.start, new_text);
+        let incremental = reparse_incremental(&previous, &rope, &changed, new_text.chars().count());
+        let full = parse_from_str(&rope.to_string());
+        assert_eq!(incremental.tokens, full.tokens, "{:?}", rope.to_string());
+        assert_eq!(incremental.lines, full.lines, "{:?}", rope.to_string());
+        assert_eq!(incremental.errors, full.errors, "{:?}", rope.to_string());
+    };
+    let src = "build --a\n\n# comment\ntest:ci --b=1 \\\n  --c\nrun 'x\nrun --d";
+    // Changes within a single line
+    check(src, 8..9, "bc");
+    check(src, 23..25, "");
+    check(src, 0..0, "common --x\n");
+    // Changes which join or split lines
+    check(src, 9..10, "");
+    check(src, 9..9, " \\");
+    check(src, 35..36, "");
+    check(src, 14..16, "\nbuild");
+    // Changes introducing or fixing errors in some line
+    check(src, 49..49, "'");
+    check(src, 48..49, "");
+    check(src, src.len()..src.len(), "\\");
+    // Changes at the start or the end of the document, or replacing all of it
+    check(src, src.len()..src.len(), "\n");
+    check(src, 0..src.len(), "x");
+    check("", 0..0, "build --x");
+}
+
+#[test]
+fn test_import_lines() {
+    let lines =
+        parse_from_str("import a.bazelrc\ntry-import 'b c'\nimport\nimport --x y\nbuild x").lines;
+    assert_eq!(
+        lines.iter().map(|l| l.import.clone()).collect::<Vec<_>>(),
+        vec!(
+            Some(ImportLine {
+                keyword: ("import".to_string(), 0..6),
+                optional: false,
+                path: Some(("a.bazelrc".to_string(), 7..16)),
+            }),
+            Some(ImportLine {
+                keyword: ("try-import".to_string(), 17..27),
+                optional: true,
+                path: Some(("b c".to_string(), 28..33)),
+            }),
+            Some(ImportLine {
+                keyword: ("import".to_string(), 34..40),
+                optional: false,
+                path: None,
+            }),
+            Some(ImportLine {
+                keyword: ("import".to_string(), 41..47),
+                optional: false,
+                path: None,
+            }),
+            None
+        )
+    );
+    // The command and the path are still available as for any other line
+    assert_eq!(lines[0].command, Some(("import".to_string(), 0..6)));
+}
+
+#[test]
+fn test_invalid_config_names() {
+    let result = parse_from_str("build:opt -c opt\nbuild:-x --a\ntest:a=b --b\nbuild: --c");
+    assert_eq!(
+        result
+            .errors
+            .iter()
+            .map(|e| (format!("{:?}", e.reason()), e.span()))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "Custom(\"Config names can't start with `-`\")".to_string(),
+                22..25
+            ),
+            (
+                "Custom(\"Config names can't contain `=`\")".to_string(),
+                34..38
+            ),
+        ]
+    );
+    // The lines are still parsed
+    assert_eq!(result.lines[1].config, Some(("-x".to_string(), 22..25)));
+}
+
+#[test]
+fn test_mixed_line_endings() {
+    let result = parse_from_str("build --a\rbuild --b\r\nbuild --c\n");
+    assert_eq!(
+        result
+            .lines
+            .iter()
+            .map(|l| (l.flags[0].name.clone().unwrap(), l.span.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (("--a".to_string(), 6..9), 0..10),
+            (("--b".to_string(), 16..19), 10..21),
+            (("--c".to_string(), 27..30), 21..31),
+        ]
+    );
+    // The lone `\r` is still reported
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].span(), 9..10);
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    bazel_flags::BazelFlags,
+    file_utils::{get_default_rc_files, get_workspace_path, normalize_path},
+    import_graph::{FileCache, ImportGraph, ParsedFile},
+    tokenizer::Span,
+};
+
+// A line of one of the project's files
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProjectLocation {
+    pub file: PathBuf,
+    pub line_nr: usize,
+    pub span: Span,
+}
+
+// All rc files Bazel reads for a workspace.
+//
+// This consists of the system rc file, the workspace's `.bazelrc` and the user's `~/.bazelrc`,
+// together with all files imported by them. The files are taken from the `FileCache`, i.e.
+// open documents are represented by their version in the editor.
+#[derive(Debug)]
+pub struct Project {
+    // The rc files read by Bazel, in the order they are read
+    pub rc_files: Vec<PathBuf>,
+    pub graph: ImportGraph,
+    files: Vec<(PathBuf, Arc<ParsedFile>)>,
+}
+
+impl Project {
+    pub fn new(rc_files: Vec<PathBuf>, cache: &FileCache, bazel_flags: &BazelFlags) -> Project {
+        let graph = ImportGraph::build(&rc_files, cache, bazel_flags);
+        let files = graph
+            .files()
+            .into_iter()
+            .filter_map(|path| Some((path.to_path_buf(), cache.get(path, bazel_flags)?)))
+            .collect();
+        Project {
+            rc_files,
+            graph,
+            files,
+        }
+    }
+
+    // The project the given file belongs to. In case the file is not read by Bazel by
+    // default, it is treated as if it were passed as `--bazelrc`.
+    pub fn for_file(file_path: &Path, cache: &FileCache, bazel_flags: &BazelFlags) -> Project {
+        let mut rc_files = get_default_rc_files(get_workspace_path(file_path).as_deref());
+        let project = Project::new(rc_files.clone(), cache, bazel_flags);
+        let file_path = normalize_path(file_path);
+        if project.contains(&file_path) {
+            return project;
+        }
+        rc_files.push(file_path);
+        Project::new(rc_files, cache, bazel_flags)
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.iter().any(|(p, _)| p == path)
+    }
+
+    // All files of the project, in the order they are first read by Bazel
+    pub fn files(&self) -> impl Iterator<Item = (&Path, &ParsedFile)> {
+        self.files.iter().map(|(p, f)| (p.as_path(), f.as_ref()))
+    }
+
+    pub fn get_file(&self, path: &Path) -> Option<&ParsedFile> {
+        let path = normalize_path(path);
+        self.files().find(|(p, _)| *p == path).map(|(_, f)| f)
+    }
+
+    // All places setting the given flag, including its `--no` form and abbreviation.
+    // Unknown flags, e.g. Starlark flags, are matched by their name.
+    pub fn find_flag(&self, invocation: &str, bazel_flags: &BazelFlags) -> Vec<ProjectLocation> {
+        let info = bazel_flags.get_by_invocation(invocation);
+        let matches = |name: &str| match (info, bazel_flags.get_by_invocation(name)) {
+            (Some(a), Some(b)) => a.name == b.name,
+            (None, None) => name == invocation,
+            _ => false,
+        };
+        let mut locations = Vec::<ProjectLocation>::new();
+        for (path, file) in self.files() {
+            for (line_nr, line) in file.lines.iter().enumerate() {
+                for name in line.flags.iter().filter_map(|f| f.name.as_ref()) {
+                    if matches(&name.0) {
+                        locations.push(ProjectLocation {
+                            file: path.to_path_buf(),
+                            line_nr,
+                            span: name.1.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        locations
+    }
+
+    // All lines defining the given config, e.g. `build:opt` for `opt`
+    pub fn find_config(&self, name: &str) -> Vec<ProjectLocation> {
+        let mut locations = Vec::<ProjectLocation>::new();
+        for (path, file) in self.files() {
+            for (line_nr, line) in file.lines.iter().enumerate() {
+                if let Some(config) = line.config.as_ref().filter(|c| c.0 == name) {
+                    locations.push(ProjectLocation {
+                        file: path.to_path_buf(),
+                        line_nr,
+                        span: config.1.clone(),
+                    });
+                }
+            }
+        }
+        locations
+    }
+
+    // The names of all configs defined within the project
+    pub fn configs(&self) -> BTreeSet<&str> {
+        self.files()
+            .flat_map(|(_, f)| &f.lines)
+            .filter_map(|l| Some(l.config.as_ref()?.0.as_str()))
+            .collect()
+    }
+}
+
+#[test]
+fn test_project() {
+    use crate::bazel_flags::load_bazel_flags;
+    use crate::import_graph::create_test_workspace;
+
+    let bazel_flags = load_bazel_flags();
+    let workspace = create_test_workspace(
+        "project",
+        &[
+            (
+                ".bazelrc",
+                "build:opt -c opt\nimport %workspace%/common.bazelrc\nbuild --keep_going",
+            ),
+            ("common.bazelrc", "build:ci --nokeep_going --//pkg:setting"),
+            ("user.bazelrc", "build:opt --jobs=4 --//pkg:setting=1"),
+            ("unrelated.bazelrc", "build --keep_going"),
+        ],
+    );
+    let cache = FileCache::default();
+    let project = Project::new(
+        vec![workspace.join(".bazelrc"), workspace.join("user.bazelrc")],
+        &cache,
+        &bazel_flags,
+    );
+    let files = project
+        .files()
+        .map(|(p, _)| p.strip_prefix(&workspace).unwrap().to_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(files, vec![".bazelrc", "common.bazelrc", "user.bazelrc"]);
+    assert!(project
+        .get_file(&workspace.join("common.bazelrc"))
+        .is_some());
+    assert!(project
+        .get_file(&workspace.join("unrelated.bazelrc"))
+        .is_none());
+
+    let describe = |locations: Vec<ProjectLocation>| {
+        locations
+            .into_iter()
+            .map(|l| {
+                let file = l.file.strip_prefix(&workspace).unwrap().to_owned();
+                (file.to_str().unwrap().to_string(), l.line_nr, l.span)
+            })
+            .collect::<Vec<_>>()
+    };
+    let expected = vec![
+        (".bazelrc".to_string(), 2, 57..69),
+        ("common.bazelrc".to_string(), 0, 9..23),
+    ];
+    assert_eq!(describe(project.find_flag("-k", &bazel_flags)), expected);
+    assert_eq!(
+        describe(project.find_flag("--//pkg:setting", &bazel_flags)),
+        vec![
+            ("common.bazelrc".to_string(), 0, 24..39),
+            ("user.bazelrc".to_string(), 0, 19..34),
+        ]
+    );
+    assert_eq!(
+        describe(project.find_config("opt")),
+        vec![
+            (".bazelrc".to_string(), 0, 5..9),
+            ("user.bazelrc".to_string(), 0, 5..9),
+        ]
+    );
+    assert_eq!(
+        project.configs().into_iter().collect::<Vec<_>>(),
+        vec!["ci", "opt"]
+    );
+}
@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    bazel_flags::{BazelFlags, Invocation},
+    effective_config::{EffectiveConfig, EffectiveFlag},
+    formatting::format_token,
+};
+
+// A flag which was added, removed or changed between two bazelrc files
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlagChange {
+    Added(String),
+    Removed(String),
+    Changed {
+        name: String,
+        old: String,
+        new: String,
+    },
+}
+
+// The changes of the effective flags of `bazel <command>`, or of the flags added by
+// `--config=<config>`. Commands with the same changes are grouped together.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SectionDiff {
+    pub commands: Vec<String>,
+    pub config: Option<String>,
+    pub changes: Vec<FlagChange>,
+}
+
+// A flag, normalized so that different spellings of the same setting compare equal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NormalizedFlag {
+    // A flag which can only be set once. Later values override earlier values.
+    SingleValued { name: String, value: Option<String> },
+    // A flag which might be repeated, an unknown flag or a value without a flag name
+    MultiValued(String),
+}
+
+// The effective flags of a command or config, normalized for comparison
+#[derive(Default)]
+struct NormalizedFlags {
+    single_valued: BTreeMap<String, Option<String>>,
+    multi_valued: BTreeSet<String>,
+}
+
+fn format_flag(name: &str, value: &Option<String>) -> String {
+    match value {
+        Some(v) => format!("{}={}", name, format_token(v)),
+        None => name.to_string(),
+    }
+}
+
+impl NormalizedFlag {
+    pub fn format(&self) -> String {
+        match self {
+            NormalizedFlag::SingleValued { name, value } => format_flag(name, value),
+            NormalizedFlag::MultiValued(str) => str.clone(),
+        }
+    }
+}
+
+// Normalizes a flag.
+//
+// Abbreviations are replaced by the long flag name, and boolean flags always get
+// an explicit value, i.e. both `-k` and `--keep_going=true` are normalized to
+// `--keep_going=true` and `--nokeep_going` is normalized to `--keep_going=false`.
+pub fn normalize_flag(flag: &crate::parser::Flag, bazel_flags: &BazelFlags) -> NormalizedFlag {
+    let value = flag.value.as_ref().map(|v| v.0.clone());
+    let Some(name) = &flag.name else {
+        return NormalizedFlag::MultiValued(format_token(&value.unwrap_or_default()));
+    };
+    let Some(Invocation {
+        flag: info,
+        negated,
+        ..
+    }) = bazel_flags.resolve_invocation(&name.0)
+    else {
+        // Unknown flags are compared verbatim
+        return NormalizedFlag::MultiValued(format_flag(&format_token(&name.0), &value));
+    };
+    let canonical_name = format!("--{}", info.name);
+    let normalized_value = if value.is_none() && info.has_negative_flag() {
+        Some(if negated { "false" } else { "true" }.to_string())
+    } else {
+        value
+    };
+    if info.allows_multiple() {
+        NormalizedFlag::MultiValued(format_flag(&canonical_name, &normalized_value))
+    } else {
+        NormalizedFlag::SingleValued {
+            name: canonical_name,
+            value: normalized_value,
+        }
+    }
+}
+
+// Normalizes the effective flags. For flags which can only be set once, the last value wins.
+fn normalize_flags(flags: &[EffectiveFlag], bazel_flags: &BazelFlags) -> NormalizedFlags {
+    let mut normalized = NormalizedFlags::default();
+    for flag in flags {
+        match normalize_flag(&flag.flag, bazel_flags) {
+            NormalizedFlag::SingleValued { name, value } => {
+                normalized.single_valued.insert(name, value);
+            }
+            NormalizedFlag::MultiValued(str) => {
+                normalized.multi_valued.insert(str);
+            }
+        }
+    }
+    normalized
+}
+
+fn diff_flags(old: &NormalizedFlags, new: &NormalizedFlags) -> Vec<FlagChange> {
+    let mut changes = Vec::<FlagChange>::new();
+
+    // Single-valued flags
+    for (name, old_value) in &old.single_valued {
+        match new.single_valued.get(name) {
+            None => changes.push(FlagChange::Removed(format_flag(name, old_value))),
+            Some(new_value) if new_value != old_value => changes.push(FlagChange::Changed {
+                name: name.clone(),
+                old: format_flag(name, old_value),
+                new: format_flag(name, new_value),
+            }),
+            Some(_) => (),
+        }
+    }
+    for (name, new_value) in &new.single_valued {
+        if !old.single_valued.contains_key(name) {
+            changes.push(FlagChange::Added(format_flag(name, new_value)));
+        }
+    }
+
+    // Multi-valued flags
+    changes.extend(
+        old.multi_valued
+            .difference(&new.multi_valued)
+            .map(|v| FlagChange::Removed(v.clone())),
+    );
+    changes.extend(
+        new.multi_valued
+            .difference(&old.multi_valued)
+            .map(|v| FlagChange::Added(v.clone())),
+    );
+    changes
+}
+
+// The commands compared by default: `startup` and all of Bazel's commands
+pub fn diff_commands(bazel_flags: &BazelFlags) -> Vec<String> {
+    let mut commands = bazel_flags
+        .commands
+        .iter()
+        .filter(|c| !["common", "always", "import", "try-import"].contains(&c.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    commands.sort();
+    commands.insert(0, "startup".to_string());
+    commands
+}
+
+// Compares the effective flags of two sets of rc files, for each of the given commands.
+//
+// Imports are followed and flags are inherited along the command chain, i.e. the flags of
+// `bazel <command>` are compared, as well as the flags each config adds to them. In contrast
+// to a textual diff, reformatting, reordering lines, splitting flags across multiple lines,
+// moving flags into imported files or using abbreviated flag names does not show up as a
+// difference. Platform-specific configs are compared like any other config.
+pub fn diff_effective_configs(
+    old: &EffectiveConfig,
+    new: &EffectiveConfig,
+    commands: &[String],
+    bazel_flags: &BazelFlags,
+) -> Vec<SectionDiff> {
+    let configs = std::iter::once(None)
+        .chain(old.configs().union(&new.configs()).cloned().map(Some))
+        .collect::<Vec<_>>();
+    let mut diffs = Vec::<SectionDiff>::new();
+    for config in configs {
+        let expand = |effective_config: &EffectiveConfig, command: &str| {
+            let expansion = match &config {
+                Some(config) => effective_config.expand_config(command, config),
+                None => effective_config.expand_on_platform(command, &[], None),
+            };
+            normalize_flags(&expansion.flags, bazel_flags)
+        };
+        let first_diff = diffs.len();
+        for command in commands {
+            let changes = diff_flags(&expand(old, command), &expand(new, command));
+            if changes.is_empty() {
+                continue;
+            }
+            match diffs[first_diff..]
+                .iter_mut()
+                .find(|d| d.changes == changes)
+            {
+                Some(diff) => diff.commands.push(command.clone()),
+                None => diffs.push(SectionDiff {
+                    commands: vec![command.clone()],
+                    config: config.clone(),
+                    changes,
+                }),
+            }
+        }
+    }
+    diffs
+}
+
+pub fn format_diff(diffs: &[SectionDiff]) -> String {
+    let mut out = String::new();
+    for diff in diffs {
+        let commands = diff
+            .commands
+            .iter()
+            .map(|c| format_token(c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        match &diff.config {
+            Some(config) => out += &format!("--config={} ({})\n", format_token(config), commands),
+            None => out += &format!("{}\n", commands),
+        }
+        for change in &diff.changes {
+            match change {
+                FlagChange::Added(flag) => out += &format!("  + {}\n", flag),
+                FlagChange::Removed(flag) => out += &format!("  - {}\n", flag),
+                FlagChange::Changed { old, new, .. } => out += &format!("  ~ {} -> {}\n", old, new),
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+fn diff_files(name: &str, files: &[(&str, &str)], commands: &[&str]) -> Vec<SectionDiff> {
+    use crate::{
+        bazel_flags::load_bazel_flags,
+        import_graph::{create_test_workspace, FileCache},
+    };
+
+    // Compares `old.bazelrc` with `new.bazelrc`
+    let ws = create_test_workspace(name, files);
+    let bazel_flags = load_bazel_flags();
+    let cache = FileCache::default();
+    let old = EffectiveConfig::new(&[ws.join("old.bazelrc")], &cache, &bazel_flags);
+    let new = EffectiveConfig::new(&[ws.join("new.bazelrc")], &cache, &bazel_flags);
+    let commands = commands.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    diff_effective_configs(&old, &new, &commands, &bazel_flags)
+}
+
+#[cfg(test)]
+fn diff_strings(old: &str, new: &str) -> Vec<SectionDiff> {
+    use std::hash::{Hash, Hasher};
+
+    // Tests run in parallel, so each pair of files gets its own workspace
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    (old, new).hash(&mut hasher);
+    diff_files(
+        &format!("diff-{:x}", hasher.finish()),
+        &[("old.bazelrc", old), ("new.bazelrc", new)],
+        &["build", "test"],
+    )
+}
+
+#[test]
+fn test_diff_ignores_formatting() {
+    // Reformatting, reordering and splitting lines
+    assert_eq!(
+        diff_strings(
+            "build --keep_going --disk_cache=/tmp",
+            "build --disk_cache \"/tmp\"\nbuild   -k\n"
+        ),
+        vec![]
+    );
+    // Boolean flags with implicit and explicit values
+    assert_eq!(
+        diff_strings("build --keep_going", "build --keep_going=true"),
+        vec![]
+    );
+    // Overridden flags only count with their effective value
+    assert_eq!(
+        diff_strings("build --jobs=2\nbuild --jobs=4", "build --jobs=4"),
+        vec![]
+    );
+}
+
+#[test]
+fn test_diff_changes() {
+    let changed_keep_going = FlagChange::Changed {
+        name: "--keep_going".to_string(),
+        old: "--keep_going=true".to_string(),
+        new: "--keep_going=false".to_string(),
+    };
+    assert_eq!(
+        diff_strings(
+            "build --keep_going\nbuild:opt -c opt",
+            "build --nokeep_going\ntest --copt=-O2\nbuild:opt -c dbg"
+        ),
+        vec![
+            SectionDiff {
+                commands: vec!["build".to_string()],
+                config: None,
+                changes: vec![changed_keep_going.clone()],
+            },
+            // `test` inherits the flags of `build`
+            SectionDiff {
+                commands: vec!["test".to_string()],
+                config: None,
+                changes: vec![
+                    changed_keep_going,
+                    FlagChange::Added("--copt=-O2".to_string())
+                ],
+            },
+            SectionDiff {
+                commands: vec!["build".to_string(), "test".to_string()],
+                config: Some("opt".to_string()),
+                changes: vec![FlagChange::Changed {
+                    name: "--compilation_mode".to_string(),
+                    old: "--compilation_mode=opt".to_string(),
+                    new: "--compilation_mode=dbg".to_string(),
+                }],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_diff_follows_imports() {
+    // Moving flags into an imported file or to a parent command doesn't change them
+    assert_eq!(
+        diff_files(
+            "diff-imports",
+            &[
+                (
+                    "old.bazelrc",
+                    "import %workspace%/c.bazelrc\nbuild --jobs=4"
+                ),
+                ("c.bazelrc", "build -k"),
+                ("new.bazelrc", "common --keep_going\nbuild --jobs 4"),
+            ],
+            &["build", "test"],
+        ),
+        vec![]
+    );
+    // ... except for the commands which don't inherit the flags
+    assert_eq!(
+        diff_files(
+            "diff-commands",
+            &[
+                ("old.bazelrc", "common --keep_going"),
+                ("new.bazelrc", "build --keep_going"),
+            ],
+            &["build", "query", "test"],
+        ),
+        vec![SectionDiff {
+            commands: vec!["query".to_string()],
+            config: None,
+            changes: vec![FlagChange::Removed("--keep_going=true".to_string())],
+        }]
+    );
+}
+
+#[test]
+fn test_diff_multi_valued_flags() {
+    // Flags which can be repeated are compared value by value
+    assert_eq!(
+        diff_strings(
+            "build --copt=-O2 --copt=-g --unknown_flag",
+            "build --copt=-g --copt=-Wall"
+        ),
+        vec![SectionDiff {
+            commands: vec!["build".to_string(), "test".to_string()],
+            config: None,
+            changes: vec![
+                FlagChange::Removed("--copt=-O2".to_string()),
+                FlagChange::Removed("--unknown_flag".to_string()),
+                FlagChange::Added("--copt=-Wall".to_string()),
+            ],
+        }]
+    );
+}
+
+#[test]
+fn test_format_diff() {
+    let diffs = diff_strings("build:opt -c opt", "build --jobs=4\nbuild:opt -c dbg");
+    assert_eq!(
+        format_diff(&diffs),
+        "build, test\n  + --jobs=4\n--config=opt (build, test)\n  ~ --compilation_mode=opt -> --compilation_mode=dbg\n"
+    );
+}
@@ -0,0 +1,313 @@
+use crate::{
+    bazel_flags::BazelFlags,
+    bazel_flags_proto::FlagInfo,
+    parser::{Flag, ImportLine, Line},
+};
+
+// What a line of an rc file does
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LineKind {
+    // Lines without command and flags, i.e. comment-only lines
+    Empty,
+    // Flags for a Bazel command, including `startup`, `common` and `always`
+    Command,
+    Import(ImportLine),
+    UnknownCommand,
+    // Flags without a leading command
+    MissingCommand,
+}
+
+// A flag, resolved against the flag metadata
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RcFlag {
+    pub flag: Flag,
+    // The index into `BazelFlags::flags`. Missing for unknown flags, flag aliases,
+    // Starlark flags and tokens without a flag name.
+    pub info: Option<usize>,
+    // Whether the `--no` form of a boolean flag is used
+    pub negated: bool,
+    // The commands the flag is applied to. On `common` lines, flags are only applied
+    // to the commands supporting them.
+    pub commands: Vec<String>,
+    // The commands the flag is passed to, but which don't support it. Bazel fails for those.
+    pub unsupported_commands: Vec<String>,
+}
+
+impl RcFlag {
+    pub fn info<'a>(&self, bazel_flags: &'a BazelFlags) -> Option<&'a FlagInfo> {
+        self.info.map(|i| &bazel_flags.flags[i])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RcLine {
+    pub line: Line,
+    pub kind: LineKind,
+    // The Bazel commands the line applies to, see `expand_command`
+    pub commands: Vec<String>,
+    // The flags of the line, in the same order as `line.flags`
+    pub flags: Vec<RcFlag>,
+}
+
+impl RcLine {
+    pub fn command(&self) -> Option<&str> {
+        self.line.command.as_ref().map(|c| c.0.as_str())
+    }
+
+    pub fn config(&self) -> Option<&str> {
+        self.line.config.as_ref().map(|c| c.0.as_str())
+    }
+
+    // Whether the line uses a config although its command does not support configs
+    pub fn has_unsupported_config(&self) -> bool {
+        self.config().is_some() && self.command().is_some_and(|c| !supports_configs(c))
+    }
+}
+
+// The semantic model of an rc file.
+//
+// Commands, imports and flags are resolved against the flag metadata once, such that
+// features working on the file do not need to look them up from the raw strings again.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct RcFile {
+    pub lines: Vec<RcLine>,
+}
+
+// The Bazel commands a line for the given command applies to. `common` and `always` apply
+// to all commands except for `startup`. Unknown commands don't apply to any command.
+pub fn expand_command<'a>(command: &'a str, bazel_flags: &'a BazelFlags) -> Vec<&'a str> {
+    match command {
+        "common" | "always" => {
+            let mut commands = bazel_flags
+                .flags_by_commands
+                .keys()
+                .map(|c| c.as_str())
+                .filter(|c| !["startup", "common", "always"].contains(c))
+                .collect::<Vec<_>>();
+            commands.sort();
+            commands
+        }
+        _ if bazel_flags.flags_by_commands.contains_key(command) => vec![command],
+        _ => vec![],
+    }
+}
+
+// Whether lines for the given command can be restricted to a config, e.g. `build:opt`.
+// Bazel does not support configs for `startup` options and imports.
+pub fn supports_configs(command: &str) -> bool {
+    !["startup", "import", "try-import"].contains(&command)
+}
+
+// Startup options can only be used on `startup` lines
+pub fn is_startup_flag(info: &FlagInfo) -> bool {
+    info.commands.iter().all(|c| c == "startup")
+}
+
+fn resolve_flag(flag: &Flag, line_command: &str, bazel_flags: &BazelFlags) -> RcFlag {
+    let resolved = flag
+        .name
+        .as_ref()
+        .and_then(|name| bazel_flags.resolve_invocation(&name.0));
+    let info = resolved.and_then(|r| bazel_flags.flags_by_name.get(&r.flag.name).copied());
+    let negated = resolved.is_some_and(|r| r.negated);
+    let line_commands = expand_command(line_command, bazel_flags);
+    let (supported, unsupported): (Vec<&str>, Vec<&str>) = match info {
+        Some(i) => line_commands
+            .iter()
+            .partition(|c| bazel_flags.flags[i].supports_command(c)),
+        None => (line_commands.clone(), vec![]),
+    };
+    let (commands, unsupported_commands) = match line_command {
+        // Bazel ignores `common` flags for commands which don't support them, as long as
+        // any of the commands supports the flag
+        "common" if !supported.is_empty() => (supported, vec![]),
+        "common" => (vec![], unsupported),
+        _ => (line_commands, unsupported),
+    };
+    let to_strings = |c: Vec<&str>| c.into_iter().map(|c| c.to_string()).collect();
+    RcFlag {
+        flag: flag.clone(),
+        info,
+        negated,
+        commands: to_strings(commands),
+        unsupported_commands: to_strings(unsupported_commands),
+    }
+}
+
+impl RcFile {
+    // Builds the model from the parsed lines, after combining `--flag value` pairs
+    pub fn new(lines: &[Line], bazel_flags: &BazelFlags) -> RcFile {
+        let lines = lines
+            .iter()
+            .map(|line| {
+                let kind = match (&line.command, &line.import) {
+                    (_, Some(import)) => LineKind::Import(import.clone()),
+                    (Some(command), None) => {
+                        if bazel_flags.flags_by_commands.contains_key(&command.0) {
+                            LineKind::Command
+                        } else {
+                            LineKind::UnknownCommand
+                        }
+                    }
+                    (None, None) if line.flags.is_empty() => LineKind::Empty,
+                    (None, None) => LineKind::MissingCommand,
+                };
+                let command = match kind {
+                    LineKind::Command => line.command.as_ref().map_or("", |c| c.0.as_str()),
+                    _ => "",
+                };
+                RcLine {
+                    line: line.clone(),
+                    kind,
+                    commands: expand_command(command, bazel_flags)
+                        .into_iter()
+                        .map(|c| c.to_string())
+                        .collect(),
+                    flags: line
+                        .flags
+                        .iter()
+                        .map(|f| resolve_flag(f, command, bazel_flags))
+                        .collect(),
+                }
+            })
+            .collect();
+        RcFile { lines }
+    }
+
+    pub fn flag(&self, line_nr: usize, flag_nr: usize) -> Option<&RcFlag> {
+        self.lines.get(line_nr)?.flags.get(flag_nr)
+    }
+
+    pub fn imports(&self) -> impl Iterator<Item = &ImportLine> {
+        self.lines.iter().filter_map(|l| match &l.kind {
+            LineKind::Import(import) => Some(import),
+            _ => None,
+        })
+    }
+}
+
+#[test]
+fn test_semantic_model() {
+    use crate::bazel_flags::{combine_key_value_flags, load_bazel_flags};
+    use crate::parser::parse_from_str;
+
+    let bazel_flags = load_bazel_flags();
+    let mut lines = parse_from_str(
+        "# comment\nbuild:ci --nokeep_going --jobs 8 --unknown\ntry-import x.bazelrc\nbuidl -k\n--x",
+    )
+    .lines;
+    combine_key_value_flags(&mut lines, &bazel_flags);
+    let model = RcFile::new(&lines, &bazel_flags);
+
+    assert_eq!(
+        model
+            .lines
+            .iter()
+            .map(|l| match &l.kind {
+                LineKind::Import(import) => format!("import {}", import.optional),
+                kind => format!("{:?}", kind),
+            })
+            .collect::<Vec<_>>(),
+        vec![
+            "Empty",
+            "Command",
+            "import true",
+            "UnknownCommand",
+            "MissingCommand"
+        ]
+    );
+    assert_eq!(model.lines[1].command(), Some("build"));
+    assert_eq!(model.lines[1].config(), Some("ci"));
+
+    let flags = &model.lines[1].flags;
+    assert_eq!(flags.len(), 3);
+    assert_eq!(
+        flags[0].info(&bazel_flags).map(|f| f.name.as_str()),
+        Some("keep_going")
+    );
+    assert!(flags[0].negated);
+    assert_eq!(
+        flags[1].info(&bazel_flags).map(|f| f.name.as_str()),
+        Some("jobs")
+    );
+    assert_eq!(
+        flags[1].flag.value.as_ref().map(|v| v.0.as_str()),
+        Some("8")
+    );
+    assert!(!flags[1].negated);
+    assert_eq!(flags[2].info, None);
+
+    assert_eq!(
+        model
+            .imports()
+            .map(|i| i.path.as_ref().unwrap().0.as_str())
+            .collect::<Vec<_>>(),
+        vec!["x.bazelrc"]
+    );
+}
+
+#[test]
+fn test_common_semantics() {
+    use crate::bazel_flags::load_bazel_flags;
+    use crate::parser::parse_from_str;
+
+    let bazel_flags = load_bazel_flags();
+    let lines = parse_from_str(
+        "common --keep_going --output_base=x --//my:setting\nalways --keep_going\nbuild --keep_going",
+    )
+    .lines;
+    let model = RcFile::new(&lines, &bazel_flags);
+    let all_commands = expand_command("common", &bazel_flags);
+    assert!(all_commands.contains(&"build") && all_commands.contains(&"version"));
+    assert!(!all_commands.contains(&"startup"));
+    assert_eq!(model.lines[0].commands, all_commands);
+
+    // `common` flags only apply to the commands supporting them
+    let keep_going = &model.lines[0].flags[0];
+    assert!(keep_going.commands.contains(&"test".to_string()));
+    assert!(!keep_going.commands.contains(&"version".to_string()));
+    assert!(keep_going.unsupported_commands.is_empty());
+    // Startup flags are not supported by any command
+    let output_base = &model.lines[0].flags[1];
+    assert!(output_base.commands.is_empty());
+    assert_eq!(output_base.unsupported_commands, all_commands);
+    // Unknown flags are assumed to apply everywhere
+    assert_eq!(model.lines[0].flags[2].commands, all_commands);
+
+    // `always` flags are passed to all commands, even if they don't support them
+    let always = &model.lines[1].flags[0];
+    assert_eq!(always.commands, all_commands);
+    assert!(always.unsupported_commands.contains(&"version".to_string()));
+
+    assert_eq!(model.lines[2].commands, vec!["build"]);
+    assert_eq!(model.lines[2].flags[0].commands, vec!["build"]);
+}
+
+#[test]
+fn test_startup_semantics() {
+    use crate::bazel_flags::load_bazel_flags;
+    use crate::parser::parse_from_str;
+
+    let bazel_flags = load_bazel_flags();
+    let lines =
+        parse_from_str("startup --output_base=x --keep_going\nstartup:ci --batch\nbuild:ci -k")
+            .lines;
+    let model = RcFile::new(&lines, &bazel_flags);
+    assert_eq!(model.lines[0].commands, vec!["startup"]);
+    assert!(model.lines[0].flags[0].unsupported_commands.is_empty());
+    assert!(is_startup_flag(
+        model.lines[0].flags[0].info(&bazel_flags).unwrap()
+    ));
+    // Only startup options are accepted on `startup` lines
+    assert_eq!(
+        model.lines[0].flags[1].unsupported_commands,
+        vec!["startup"]
+    );
+    assert!(!is_startup_flag(
+        model.lines[0].flags[1].info(&bazel_flags).unwrap()
+    ));
+    // Configs are not supported on `startup` lines
+    assert!(!model.lines[0].has_unsupported_config());
+    assert!(model.lines[1].has_unsupported_config());
+    assert!(!model.lines[2].has_unsupported_config());
+}
@@ -0,0 +1,186 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::Deserialize;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+// The project file with the flag policy of a workspace, located next to the workspace's `.bazelrc`
+pub const POLICY_FILE_NAME: &str = ".bazelrc-lsp.json";
+
+// The settings of the language server.
+//
+// Settings are passed as `initializationOptions` and through `workspace/didChangeConfiguration`.
+// Clients may either send the settings directly or nested within a `bazelrc` section.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    // Validate lines using `bazel canonicalize-flags` on open and save.
+    // Off by default, since it requires a local Bazel and starts a Bazel server.
+    pub validate_with_bazel: bool,
+    // Show inlay hints with the files defining the configs referenced through `--config`
+    pub config_location_hints: bool,
+    // Show inlay hints with the implicit `=true` of boolean flags without value
+    pub implicit_value_hints: bool,
+    // Report diagnostics indicating that Bazel might fail (e.g., unsupported flags or
+    // configs which might be undefined) as errors instead of warnings
+    pub strict: bool,
+    // Warn about flag values which look like secrets, e.g. access tokens
+    pub detect_secrets: bool,
+    // Report abbreviated flags like `-k`, which are less readable than their long names
+    pub report_abbreviations: bool,
+    // Report experimental flags, which might break between Bazel releases
+    pub report_experimental_flags: bool,
+    // Report flags which are set to their default value
+    pub report_redundant_defaults: bool,
+    // Report flags whose default value changes when upgrading to this Bazel version
+    pub upgrade_target_version: Option<String>,
+    // Sort flag completions by their documentation category instead of by name
+    pub sort_completions_by_category: bool,
+    // Severity overrides per diagnostic kind, e.g. `{"unknown-flag": "error", "unused-config": "off"}`.
+    // Overrides take precedence over `strict`.
+    pub diagnostics: BTreeMap<String, DiagnosticLevel>,
+    // Flags which must not be used, in addition to the ones from the workspace's policy file
+    pub banned_flags: Vec<BannedFlag>,
+}
+
+// The severity of a diagnostic kind, as configured by the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticLevel {
+    // Don't report the diagnostic at all
+    Off,
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl DiagnosticLevel {
+    // The LSP severity, or `None` for diagnostics which are turned off
+    pub fn severity(&self) -> Option<DiagnosticSeverity> {
+        match self {
+            DiagnosticLevel::Off => None,
+            DiagnosticLevel::Error => Some(DiagnosticSeverity::ERROR),
+            DiagnosticLevel::Warning => Some(DiagnosticSeverity::WARNING),
+            DiagnosticLevel::Information => Some(DiagnosticSeverity::INFORMATION),
+            DiagnosticLevel::Hint => Some(DiagnosticSeverity::HINT),
+        }
+    }
+}
+
+// A flag which must not be used, e.g. because it should only be set in the user's own rc file
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BannedFlag {
+    // The flag, e.g. `--disk_cache`. Abbreviations and the `--no` form are banned, too.
+    pub flag: String,
+    // Why the flag is banned and what to do instead
+    pub message: Option<String>,
+    // Defaults to `warning`
+    pub severity: Option<DiagnosticLevel>,
+    // Glob patterns for the files the ban applies to, relative to the workspace root,
+    // e.g. `.bazelrc`. The ban applies to all files if empty.
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+// The contents of the policy file
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Policy {
+    pub banned_flags: Vec<BannedFlag>,
+}
+
+impl Policy {
+    // Reads the policy file of the workspace. A missing file is the same as an empty policy.
+    pub fn load(workspace: &Path) -> Result<Policy, String> {
+        let path = workspace.join(POLICY_FILE_NAME);
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Ok(Policy::default());
+        };
+        serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            validate_with_bazel: false,
+            config_location_hints: true,
+            implicit_value_hints: true,
+            strict: false,
+            detect_secrets: false,
+            report_abbreviations: false,
+            report_experimental_flags: false,
+            report_redundant_defaults: false,
+            upgrade_target_version: None,
+            sort_completions_by_category: false,
+            diagnostics: BTreeMap::new(),
+            banned_flags: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_json(value: &serde_json::Value) -> Option<Settings> {
+        let value = value.get("bazelrc").unwrap_or(value);
+        serde_json::from_value(value.clone()).ok()
+    }
+}
+
+#[test]
+fn test_settings_from_json() {
+    let settings = Settings::from_json(&serde_json::json!({"validateWithBazel": true})).unwrap();
+    assert!(settings.validate_with_bazel);
+    assert!(settings.config_location_hints);
+    assert!(!settings.strict);
+    let settings =
+        Settings::from_json(&serde_json::json!({"bazelrc": {"validateWithBazel": true}})).unwrap();
+    assert!(settings.validate_with_bazel);
+    assert_eq!(
+        Settings::from_json(&serde_json::json!({})),
+        Some(Settings::default())
+    );
+    assert_eq!(Settings::from_json(&serde_json::json!(null)), None);
+
+    let settings = Settings::from_json(
+        &serde_json::json!({"diagnostics": {"unknown-flag": "error", "unused-config": "off"}}),
+    )
+    .unwrap();
+    assert_eq!(
+        settings.diagnostics.get("unknown-flag"),
+        Some(&DiagnosticLevel::Error)
+    );
+    assert_eq!(
+        settings.diagnostics.get("unused-config"),
+        Some(&DiagnosticLevel::Off)
+    );
+    assert_eq!(
+        Settings::from_json(&serde_json::json!({"diagnostics": {"unknown-flag": "loud"}})),
+        None
+    );
+}
+
+#[test]
+fn test_load_policy() {
+    let ws = crate::import_graph::create_test_workspace(
+        "policy",
+        &[(
+            POLICY_FILE_NAME,
+            r#"{"bannedFlags": [{"flag": "--disk_cache", "severity": "error", "files": [".bazelrc"]}]}"#,
+        )],
+    );
+    let policy = Policy::load(&ws).unwrap();
+    assert_eq!(
+        policy.banned_flags,
+        vec![BannedFlag {
+            flag: "--disk_cache".to_string(),
+            message: None,
+            severity: Some(DiagnosticLevel::Error),
+            files: vec![".bazelrc".to_string()],
+        }]
+    );
+    assert_eq!(Policy::load(&ws.join("missing")), Ok(Policy::default()));
+
+    std::fs::write(ws.join(POLICY_FILE_NAME), "{").unwrap();
+    assert!(Policy::load(&ws).is_err());
+}
@@ -0,0 +1,62 @@
+// The Levenshtein distance between two strings, counted in chars
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+// Finds the candidate closest to the given name, as long as it is similar enough
+// to likely be a typo. Ties are broken by the order of the candidates.
+pub fn find_similar<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    find_all_similar(name, candidates, 1).pop()
+}
+
+// Like `find_similar`, but returns up to `limit` candidates, closest first
+pub fn find_all_similar<'a>(
+    name: &str,
+    candidates: impl Iterator<Item = &'a str>,
+    limit: usize,
+) -> Vec<&'a str> {
+    // Allow roughly one typo per three characters, but at most 3
+    let max_distance = (name.chars().count() / 3).clamp(1, 3);
+    let mut similar = candidates
+        .filter(|c| *c != name)
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect::<Vec<_>>();
+    similar.sort_by_key(|(distance, _)| *distance);
+    similar.into_iter().take(limit).map(|(_, c)| c).collect()
+}
+
+#[test]
+fn test_suggestions() {
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("relese", "release"), 1);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("äb", "ab"), 1);
+
+    let configs = ["debug", "release", "remote"];
+    assert_eq!(
+        find_similar("relese", configs.iter().copied()),
+        Some("release")
+    );
+    assert_eq!(find_similar("ci", configs.iter().copied()), None);
+    assert_eq!(find_similar("xyzzy", configs.iter().copied()), None);
+    assert_eq!(
+        find_all_similar(
+            "keep_goin",
+            ["keep_goings", "keep_going", "keep"].into_iter(),
+            2
+        ),
+        vec!["keep_going", "keep_goings"]
+    );
+}
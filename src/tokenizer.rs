@@ -25,6 +25,16 @@ impl fmt::Display for Token {
     }
 }
 
+// The byte order mark. When reading files, it is stripped by `file_utils::decode_bazelrc`,
+// but editors might still send it as part of the document.
+pub const BOM: char = '\u{feff}';
+
+// Labels of the errors reported by the tokenizer, e.g. used to provide hints on how
+// to fix them
+pub const UNCLOSED_QUOTE: &str = "unclosed quote";
+pub const TRAILING_BACKSLASH: &str = "trailing backslash";
+pub const LONE_CARRIAGE_RETURN: &str = "lone carriage return";
+
 // Tokenizer for bazelrc files.
 //
 // The syntax supported by bazelrc is primarily implementation-defined
@@ -46,8 +56,20 @@ pub fn tokenizer() -> impl Parser<char, Vec<Spanned<Token>>, Error = Simple<char
     // Characters can be escaped with a `\` (except for newlines; those are treated in escaped_newline)
     let escaped_char = just('\\').ignore_then(filter(|c| *c != '\n' && *c != '\r'));
 
-    // A newline. Either a Windows or a Unix newline
-    let newline_raw = just('\n').or(just('\r').ignore_then(just('\n')));
+    // A newline. Either a Windows or a Unix newline. A lone `\r` is reported, but still
+    // separates lines, consistent with how editors display it.
+    let newline_raw =
+        just('\n').or(just('\r')
+            .then(just('\n').or_not())
+            .validate(|(_, lf), span, emit| {
+                if lf.is_none() {
+                    emit(
+                        Simple::custom(span, "Carriage return without a following newline")
+                            .with_label(LONE_CARRIAGE_RETURN),
+                    );
+                }
+                '\n'
+            }));
     let newline = newline_raw.map(|_| Token::Newline);
 
     // Newlines can be escaped using a `\`, but in contrast to other escaped parameters they
@@ -55,24 +77,64 @@ pub fn tokenizer() -> impl Parser<char, Vec<Spanned<Token>>, Error = Simple<char
     let escaped_newline_raw = just('\\').ignore_then(newline_raw);
     let escaped_newline = escaped_newline_raw.map(|_| Token::EscapedNewline);
 
+    // A `\` at the end of the file does not escape anything. We still accept it, such that
+    // the preceding tokens are not lost while the user is typing.
+    let trailing_backslash = just('\\').then(end()).validate(|_, span, emit| {
+        emit(
+            Simple::custom(span, "Unexpected `\\` at the end of the file")
+                .with_label(TRAILING_BACKSLASH),
+        );
+        Option::<char>::None
+    });
+
     // A token character can be either a raw character, an escaped character
     // or an escaped newline.
     let token_char = (raw_token_char.or(escaped_char))
         .map(Option::Some)
-        .or(escaped_newline_raw.to(Option::<char>::None));
+        .or(escaped_newline_raw.to(Option::<char>::None))
+        .or(trailing_backslash);
 
     // A token consists of multiple token_chars
-    let unquoted_token_raw = token_char.repeated().at_least(1);
+    let unquoted_token_raw = token_char.clone().repeated().at_least(1);
+
+    // A quote which is not closed extends until the end of the line. It is reported as
+    // an error, but still produces a token such that the rest of the line is not lost.
+    let report_unclosed = |quote: char| {
+        move |(chars, closing): (Vec<Option<char>>, Option<char>),
+              span: Span,
+              emit: &mut dyn FnMut(Simple<char>)| {
+            if closing.is_none() {
+                emit(
+                    Simple::custom(
+                        span,
+                        format!(
+                            "Unterminated quoted token, missing closing quote `{}`",
+                            quote
+                        ),
+                    )
+                    .with_label(UNCLOSED_QUOTE),
+                );
+            }
+            chars
+        }
+    };
 
     // Quoted tokens with `"`
     let dquoted_token_raw = just('"')
-        .ignore_then(token_char.or(one_of(" \t\'#").map(Option::Some)).repeated())
-        .then_ignore(just('"'));
+        .ignore_then(
+            token_char
+                .clone()
+                .or(one_of(" \t\'#").map(Option::Some))
+                .repeated(),
+        )
+        .then(just('"').or_not())
+        .validate(report_unclosed('"'));
 
     // Quoted tokens with `'`
     let squoted_token_raw = just('\'')
         .ignore_then(token_char.or(one_of(" \t\"#").map(Option::Some)).repeated())
-        .then_ignore(just('\''));
+        .then(just('\'').or_not())
+        .validate(report_unclosed('\''));
 
     // Quoted tokens. Either with `"` or with `'`
     let quoted_token_raw = dquoted_token_raw.or(squoted_token_raw);
@@ -97,11 +159,16 @@ pub fn tokenizer() -> impl Parser<char, Vec<Spanned<Token>>, Error = Simple<char
         .recover_with(skip_then_retry_until([]))
         .map_with_span(|tok, span| (tok, span));
 
-    token
-        .padded_by(one_of(" \t").repeated())
-        .repeated()
-        .collect::<Vec<_>>()
-        .then_ignore(end())
+    // A byte order mark at the start of the file, as written by some Windows editors
+    let bom = just(BOM).or_not();
+
+    bom.ignore_then(
+        token
+            .padded_by(one_of(" \t").repeated())
+            .repeated()
+            .collect::<Vec<_>>(),
+    )
+    .then_ignore(end())
 }
 
 #[test]
@@ -207,6 +274,20 @@ fn test_tokens() {
     assert!(tokenizer().parse("'my\ntoken'").is_err());
     // But a quoted token can contain escaped newlines
     assert_single_flag!("'my\\\ntoken'", "mytoken".to_string());
+    // An unclosed quote is reported, but still produces a token until the end of the line
+    assert_eq!(
+        tokenizer().parse_recovery("'my token\nx").0,
+        Some(vec!(
+            (Token::Token("my token".to_string()), 0..9),
+            (Token::Newline, 9..10),
+            (Token::Token("x".to_string()), 10..11),
+        ))
+    );
+    // The same holds for a `\` at the end of the file
+    assert_eq!(
+        tokenizer().parse_recovery("ab\\").0,
+        Some(vec!((Token::Token("ab".to_string()), 0..3)))
+    );
 
     // `#` inside a quoted token does not start a token
     assert_single_flag!("'a#c'", "a#c".to_string());
@@ -240,3 +321,14 @@ fn test_comments() {
         ))
     );
 }
+
+#[test]
+fn test_bom() {
+    assert_eq!(
+        tokenizer().parse("\u{feff}build -x"),
+        Ok(vec!(
+            (Token::Token("build".to_string()), 1..6),
+            (Token::Token("-x".to_string()), 7..9)
+        ))
+    );
+}
@@ -0,0 +1,294 @@
+use ropey::Rope;
+use tower_lsp::lsp_types::Range;
+
+use crate::{
+    bazel_flags::{load_bazel_flags_for_release, BazelFlags},
+    lsp_utils::range_to_lsp,
+    parser::Line,
+};
+
+// A flag which will stop working or behave differently after upgrading Bazel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpgradeIssue {
+    pub line_nr: usize,
+    pub range: Range,
+    // The flag as written in the rc file
+    pub flag: String,
+    pub message: String,
+    // The flag to use instead, if any
+    pub replacement: Option<String>,
+}
+
+// Finds a flag of the target version which likely replaces a removed flag.
+//
+// Bazel usually renames flags by dropping or adding the `experimental_` prefix, once
+// a feature is stabilized or turned into an experiment again.
+fn find_renamed_flag(name: &str, target: &BazelFlags) -> Option<String> {
+    let candidates = match name.strip_prefix("experimental_") {
+        Some(stable_name) => vec![stable_name.to_string()],
+        None => vec![format!("experimental_{}", name)],
+    };
+    candidates
+        .into_iter()
+        .find(|c| target.flags_by_name.contains_key(c))
+}
+
+// Compares the flags used by the lines between the current and the target Bazel version.
+//
+// Reported are flags which are removed in the target version, which are no longer supported
+// for the command they are used with, which are deprecated in the target version and
+// flags which change from boolean to non-boolean flags or vice versa.
+pub fn find_upgrade_issues(
+    rope: &Rope,
+    lines: &[Line],
+    current: &BazelFlags,
+    target: &BazelFlags,
+) -> Vec<UpgradeIssue> {
+    let mut issues = Vec::<UpgradeIssue>::new();
+    for (line_nr, line) in lines.iter().enumerate() {
+        let Some(command) = &line.command else {
+            continue;
+        };
+        for flag in &line.flags {
+            let Some(name) = &flag.name else {
+                continue;
+            };
+            // Unknown flags are already reported by the linter
+            let Some(current_info) = current.get_by_invocation(&name.0) else {
+                continue;
+            };
+            let mut issue = |message: String, replacement: Option<String>| {
+                issues.push(UpgradeIssue {
+                    line_nr,
+                    range: range_to_lsp(rope, &name.1).unwrap_or_default(),
+                    flag: name.0.clone(),
+                    message,
+                    replacement,
+                })
+            };
+            let Some(target_info) = target.flags_by_name.get(&current_info.name) else {
+                let replacement = find_renamed_flag(&current_info.name, target);
+                let message = match &replacement {
+                    Some(new_name) => format!(
+                        "The flag --{} is renamed to --{}",
+                        current_info.name, new_name
+                    ),
+                    None if current_info.name.starts_with("incompatible_") => format!(
+                        "The flag --{} is removed. Usually, this means its new behavior is the default now",
+                        current_info.name
+                    ),
+                    None => format!("The flag --{} is removed", current_info.name),
+                };
+                issue(message, replacement.map(|n| format!("--{}", n)));
+                continue;
+            };
+            let target_info = &target.flags[*target_info];
+            if current_info.supports_command(&command.0)
+                && !target_info.supports_command(&command.0)
+            {
+                issue(
+                    format!(
+                        "The flag --{} is no longer supported for {:?}",
+                        target_info.name, command.0
+                    ),
+                    None,
+                );
+            }
+            if target_info.is_deprecated() && !current_info.is_deprecated() {
+                issue(
+                    format!(
+                        "The flag --{} is deprecated and will be removed in a future version",
+                        target_info.name
+                    ),
+                    None,
+                );
+            }
+            if current_info.has_negative_flag() != target_info.has_negative_flag() {
+                let kind = if target_info.has_negative_flag() {
+                    "a boolean flag"
+                } else {
+                    "no longer a boolean flag"
+                };
+                issue(
+                    format!(
+                        "The flag --{} is {} and might behave differently",
+                        target_info.name, kind
+                    ),
+                    None,
+                );
+            }
+        }
+    }
+    issues
+}
+
+// A flag whose default value differs between two Bazel versions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DefaultChange {
+    pub flag: String,
+    pub current: String,
+    pub target: String,
+    // The release which added the flag, for flags which don't exist in the current version.
+    // `current` is the default value the flag was added with.
+    pub added_in: Option<String>,
+}
+
+// Lists the flags whose default value differs between the current and the target Bazel
+// version, sorted by name. Silently flipped defaults are a common upgrade hazard, since
+// they change the behavior of flags which are not set at all.
+//
+// Flags added in between are compared against their default value in the release which
+// added them, e.g. a flag added as `true` and flipped to `false` before the target version.
+pub fn find_default_changes(current: &BazelFlags, target: &BazelFlags) -> Vec<DefaultChange> {
+    let mut changes = target
+        .flags
+        .iter()
+        .filter_map(|target_info| {
+            let target_default = target_info.default_value.as_ref()?;
+            let (current_default, added_in) = match current.flags_by_name.get(&target_info.name) {
+                Some(i) => (current.flags[*i].default_value.clone()?, None),
+                None => {
+                    let added_in = target_info.added_in_version.as_ref()?;
+                    let added_flags = load_bazel_flags_for_release(added_in)?;
+                    let added_info =
+                        &added_flags.flags[*added_flags.flags_by_name.get(&target_info.name)?];
+                    (added_info.default_value.clone()?, Some(added_in.clone()))
+                }
+            };
+            (!current_default.eq_ignore_ascii_case(target_default)).then(|| DefaultChange {
+                flag: target_info.name.clone(),
+                current: current_default,
+                target: target_default.clone(),
+                added_in,
+            })
+        })
+        .collect::<Vec<_>>();
+    changes.sort_by(|a, b| a.flag.cmp(&b.flag));
+    changes
+}
+
+#[test]
+fn test_upgrade_issues() {
+    use crate::bazel_flags::combine_key_value_flags;
+    use crate::bazel_flags_proto::FlagInfo;
+    use crate::parser::parse_from_str;
+
+    let flag = |name: &str, has_negative_flag: bool, tags: &[&str]| FlagInfo {
+        name: name.to_string(),
+        has_negative_flag: Some(has_negative_flag),
+        commands: vec!["build".to_string()],
+        metadata_tags: tags.iter().map(|t| t.to_string()).collect(),
+        ..Default::default()
+    };
+    let current = BazelFlags::from_flags(vec![
+        flag("keep_going", true, &[]),
+        flag("experimental_foo", true, &[]),
+        flag("incompatible_bar", true, &[]),
+        flag("old", false, &[]),
+        flag("mode", true, &[]),
+    ]);
+    let target = BazelFlags::from_flags(vec![
+        flag("keep_going", true, &[]),
+        flag("foo", true, &[]),
+        flag("old", false, &["DEPRECATED"]),
+        flag("mode", false, &[]),
+    ]);
+
+    let src = "build --keep_going --experimental_foo --incompatible_bar\nbuild --old=x --nomode\nbuild --unknown";
+    let mut lines = parse_from_str(src).lines;
+    combine_key_value_flags(&mut lines, &current);
+    let issues = find_upgrade_issues(&Rope::from_str(src), &lines, &current, &target);
+    assert_eq!(
+        issues
+            .iter()
+            .map(|i| (i.line_nr, i.message.as_str(), i.replacement.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![
+            (0, "The flag --experimental_foo is renamed to --foo", Some("--foo")),
+            (0, "The flag --incompatible_bar is removed. Usually, this means its new behavior is the default now", None),
+            (1, "The flag --old is deprecated and will be removed in a future version", None),
+            (1, "The flag --mode is no longer a boolean flag and might behave differently", None),
+        ]
+    );
+}
+
+#[test]
+fn test_upgrade_issues_between_releases() {
+    use crate::bazel_flags::{combine_key_value_flags, load_bazel_flags_for_release};
+    use crate::parser::parse_from_str;
+
+    let check = |src: &str, from: &str, to: &str| {
+        let current = load_bazel_flags_for_release(from).unwrap();
+        let target = load_bazel_flags_for_release(to).unwrap();
+        let mut lines = parse_from_str(src).lines;
+        combine_key_value_flags(&mut lines, &current);
+        find_upgrade_issues(&Rope::from_str(src), &lines, &current, &target)
+            .into_iter()
+            .map(|i| i.message)
+            .collect::<Vec<_>>()
+    };
+    // Releases without a flag dump are derived from the bundled one
+    assert_eq!(
+        check("build --javabase=x --keep_going", "7.1.0", "9.0.0"),
+        vec!["The flag --javabase is removed"]
+    );
+    assert_eq!(
+        check("common --experimental_enable_bzlmod", "5.4.0", "7.1.0"),
+        vec!["The flag --experimental_enable_bzlmod is renamed to --enable_bzlmod"]
+    );
+    assert_eq!(
+        check("build --javabase=x", "7.1.0", "7.1.0"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn test_default_changes() {
+    use crate::bazel_flags::load_bazel_flags_for_release;
+
+    let changes = |from: &str, to: &str| {
+        find_default_changes(
+            &load_bazel_flags_for_release(from).unwrap(),
+            &load_bazel_flags_for_release(to).unwrap(),
+        )
+        .into_iter()
+        .map(|c| match &c.added_in {
+            Some(added_in) => format!(
+                "{}: {} -> {} (added in {})",
+                c.flag, c.current, c.target, added_in
+            ),
+            None => format!("{}: {} -> {}", c.flag, c.current, c.target),
+        })
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        changes("7.1.0", "8.0.0"),
+        vec![
+            "enable_workspace: true -> false",
+            "incompatible_disallow_empty_glob: false -> true",
+            "legacy_external_runfiles: true -> false"
+        ]
+    );
+    // Also works for downgrades
+    assert_eq!(
+        changes("7.1.0", "6.5.0"),
+        vec![
+            "enable_bzlmod: true -> false",
+            "incompatible_sandbox_hermetic_tmp: true -> false",
+            "remote_download_outputs: toplevel -> all"
+        ]
+    );
+    // Flags added in between are compared with the default they were added with
+    assert_eq!(
+        changes("6.0.0", "8.0.0"),
+        vec![
+            "enable_bzlmod: false -> true",
+            "enable_workspace: true -> false (added in 7.1.0)",
+            "incompatible_disallow_empty_glob: false -> true",
+            "incompatible_sandbox_hermetic_tmp: false -> true",
+            "legacy_external_runfiles: true -> false",
+            "remote_download_outputs: all -> toplevel"
+        ]
+    );
+    assert_eq!(changes("8.0.0", "8.1.0"), Vec::<String>::new());
+}
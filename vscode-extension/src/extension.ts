@@ -1,4 +1,5 @@
 import {
+  commands,
   type ExtensionContext
 } from 'vscode';
 
@@ -6,6 +7,8 @@ import {
   type Executable,
   LanguageClient,
   type LanguageClientOptions,
+  type Location,
+  type Position,
   type ServerOptions
 } from 'vscode-languageclient/node';
 
@@ -34,7 +37,25 @@ export async function activate (context: ExtensionContext) {
   // Options to control the language client
   const clientOptions: LanguageClientOptions = {
     // Register the server for bazelrc documents
-    documentSelector: [{ language: 'bazelrc' }]
+    documentSelector: [{ language: 'bazelrc' }],
+    middleware: {
+      // The code lenses send their arguments as LSP types, but `editor.action.showReferences`
+      // expects VS Code types. Convert them and show the references in a peek view, instead of
+      // letting the server open the first reference.
+      executeCommand: async (command, args, next) => {
+        if (command !== 'bazelrc.showReferences' || client === null) {
+          return await next(command, args);
+        }
+        const [uri, position, locations] = args as [string, Position, Location[]];
+        const converter = client.protocol2CodeConverter;
+        return await commands.executeCommand(
+          'editor.action.showReferences',
+          converter.asUri(uri),
+          converter.asPosition(position),
+          locations.map((location) => converter.asLocation(location))
+        );
+      }
+    }
   };
 
   // Create the language client and start the client.